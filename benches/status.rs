@@ -0,0 +1,68 @@
+//! Benchmarks of the status path, which CoolerControl calls every second.
+//!
+//! The status cache is disabled, so every call makes the hop to the blocking
+//! pool to read the mock backend. Run with `cargo bench --features mock`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use prost::Message;
+use std::{hint::black_box, sync::Arc, time::Duration};
+use tokio::{net::UnixListener, runtime::Runtime};
+use tonic::{Request, codegen::tokio_stream::wrappers::UnixListenerStream, transport::Server};
+use tuxedo_infinitybook_gen10_cc_plugin::{
+    client,
+    device_service::v1::{
+        StatusRequest,
+        device_service_client::DeviceServiceClient,
+        device_service_server::{DeviceService, DeviceServiceServer},
+    },
+    mock::MockBackend,
+    service::{DEVICE_ID, ServiceConfig},
+};
+
+fn status(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let backend = MockBackend::default();
+    backend.state().speeds = [40.0, 60.0];
+    let service = Arc::new(backend.service_with(ServiceConfig {
+        status_ttl: Duration::ZERO,
+        ..Default::default()
+    }));
+    let request = || StatusRequest {
+        device_id: DEVICE_ID.into(),
+    };
+
+    c.bench_function("status", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let response = service.status(Request::new(request())).await.unwrap();
+            black_box(response.into_inner().encode_to_vec())
+        })
+    });
+
+    // Serve the service on a Unix socket, so that requests go through the
+    // whole gRPC stack.
+    let socket =
+        std::env::temp_dir().join(format!("tuxedo-grpc-bench-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket);
+    let client = runtime.block_on(async {
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(DeviceServiceServer::from_arc(service.clone()))
+                .serve_with_incoming(UnixListenerStream::new(listener)),
+        );
+
+        DeviceServiceClient::new(client::connect(socket.to_str().unwrap()).await.unwrap())
+    });
+
+    c.bench_function("status over gRPC", |b| {
+        b.to_async(&runtime).iter(|| {
+            let mut client = client.clone();
+            async move { black_box(client.status(request()).await.unwrap()) }
+        })
+    });
+
+    std::fs::remove_file(socket).unwrap();
+}
+
+criterion_group!(benches, status);
+criterion_main!(benches);
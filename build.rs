@@ -1,9 +1,13 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+
     tonic_prost_build::configure()
         .build_server(true)
-        .build_client(false)
+        .build_client(true)
         // needed for older protoc packages:
         .protoc_arg("--experimental_allow_proto3_optional")
+        // used by gRPC reflection:
+        .file_descriptor_set_path(out_dir.join("device_service_descriptor.bin"))
         .compile_protos(
             &[
                 "proto/coolercontrol/models/v1/device.proto",
@@ -11,5 +15,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             ],
             &["proto"],
         )?;
+
+    tonic_prost_build::configure()
+        .build_server(true)
+        .build_client(true)
+        // the plugin's own services use the CoolerControl types generated
+        // above:
+        .extern_path(".coolercontrol.models.v1", "crate::models::v1")
+        .protoc_arg("--experimental_allow_proto3_optional")
+        .compile_protos(&["proto/tuxedo/v1/status_stream.proto"], &["proto"])?;
     Ok(())
 }
@@ -0,0 +1,53 @@
+use nix::unistd::geteuid;
+use tokio::net::UnixStream;
+use tracing::warn;
+
+/// Decides which local processes may connect to the socket, based on the
+/// credentials the kernel reports for the connecting process.
+///
+/// Root and the user the plugin runs as are always allowed, as they could
+/// control the fans directly anyway.
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+}
+
+impl AccessPolicy {
+    /// Also allow processes running as any of the given users or with any of
+    /// the given primary groups.
+    pub fn new(uids: Vec<u32>, gids: Vec<u32>) -> Self {
+        Self { uids, gids }
+    }
+
+    /// Whether a connection should be served. Rejected connections are
+    /// logged.
+    pub fn allows_connection(&self, stream: &UnixStream) -> bool {
+        match stream.peer_cred() {
+            Ok(cred) if self.allows(cred.uid(), cred.gid()) => true,
+            Ok(cred) => {
+                warn!(
+                    "Rejecting connection from pid {:?} running as uid {} and gid {}",
+                    cred.pid(),
+                    cred.uid(),
+                    cred.gid()
+                );
+                false
+            }
+            Err(e) => {
+                warn!("Rejecting connection without peer credentials: {e}");
+                false
+            }
+        }
+    }
+
+    fn allows(&self, uid: u32, gid: u32) -> bool {
+        uid == 0
+            || uid == geteuid().as_raw()
+            || self.uids.contains(&uid)
+            || self.gids.contains(&gid)
+    }
+}
+
+#[cfg(test)]
+mod tests;
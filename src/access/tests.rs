@@ -0,0 +1,24 @@
+use super::*;
+
+/// A user that is neither root nor the one running the tests.
+fn stranger() -> u32 {
+    geteuid().as_raw() + 1000
+}
+
+#[test]
+fn root_and_own_user_are_always_allowed() {
+    let policy = AccessPolicy::default();
+
+    assert!(policy.allows(0, 0));
+    assert!(policy.allows(geteuid().as_raw(), 12345));
+    assert!(!policy.allows(stranger(), 12345));
+}
+
+#[test]
+fn configured_users_and_groups_are_allowed() {
+    let policy = AccessPolicy::new(vec![stranger()], vec![970]);
+
+    assert!(policy.allows(stranger(), 12345));
+    assert!(policy.allows(stranger() + 1, 970));
+    assert!(!policy.allows(stranger() + 1, 12345));
+}
@@ -0,0 +1,28 @@
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Tracks when CoolerControl last polled or set a fan, to tell whether its
+/// daemon is still running.
+pub struct ActivityTracker {
+    last_request: Mutex<Instant>,
+}
+
+impl Default for ActivityTracker {
+    fn default() -> Self {
+        Self {
+            last_request: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl ActivityTracker {
+    /// Record that a request was just received.
+    pub fn record(&self) {
+        *self.last_request.lock().unwrap() = Instant::now();
+    }
+
+    /// How long it has been since the last request.
+    pub fn idle(&self) -> Duration {
+        self.last_request.lock().unwrap().elapsed()
+    }
+}
@@ -0,0 +1,180 @@
+use crate::tuxedo_io::{Fan, PerformanceProfile, Tdp, TuxedoIo};
+use std::io::Result;
+
+/// Everything reported by the hardware for a single status poll.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FanStatusSnapshot {
+    /// Speed of each fan as a percentage.
+    pub speeds: [f64; 2],
+
+    /// Temperature of the sensor associated with each fan, in degrees Celsius.
+    pub temperatures: [u8; 2],
+
+    /// Raw performance mode set in the firmware, if it could be read.
+    pub mode: Option<i32>,
+
+    /// Whether the firmware leaves the fan and power policy to the OS, if it
+    /// could be read.
+    pub mode_enable: Option<bool>,
+
+    /// Whether the firmware will stop the fans completely when set to 0%.
+    pub fans_off_available: bool,
+
+    /// Power limit currently set for each supported TDP rail, in watts.
+    pub tdp: [Option<u32>; 3],
+}
+
+/// A hardware interface that can monitor and control the laptop fans.
+pub trait FanBackend: Send + Sync {
+    /// Describe the hardware interface in use.
+    fn model(&self) -> String;
+
+    /// Check that the hardware interface is still usable, such as after the
+    /// system resumes from sleep.
+    fn check(&self) -> Result<()>;
+
+    /// Get the allowed range of a TDP rail in watts, or `None` if the rail is
+    /// not supported. This is read once when the backend is opened.
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)>;
+
+    /// Get the raw performance mode currently set in the firmware.
+    fn get_mode(&self) -> Result<i32>;
+
+    /// Get whether the firmware leaves the fan and power policy to the OS
+    /// rather than applying its own.
+    fn get_mode_enable(&self) -> Result<bool>;
+
+    /// Hand the fan and power policy to the OS, or back to the firmware.
+    fn set_mode_enable(&self, enabled: bool) -> Result<()>;
+
+    /// Switch the firmware to a performance profile.
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()>;
+
+    /// Get the power limit currently set for a TDP rail, in watts.
+    fn get_tdp(&self, tdp: Tdp) -> Result<u32>;
+
+    /// Set the power limit of a TDP rail, in watts.
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()>;
+
+    /// Whether the firmware will stop the fans completely when set to 0%. This
+    /// is read once when the backend is opened.
+    fn fans_off_available(&self) -> bool;
+
+    /// Whether [`FanBackend::set_performance_profile`] is supported. This is
+    /// known when the backend is opened.
+    fn performance_profiles_available(&self) -> bool;
+
+    /// Get the minimum recommended fan speed for all fans, as a percentage.
+    fn get_fan_min_speed(&self) -> Result<u8>;
+
+    /// Get the current speed of a fan as a percentage.
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64>;
+
+    /// Read all fan metrics at once, so that a status poll makes a single trip
+    /// to the hardware.
+    fn read_all_status(&self) -> Result<FanStatusSnapshot>;
+
+    /// Set the desired speed of a fan as a percentage, returning the raw value
+    /// that was sent to the hardware. The percentage is only rounded when it is
+    /// converted to that raw value.
+    ///
+    /// This may block until the desired speed is reached.
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32>;
+
+    /// Get the raw value that [`FanBackend::set_fan_speed`] would send to the
+    /// hardware for a percentage.
+    fn fan_speed_value(&self, percentage: f64) -> i32;
+
+    /// Set all fans to default mode (controlled by firmware).
+    fn set_fans_auto(&self) -> Result<()>;
+}
+
+impl FanBackend for TuxedoIo {
+    fn model(&self) -> String {
+        format!(
+            "{}, hwcheck code {}",
+            self.interface().name,
+            self.hwcheck_code()
+        )
+    }
+
+    fn check(&self) -> Result<()> {
+        self.check()
+    }
+
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        self.get_tdp_bounds(tdp)
+    }
+
+    fn get_mode(&self) -> Result<i32> {
+        self.get_mode()
+    }
+
+    fn get_mode_enable(&self) -> Result<bool> {
+        self.get_mode_enable()
+    }
+
+    fn set_mode_enable(&self, enabled: bool) -> Result<()> {
+        self.set_mode_enable(enabled)
+    }
+
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()> {
+        self.set_performance_profile(profile)
+    }
+
+    fn get_tdp(&self, tdp: Tdp) -> Result<u32> {
+        self.get_tdp(tdp)
+    }
+
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()> {
+        self.set_tdp(tdp, watts)
+    }
+
+    fn fans_off_available(&self) -> bool {
+        self.fans_off_available()
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        true
+    }
+
+    fn get_fan_min_speed(&self) -> Result<u8> {
+        self.get_fan_min_speed()
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        self.get_fan_speed(fan)
+    }
+
+    fn read_all_status(&self) -> Result<FanStatusSnapshot> {
+        Ok(FanStatusSnapshot {
+            speeds: [
+                self.get_fan_speed(Fan::Fan1)?,
+                self.get_fan_speed(Fan::Fan2)?,
+            ],
+            temperatures: [
+                self.get_fan_temperature(Fan::Fan1)?,
+                self.get_fan_temperature(Fan::Fan2)?,
+            ],
+            mode: self.get_mode().ok(),
+            mode_enable: self.get_mode_enable().ok(),
+            fans_off_available: self.fans_off_available(),
+            tdp: Tdp::ALL.map(|tdp| {
+                self.get_tdp_bounds(tdp)
+                    .and_then(|_| self.get_tdp(tdp).ok())
+            }),
+        })
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        self.set_fan_speed(fan, percentage)
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        self.fan_speed_value(percentage)
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        self.set_fans_auto()
+    }
+}
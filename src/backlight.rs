@@ -0,0 +1,68 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const BACKLIGHT_CLASS_DIR: &str = "/sys/class/backlight";
+
+/// Backlight types in the order they are preferred in, as firmware and
+/// platform interfaces usually control the panel more reliably than the raw
+/// one of the graphics driver.
+const BACKLIGHT_TYPES: [&str; 3] = ["firmware", "platform", "raw"];
+
+/// Backlight of the internal display, as exposed in sysfs.
+#[derive(Debug)]
+pub struct DisplayBacklight {
+    dir: PathBuf,
+    max_brightness: u32,
+}
+
+impl DisplayBacklight {
+    /// Use the backlight in the given directory, or `None` if it cannot be
+    /// read.
+    pub fn new(dir: impl Into<PathBuf>) -> Option<Self> {
+        let dir = dir.into();
+        let max_brightness = fs::read_to_string(dir.join("max_brightness"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+            .filter(|max| *max > 0)?;
+
+        Some(Self {
+            dir,
+            max_brightness,
+        })
+    }
+
+    /// Find the backlight of the internal display, if there is one.
+    pub fn find() -> Option<Self> {
+        let mut dirs = fs::read_dir(BACKLIGHT_CLASS_DIR)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let dir = entry.path();
+                let kind = fs::read_to_string(dir.join("type")).ok()?;
+                let rank = BACKLIGHT_TYPES.iter().position(|t| *t == kind.trim())?;
+
+                Some((rank, dir))
+            })
+            .collect::<Vec<_>>();
+
+        dirs.sort();
+        dirs.into_iter().find_map(|(_, dir)| Self::new(dir))
+    }
+
+    /// Directory of the backlight, for logging and the sandbox.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Set the brightness as a percentage of the maximum.
+    pub fn set_brightness(&self, percent: u8) -> io::Result<()> {
+        let brightness =
+            (f64::from(self.max_brightness) * f64::from(percent.min(100)) / 100.0).round() as u32;
+
+        fs::write(self.dir.join("brightness"), brightness.to_string())
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const POWER_SUPPLY_CLASS_DIR: &str = "/sys/class/power_supply";
+
+/// Lowest charge limit offered, matching what TUXEDO Control Center allows.
+pub const MIN_CHARGE_LIMIT: u8 = 40;
+
+/// The level a battery stops charging at, as exposed in sysfs.
+#[derive(Debug)]
+pub struct ChargeLimit {
+    path: PathBuf,
+}
+
+impl ChargeLimit {
+    /// Use the charge limit of the battery in the given directory.
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            path: dir.as_ref().join("charge_control_end_threshold"),
+        }
+    }
+
+    /// Find the battery that supports a charge limit, if there is one.
+    pub fn find() -> Option<Self> {
+        fs::read_dir(POWER_SUPPLY_CLASS_DIR)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|dir| {
+                fs::read_to_string(dir.join("type")).is_ok_and(|kind| kind.trim() == "Battery")
+            })
+            .map(Self::new)
+            .find(|limit| limit.path.exists())
+    }
+
+    /// Path of the threshold file, for logging and the sandbox.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the charge limit as a percentage.
+    pub fn get(&self) -> io::Result<u8> {
+        fs::read_to_string(&self.path)?
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Set the charge limit as a percentage. The driver may only accept some
+    /// values, and rejects others with `EINVAL`.
+    pub fn set(&self, percent: u8) -> io::Result<()> {
+        fs::write(&self.path, percent.to_string())
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    sys::{CL_MAX_FAN_SPEED, ioctl},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp, open_device, unsupported_code},
+};
+use std::{
+    io::{Error, ErrorKind, Result},
+    os::fd::{AsRawFd, OwnedFd},
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+use tracing::debug;
+
+/// Hardware check result that identifies the Clevo interface.
+const CLEVO_HWCHECK_CODE: i32 = 1;
+
+/// Argument to the fan auto ioctl selecting every fan.
+const ALL_FANS_MASK: i32 = 0xf;
+
+/// Safe wrapper around the Clevo flavor of the Tuxedo driver IOCTL interface,
+/// used by TUXEDO laptops that are not built on Uniwill hardware.
+///
+/// The EC reports the duty and temperature of each fan packed into a single
+/// value, and sets the speed of all fans at once.
+pub struct ClevoIo<E = OwnedFd> {
+    ec: E,
+
+    /// Held while setting a fan speed, since doing so reads the speeds of the
+    /// other fans and writes them back. Without it, two concurrent writes could
+    /// each restore the speed the other one is replacing.
+    command_lock: Mutex<()>,
+}
+
+/// The EC commands of the Clevo interface, implemented by the driver device.
+pub trait Ec {
+    /// Run the hardware check, returning the interface code.
+    fn hwcheck(&self) -> Result<i32>;
+
+    /// Read the packed duty and temperature of a fan.
+    fn fan_info(&self, index: usize) -> Result<i32>;
+
+    /// Set the duty of every fan at once, packed one per byte.
+    fn set_fan_speeds(&self, value: i32) -> Result<()>;
+
+    /// Return every fan to automatic control.
+    fn set_fans_auto(&self) -> Result<()>;
+}
+
+impl Ec for OwnedFd {
+    fn hwcheck(&self) -> Result<i32> {
+        let mut code = 0;
+
+        unsafe {
+            ioctl::cl_hwcheck(self.as_raw_fd(), &mut code)?;
+        }
+
+        Ok(code)
+    }
+
+    fn fan_info(&self, index: usize) -> Result<i32> {
+        let mut value = 0;
+
+        unsafe {
+            match index {
+                0 => ioctl::r_cl_faninfo1(self.as_raw_fd(), &mut value)?,
+                1 => ioctl::r_cl_faninfo2(self.as_raw_fd(), &mut value)?,
+                _ => ioctl::r_cl_faninfo3(self.as_raw_fd(), &mut value)?,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn set_fan_speeds(&self, value: i32) -> Result<()> {
+        unsafe {
+            ioctl::w_cl_fanspeed(self.as_raw_fd(), &value)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        unsafe {
+            ioctl::w_cl_fanauto(self.as_raw_fd(), &ALL_FANS_MASK)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The state of a fan as reported by the EC.
+struct FanInfo {
+    /// Raw duty between 0 and [`CL_MAX_FAN_SPEED`].
+    speed: u8,
+
+    /// Temperature of the sensor associated with the fan, in degrees Celsius.
+    temperature: u8,
+}
+
+impl ClevoIo {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::with_ec(open_device(path)?)
+    }
+}
+
+impl<E: Ec> ClevoIo<E> {
+    fn with_ec(ec: E) -> Result<Self> {
+        let code = ec.hwcheck()?;
+        debug!("Clevo hardware check returned code {code}");
+
+        if code == CLEVO_HWCHECK_CODE {
+            Ok(Self {
+                ec,
+                command_lock: Mutex::new(()),
+            })
+        } else {
+            Err(unsupported_code(code))
+        }
+    }
+
+    /// Read the state of a fan. Clevo devices may have a third fan, which is
+    /// not exposed but must be preserved when setting the others.
+    fn get_fan_info(&self, index: usize) -> Result<FanInfo> {
+        let value = self.ec.fan_info(index)?;
+
+        Ok(FanInfo {
+            speed: value as u8,
+            temperature: (value >> 16) as u8,
+        })
+    }
+}
+
+fn unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "not supported by the Clevo interface",
+    )
+}
+
+fn speed_to_percentage(speed: u8) -> f64 {
+    speed as f64 * 100.0 / CL_MAX_FAN_SPEED as f64
+}
+
+fn percentage_to_speed(percentage: f64) -> u8 {
+    (CL_MAX_FAN_SPEED as f64 * percentage.clamp(0.0, 100.0) / 100.0).round() as u8
+}
+
+impl<E: Ec + Send + Sync> FanBackend for ClevoIo<E> {
+    fn model(&self) -> String {
+        "Clevo".into()
+    }
+
+    fn check(&self) -> Result<()> {
+        let code = self.ec.hwcheck()?;
+
+        if code == CLEVO_HWCHECK_CODE {
+            Ok(())
+        } else {
+            Err(unsupported_code(code))
+        }
+    }
+
+    fn get_tdp_bounds(&self, _tdp: Tdp) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn get_tdp(&self, _tdp: Tdp) -> Result<u32> {
+        Err(unsupported())
+    }
+
+    fn set_tdp(&self, _tdp: Tdp, _watts: u32) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn get_mode(&self) -> Result<i32> {
+        Err(unsupported())
+    }
+
+    fn get_mode_enable(&self) -> Result<bool> {
+        Err(unsupported())
+    }
+
+    fn set_mode_enable(&self, _enabled: bool) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn set_performance_profile(&self, _profile: PerformanceProfile) -> Result<()> {
+        Err(unsupported())
+    }
+
+    fn fans_off_available(&self) -> bool {
+        false
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        false
+    }
+
+    fn get_fan_min_speed(&self) -> Result<u8> {
+        // The Clevo interface does not report a minimum speed.
+        Ok(0)
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        Ok(speed_to_percentage(self.get_fan_info(fan as usize)?.speed))
+    }
+
+    fn read_all_status(&self) -> Result<FanStatusSnapshot> {
+        let fans = [self.get_fan_info(0)?, self.get_fan_info(1)?];
+
+        Ok(FanStatusSnapshot {
+            speeds: fans.each_ref().map(|info| speed_to_percentage(info.speed)),
+            temperatures: fans.each_ref().map(|info| info.temperature),
+            ..Default::default()
+        })
+    }
+
+    /// Set the desired speed of a fan as a percentage, keeping the other fans
+    /// at their current speed.
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        let _lock = self
+            .command_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut speeds = [0; 3];
+
+        for (index, speed) in speeds.iter_mut().enumerate() {
+            *speed = if index == fan as usize {
+                percentage_to_speed(percentage)
+            } else {
+                // Not every device has a third fan, in which case it does not
+                // matter what it is set to.
+                self.get_fan_info(index).map_or(0, |info| info.speed)
+            };
+        }
+
+        self.ec
+            .set_fan_speeds(i32::from_le_bytes([speeds[0], speeds[1], speeds[2], 0]))?;
+
+        Ok(speeds[fan as usize].into())
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        percentage_to_speed(percentage).into()
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        let _lock = self
+            .command_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        self.ec.set_fans_auto()
+    }
+}
+
+#[cfg(test)]
+mod tests;
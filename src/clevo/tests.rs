@@ -0,0 +1,84 @@
+use super::*;
+use std::{thread, time::Duration};
+
+/// An EC holding the duty of three fans, which is slow to report them so that
+/// concurrent writes overlap.
+#[derive(Default)]
+struct FakeEc {
+    speeds: Mutex<[u8; 3]>,
+}
+
+impl Ec for FakeEc {
+    fn hwcheck(&self) -> Result<i32> {
+        Ok(CLEVO_HWCHECK_CODE)
+    }
+
+    fn fan_info(&self, index: usize) -> Result<i32> {
+        let speed = self.speeds.lock().unwrap()[index];
+        thread::sleep(Duration::from_millis(20));
+        Ok(speed.into())
+    }
+
+    fn set_fan_speeds(&self, value: i32) -> Result<()> {
+        let [fan1, fan2, fan3, _] = value.to_le_bytes();
+        *self.speeds.lock().unwrap() = [fan1, fan2, fan3];
+        Ok(())
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn set_fan_speed_keeps_the_other_fans() {
+    let clevo = ClevoIo::with_ec(FakeEc {
+        speeds: Mutex::new([10, 20, 30]),
+    })
+    .unwrap();
+
+    clevo.set_fan_speed(Fan::Fan2, 100.0).unwrap();
+
+    assert_eq!(
+        *clevo.ec.speeds.lock().unwrap(),
+        [10, percentage_to_speed(100.0), 30]
+    );
+}
+
+#[test]
+fn concurrent_writes_to_different_fans_both_apply() {
+    let clevo = ClevoIo::with_ec(FakeEc::default()).unwrap();
+
+    thread::scope(|scope| {
+        scope.spawn(|| clevo.set_fan_speed(Fan::Fan1, 100.0).unwrap());
+        scope.spawn(|| clevo.set_fan_speed(Fan::Fan2, 100.0).unwrap());
+    });
+
+    let full = percentage_to_speed(100.0);
+    assert_eq!(*clevo.ec.speeds.lock().unwrap(), [full, full, 0]);
+}
+
+#[test]
+fn open_rejects_other_interfaces() {
+    struct Uniwill;
+
+    impl Ec for Uniwill {
+        fn hwcheck(&self) -> Result<i32> {
+            Ok(CLEVO_HWCHECK_CODE + 1)
+        }
+
+        fn fan_info(&self, _index: usize) -> Result<i32> {
+            unreachable!()
+        }
+
+        fn set_fan_speeds(&self, _value: i32) -> Result<()> {
+            unreachable!()
+        }
+
+        fn set_fans_auto(&self) -> Result<()> {
+            unreachable!()
+        }
+    }
+
+    assert!(ClevoIo::with_ec(Uniwill).is_err());
+}
@@ -0,0 +1,158 @@
+use crate::{
+    device_service::v1::{
+        FixedDutyRequest, HealthRequest, ResetChannelRequest, StatusRequest,
+        device_service_client::DeviceServiceClient,
+    },
+    models::v1::{self, status::Metric},
+    service::{
+        CONSECUTIVE_FAILURES_METADATA, DEVICE_ID, FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID,
+        LAST_ERROR_METADATA, REACHABLE_METADATA, TOTAL_FAILURES_METADATA,
+    },
+    tuxedo::v1::{WatchStatusRequest, status_stream_client::StatusStreamClient},
+    uds,
+};
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use hyper_util::rt::TokioIo;
+use tonic::transport::{Channel, Endpoint};
+use tower::service_fn;
+
+/// Commands that talk to a running plugin through its socket instead of
+/// starting one.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print whether the plugin can talk to the driver, and its recent errors
+    Health,
+    /// Print the fan speeds, temperatures and power limits
+    Status,
+    /// Print readings as they change, until interrupted
+    Watch {
+        /// Milliseconds between samples, or the plugin's default if not given
+        #[clap(long, default_value_t = 0)]
+        interval_ms: u32,
+    },
+    /// Set a fan to a fixed duty in percent, or a power limit in watts
+    Set {
+        /// Channel to set, such as fan1, fan2 or charge_limit
+        channel: String,
+        duty: i32,
+    },
+    /// Hand a fan back to the firmware, or both fans if none is given
+    Reset {
+        /// Fan to reset, fan1 or fan2
+        channel: Option<String>,
+    },
+}
+
+/// Run a command against the plugin listening on the given socket.
+pub async fn run(command: Command, uds_path: &str) -> Result<()> {
+    let channel = connect(uds_path)
+        .await
+        .with_context(|| format!("failed to connect to {uds_path}, is the plugin running?"))?;
+    let mut client = DeviceServiceClient::new(channel.clone());
+
+    match command {
+        Command::Health => {
+            let response = client.health(HealthRequest {}).await?;
+            let metadata = response.metadata();
+            let health = response.get_ref();
+
+            println!("{:<24} {}", "status", health.status().as_str_name());
+            println!("{:<24} {}s", "uptime", health.uptime_seconds);
+            for (name, key) in [
+                ("reachable", REACHABLE_METADATA),
+                ("consecutive failures", CONSECUTIVE_FAILURES_METADATA),
+                ("total failures", TOTAL_FAILURES_METADATA),
+                ("last error", LAST_ERROR_METADATA),
+            ] {
+                if let Some(value) = metadata.get(key).and_then(|value| value.to_str().ok()) {
+                    println!("{name:<24} {value}");
+                }
+            }
+        }
+        Command::Status => {
+            let response = client
+                .status(StatusRequest {
+                    device_id: DEVICE_ID.into(),
+                })
+                .await?
+                .into_inner();
+
+            for status in response.status {
+                print_status(&status);
+            }
+        }
+        Command::Watch { interval_ms } => {
+            let mut updates = StatusStreamClient::new(channel)
+                .watch_status(WatchStatusRequest {
+                    device_id: DEVICE_ID.into(),
+                    interval_ms,
+                })
+                .await?
+                .into_inner();
+
+            while let Some(update) = updates.message().await? {
+                for status in update.status {
+                    print_status(&status);
+                }
+            }
+        }
+        Command::Set { channel, duty } => {
+            client
+                .fixed_duty(FixedDutyRequest {
+                    device_id: DEVICE_ID.into(),
+                    channel_id: channel,
+                    duty,
+                })
+                .await?;
+        }
+        Command::Reset { channel } => {
+            let channels = match channel {
+                Some(channel) => vec![channel],
+                None => vec![FAN_1_CHANNEL_ID.into(), FAN_2_CHANNEL_ID.into()],
+            };
+
+            for channel_id in channels {
+                client
+                    .reset_channel(ResetChannelRequest {
+                        device_id: DEVICE_ID.into(),
+                        channel_id,
+                    })
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_status(status: &v1::Status) {
+    let value = match &status.metric {
+        Some(Metric::Temp(temp)) => format!("{temp:.1} °C"),
+        Some(Metric::Speed(speed)) => match (speed.duty, speed.rpm) {
+            (Some(duty), Some(rpm)) => format!("{duty:.0}% ({rpm} RPM)"),
+            (Some(duty), None) => format!("{duty:.0}%"),
+            (None, Some(rpm)) => format!("{rpm} RPM"),
+            (None, None) => "unknown".into(),
+        },
+        Some(Metric::Mhz(mhz)) => format!("{mhz} MHz"),
+        Some(Metric::Watts(watts)) => format!("{watts:.0} W"),
+        None => "unknown".into(),
+    };
+    println!("{:<24} {value}", status.id);
+}
+
+/// Connect to the plugin listening on the given socket.
+pub async fn connect(uds_path: &str) -> Result<Channel> {
+    let uds_path = uds_path.to_owned();
+
+    // The URI is required by tonic but unused, as the connector ignores it.
+    let channel = Endpoint::try_from("http://[::]:50051")?
+        .connect_with_connector(service_fn(move |_| {
+            let uds_path = uds_path.clone();
+            async move { uds::connect(&uds_path).await.map(TokioIo::new) }
+        }))
+        .await?;
+
+    Ok(channel)
+}
@@ -0,0 +1,332 @@
+use crate::{
+    curve::Curve,
+    filter::FilterSettings,
+    metrics::MetricsAddress,
+    service::{FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::{collections::HashMap, io, net::SocketAddr, path::Path, path::PathBuf};
+
+/// Where the configuration file is read from unless another is given.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/tuxedo-infinitybook-gen10-cc-plugin/config.toml";
+
+/// Settings read from the configuration file.
+///
+/// Every setting is optional, and one given on the command line takes
+/// precedence over the file. The labels, `[limits]` fan floors and
+/// `[smoothing]` are reloaded on SIGHUP; everything else is only read when
+/// starting.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path of the Unix socket to serve on.
+    pub socket_path: Option<String>,
+
+    /// TCP address to also serve on, without any authentication.
+    pub listen_tcp: Option<SocketAddr>,
+
+    /// Vsock port to also serve virtual machines on, without any
+    /// authentication.
+    pub listen_vsock: Option<u32>,
+
+    /// File to lock while running, so that only one instance drives the
+    /// hardware at a time.
+    pub lock_file: Option<PathBuf>,
+
+    /// Labels to show in CoolerControl, keyed by channel or temperature ID.
+    pub labels: HashMap<String, String>,
+
+    pub access: Access,
+    pub limits: Limits,
+    pub smoothing: Smoothing,
+    pub startup: Startup,
+    pub metrics: Metrics,
+
+    /// Fan curve to run when CoolerControl stops making requests.
+    pub fallback: Option<Fallback>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Access {
+    /// Users other than root allowed to connect to the socket.
+    pub uids: Vec<u32>,
+
+    /// Groups whose members are allowed to connect to the socket, by the
+    /// primary group of the connecting process.
+    pub gids: Vec<u32>,
+
+    /// Permissions to give the socket file, such as `0o660`.
+    pub socket_mode: Option<u32>,
+
+    /// Group to give the socket file to. Processes with it as their primary
+    /// group are allowed to connect too.
+    pub socket_group: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Never run the fans slower than this percentage.
+    pub fan_floor: Option<u8>,
+
+    /// Never run individual fans slower than these percentages, keyed by
+    /// channel ID.
+    pub min_duty_floor: HashMap<String, u8>,
+
+    /// Raw fan speed value the EC treats as full speed.
+    pub max_fan_raw: Option<i32>,
+
+    /// Speed of the fans at full speed in RPM, to estimate their RPM from the
+    /// duty when it can't be read.
+    pub max_rpm: Option<u32>,
+
+    /// Run the fans at full speed from this temperature in degrees Celsius,
+    /// from 50 to 110.
+    pub critical_temp: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Smoothing {
+    /// Minimum time in milliseconds between speed changes of the same fan.
+    pub min_write_interval_ms: Option<u64>,
+
+    /// Largest change of a fan's speed in percent per second.
+    pub ramp_rate: Option<f64>,
+
+    /// Weight of a new fan duty reading in the moving average of the duty
+    /// reported to CoolerControl, from 0 to 1.
+    pub duty_alpha: Option<f64>,
+
+    /// Weight of a new temperature reading in the moving average of the
+    /// temperature reported to CoolerControl, from 0 to 1.
+    pub temp_alpha: Option<f64>,
+
+    /// How far a fan duty reading has to move in percent before the duty
+    /// reported to CoolerControl changes.
+    pub duty_deadband: Option<f64>,
+
+    /// How far a temperature reading has to move in degrees Celsius before
+    /// the temperature reported to CoolerControl changes.
+    pub temp_deadband: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Startup {
+    /// Path of the TUXEDO driver's ioctl device.
+    pub device_path: Option<PathBuf>,
+
+    /// Seconds to wait for the driver to appear when starting.
+    pub device_wait: Option<u64>,
+
+    /// Load the driver's kernel module if its device is missing. Defaults to
+    /// true.
+    pub modprobe: Option<bool>,
+
+    /// Drop root privileges to this user once the driver is open and the
+    /// socket is bound.
+    pub user: Option<String>,
+
+    /// Restrict the files and system calls available to the service once it
+    /// has started.
+    pub sandbox: bool,
+
+    /// Hand the fan and power policy to the OS (`true`) or the firmware
+    /// (`false`) when starting, on firmware that has a switch for it.
+    pub mode_enable: Option<bool>,
+
+    /// Leave power limits and the performance profile as they are when
+    /// stopping.
+    pub keep_power_settings: bool,
+
+    /// Check that the driver is reachable every this many seconds.
+    pub self_check_interval: Option<u64>,
+
+    /// Log a snapshot of the device state every this many seconds.
+    pub telemetry_interval: Option<u64>,
+
+    /// Return the fans to firmware control after this many seconds without
+    /// requests.
+    pub watchdog_timeout: Option<u64>,
+
+    /// Milliseconds to wait for a driver call before giving up on it.
+    pub io_timeout_ms: Option<u64>,
+
+    /// Seconds to let calls in progress finish when stopping, before the fans
+    /// are handed back to the firmware regardless.
+    pub shutdown_timeout: Option<u64>,
+
+    /// Whether requests are served from one thread or from one per CPU.
+    pub runtime: Option<RuntimeFlavor>,
+}
+
+/// How the async runtime runs the service.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuntimeFlavor {
+    /// Everything on the main thread, which is plenty for a couple of fans
+    #[default]
+    CurrentThread,
+    /// A worker thread per CPU, for when many clients are streaming status
+    MultiThread,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metrics {
+    /// TCP address or Unix socket path to serve Prometheus metrics on.
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fallback {
+    /// Run the curve after this many seconds without requests.
+    pub timeout: u64,
+
+    /// Temperature and duty pairs, followed by each fan using its own sensor.
+    pub curve: Vec<(f64, u32)>,
+}
+
+impl Fallback {
+    pub fn curve(&self) -> Result<Curve> {
+        Curve::new(self.curve.clone()).map_err(|e| anyhow!("fallback.curve: {}", e.message()))
+    }
+}
+
+impl Smoothing {
+    pub fn filter_settings(&self) -> FilterSettings {
+        let defaults = FilterSettings::default();
+
+        FilterSettings {
+            duty_alpha: self.duty_alpha.unwrap_or(defaults.duty_alpha),
+            temp_alpha: self.temp_alpha.unwrap_or(defaults.temp_alpha),
+            duty_deadband: self.duty_deadband.unwrap_or(defaults.duty_deadband),
+            temp_deadband: self.temp_deadband.unwrap_or(defaults.temp_deadband),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn address(&self) -> Result<Option<MetricsAddress>> {
+        self.listen
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| anyhow!("metrics.listen: {e}"))
+    }
+}
+
+impl Config {
+    /// Read the configuration file at the given path, or `None` if there is
+    /// no file there.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+
+        let config: Self =
+            toml::from_str(&contents).with_context(|| format!("invalid {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid {}", path.display()))?;
+
+        Ok(Some(config))
+    }
+
+    /// Check the ranges that the command line options enforce for the same
+    /// settings.
+    fn validate(&self) -> Result<()> {
+        if self.access.socket_mode.is_some_and(|mode| mode > 0o777) {
+            bail!("access.socket_mode must be a permission mode such as 0o660");
+        }
+        if self.limits.fan_floor.is_some_and(|floor| floor > 100) {
+            bail!("limits.fan_floor must be a percentage from 0 to 100");
+        }
+        for (channel_id, floor) in &self.limits.min_duty_floor {
+            if ![FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID].contains(&channel_id.as_str()) {
+                bail!("limits.min_duty_floor: unknown fan channel ID {channel_id}");
+            }
+            if *floor > 100 {
+                bail!("limits.min_duty_floor.{channel_id} must be a percentage from 0 to 100");
+            }
+        }
+        if self.limits.max_fan_raw.is_some_and(|max| max < 1) {
+            bail!("limits.max_fan_raw must be at least 1");
+        }
+        if self
+            .limits
+            .critical_temp
+            .is_some_and(|temp| !(50..=110).contains(&temp))
+        {
+            bail!("limits.critical_temp must be from 50 to 110 degrees Celsius");
+        }
+        if self.limits.max_rpm == Some(0) {
+            bail!("limits.max_rpm must be at least 1");
+        }
+        if self
+            .smoothing
+            .ramp_rate
+            .is_some_and(|rate| !(rate.is_finite() && rate > 0.0))
+        {
+            bail!("smoothing.ramp_rate must be a number greater than 0");
+        }
+        for (name, alpha) in [
+            ("duty_alpha", self.smoothing.duty_alpha),
+            ("temp_alpha", self.smoothing.temp_alpha),
+        ] {
+            if alpha.is_some_and(|alpha| !(alpha > 0.0 && alpha <= 1.0)) {
+                bail!("smoothing.{name} must be greater than 0 and at most 1");
+            }
+        }
+        for (name, deadband) in [
+            ("duty_deadband", self.smoothing.duty_deadband),
+            ("temp_deadband", self.smoothing.temp_deadband),
+        ] {
+            if deadband.is_some_and(|deadband| deadband.is_nan() || deadband < 0.0) {
+                bail!("smoothing.{name} must not be negative");
+            }
+        }
+        if self.startup.self_check_interval == Some(0) {
+            bail!("startup.self_check_interval must be at least 1");
+        }
+        if self.startup.telemetry_interval == Some(0) {
+            bail!("startup.telemetry_interval must be at least 1");
+        }
+        if self.startup.io_timeout_ms == Some(0) {
+            bail!("startup.io_timeout_ms must be at least 1");
+        }
+        if self.startup.watchdog_timeout == Some(0) {
+            bail!("startup.watchdog_timeout must be at least 1");
+        }
+        self.metrics.address()?;
+        if let Some(fallback) = &self.fallback {
+            if fallback.timeout == 0 {
+                bail!("fallback.timeout must be at least 1");
+            }
+            fallback.curve()?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a ramp rate from the command line, which like `smoothing.ramp_rate`
+/// must be a positive number.
+pub fn parse_ramp_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        Ok(_) => Err("must be a number greater than 0".into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests;
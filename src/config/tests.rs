@@ -0,0 +1,223 @@
+use super::*;
+use crate::{
+    Args,
+    device_service::v1::{ListDevicesRequest, device_service_server::DeviceService},
+    mock::MockBackend,
+    reload_on_hangup,
+};
+use clap::Parser;
+use nix::libc;
+use std::time::Duration;
+use tokio::{
+    signal::unix::{SignalKind, signal},
+    time::sleep,
+};
+use tonic::Request;
+
+fn validate(contents: &str) -> Result<()> {
+    toml::from_str::<Config>(contents).unwrap().validate()
+}
+
+/// Check that a config is rejected with an error naming the setting.
+fn assert_rejected(contents: &str, setting: &str) {
+    match validate(contents) {
+        Ok(()) => panic!("{contents:?} was accepted"),
+        Err(e) => assert!(
+            e.to_string().contains(setting),
+            "error for {contents:?} doesn't name {setting}: {e}"
+        ),
+    }
+}
+
+#[test]
+fn empty_config_is_valid() {
+    validate("").unwrap();
+}
+
+#[test]
+fn settings_within_range_are_valid() {
+    validate(
+        r#"
+        [access]
+        socket_mode = 0o660
+
+        [limits]
+        fan_floor = 100
+        min_duty_floor = { fan1 = 0, fan2 = 100 }
+        max_fan_raw = 1
+        critical_temp = 110
+        max_rpm = 1
+
+        [smoothing]
+        duty_alpha = 1.0
+        temp_alpha = 0.01
+        duty_deadband = 0.0
+        temp_deadband = 2.5
+        ramp_rate = 0.5
+
+        [startup]
+        self_check_interval = 1
+        telemetry_interval = 1
+        io_timeout_ms = 1
+        watchdog_timeout = 1
+
+        [metrics]
+        listen = "127.0.0.1:9100"
+
+        [fallback]
+        timeout = 1
+        curve = [[40.0, 30], [80.0, 100]]
+        "#,
+    )
+    .unwrap();
+}
+
+#[test]
+fn socket_mode_must_be_a_permission_mode() {
+    assert_rejected("[access]\nsocket_mode = 0o1777", "access.socket_mode");
+}
+
+#[test]
+fn fan_floors_must_be_percentages() {
+    assert_rejected("[limits]\nfan_floor = 101", "limits.fan_floor");
+    assert_rejected(
+        "[limits]\nmin_duty_floor = { fan2 = 101 }",
+        "limits.min_duty_floor.fan2",
+    );
+    assert_rejected(
+        "[limits]\nmin_duty_floor = { fan3 = 50 }",
+        "unknown fan channel ID fan3",
+    );
+}
+
+#[test]
+fn raw_maximum_and_rpm_must_be_positive() {
+    assert_rejected("[limits]\nmax_fan_raw = 0", "limits.max_fan_raw");
+    assert_rejected("[limits]\nmax_fan_raw = -5", "limits.max_fan_raw");
+    assert_rejected("[limits]\nmax_rpm = 0", "limits.max_rpm");
+}
+
+#[test]
+fn critical_temperature_must_be_from_50_to_110() {
+    validate("[limits]\ncritical_temp = 50").unwrap();
+    assert_rejected("[limits]\ncritical_temp = 49", "limits.critical_temp");
+    assert_rejected("[limits]\ncritical_temp = 111", "limits.critical_temp");
+}
+
+#[test]
+fn smoothing_weights_must_be_within_zero_and_one() {
+    for name in ["duty_alpha", "temp_alpha"] {
+        for alpha in ["0.0", "-0.5", "1.5", "nan"] {
+            assert_rejected(
+                &format!("[smoothing]\n{name} = {alpha}"),
+                &format!("smoothing.{name}"),
+            );
+        }
+    }
+}
+
+#[test]
+fn smoothing_deadbands_must_not_be_negative() {
+    for name in ["duty_deadband", "temp_deadband"] {
+        for deadband in ["-0.1", "nan"] {
+            assert_rejected(
+                &format!("[smoothing]\n{name} = {deadband}"),
+                &format!("smoothing.{name}"),
+            );
+        }
+    }
+}
+
+#[test]
+fn ramp_rate_must_be_positive() {
+    for rate in ["0.0", "-1.0", "nan", "inf"] {
+        assert_rejected(
+            &format!("[smoothing]\nramp_rate = {rate}"),
+            "smoothing.ramp_rate",
+        );
+        assert!(parse_ramp_rate(rate).is_err(), "{rate} was accepted");
+    }
+    assert!(parse_ramp_rate("fast").is_err());
+    assert_eq!(parse_ramp_rate("2.5"), Ok(2.5));
+}
+
+#[test]
+fn intervals_and_timeouts_must_be_positive() {
+    for name in [
+        "self_check_interval",
+        "telemetry_interval",
+        "io_timeout_ms",
+        "watchdog_timeout",
+    ] {
+        assert_rejected(
+            &format!("[startup]\n{name} = 0"),
+            &format!("startup.{name}"),
+        );
+    }
+    assert_rejected(
+        "[fallback]\ntimeout = 0\ncurve = [[40.0, 30]]",
+        "fallback.timeout",
+    );
+}
+
+#[test]
+fn metrics_address_and_fallback_curve_must_parse() {
+    assert_rejected("[metrics]\nlisten = \"localhost\"", "metrics.listen");
+    assert_rejected("[fallback]\ntimeout = 10\ncurve = []", "fallback.curve");
+}
+
+#[test]
+fn load_names_the_file_of_an_invalid_config() {
+    let path = std::env::temp_dir().join(format!("tuxedo-config-test-{}.toml", std::process::id()));
+    std::fs::write(&path, "[limits]\nfan_floor = 200\n").unwrap();
+
+    let error = Config::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(error.to_string().contains(&path.display().to_string()));
+    assert!(format!("{error:#}").contains("limits.fan_floor"));
+}
+
+#[tokio::test]
+async fn invalid_config_is_not_reloaded() {
+    let path = std::env::temp_dir().join(format!("tuxedo-reload-test-{}.toml", std::process::id()));
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let args = Args::parse_from(["test"]);
+    let hangup = signal(SignalKind::hangup()).unwrap();
+
+    let fan1_label = || async {
+        service
+            .list_devices(Request::new(ListDevicesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .devices[0]
+            .info
+            .as_ref()
+            .unwrap()
+            .channels["fan1"]
+            .label
+            .clone()
+            .unwrap()
+    };
+    // Send SIGHUP and give the reload a moment to happen.
+    let reload = |contents: &'static str| {
+        std::fs::write(&path, contents).unwrap();
+        unsafe { libc::raise(libc::SIGHUP) };
+        sleep(Duration::from_millis(100))
+    };
+
+    tokio::select! {
+        () = reload_on_hangup(&service, &args, &path, hangup) => unreachable!(),
+        () = async {
+            reload("[labels]\nfan1 = \"CPU\"\n").await;
+            assert_eq!(fan1_label().await, "CPU");
+
+            reload("[labels]\nfan1 = \"GPU\"\n[limits]\nfan_floor = 200\n").await;
+            assert_eq!(fan1_label().await, "CPU");
+        } => {}
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
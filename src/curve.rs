@@ -0,0 +1,67 @@
+use tonic::Status;
+
+/// A fan curve that maps temperatures to fan duties, interpolating linearly
+/// between its points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve {
+    /// Temperature and duty pairs, ordered by temperature.
+    points: Vec<(f64, f64)>,
+}
+
+impl Curve {
+    /// Create a curve from temperature (in degrees Celsius) and duty (as a
+    /// percentage) pairs, in any order.
+    pub fn new(mut points: Vec<(f64, u32)>) -> Result<Self, Status> {
+        if points.is_empty() {
+            return Err(Status::invalid_argument("Speed profile has no points"));
+        }
+
+        for &(temp, duty) in &points {
+            if !temp.is_finite() {
+                return Err(Status::invalid_argument(format!(
+                    "Speed profile temperature {temp} is not a number"
+                )));
+            }
+
+            if duty > 100 {
+                return Err(Status::invalid_argument(format!(
+                    "Speed profile duty {duty}% is over 100%"
+                )));
+            }
+        }
+
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(Self {
+            points: points
+                .into_iter()
+                .map(|(temp, duty)| (temp, duty.into()))
+                .collect(),
+        })
+    }
+
+    /// Get the duty for the given temperature. Temperatures outside of the
+    /// curve use the duty of the nearest end.
+    pub fn duty_at(&self, temp: f64) -> f64 {
+        let (first_temp, first_duty) = self.points[0];
+        let (last_temp, last_duty) = self.points[self.points.len() - 1];
+
+        if temp <= first_temp {
+            return first_duty;
+        }
+        if temp >= last_temp {
+            return last_duty;
+        }
+
+        for window in self.points.windows(2) {
+            let ((low_temp, low_duty), (high_temp, high_duty)) = (window[0], window[1]);
+
+            if temp <= high_temp {
+                let ratio = (temp - low_temp) / (high_temp - low_temp);
+                return low_duty + (high_duty - low_duty) * ratio;
+            }
+        }
+
+        last_duty
+    }
+}
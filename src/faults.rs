@@ -0,0 +1,338 @@
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp},
+};
+use nix::libc;
+use std::{
+    fmt,
+    io::{Error, Result},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// A driver call that faults can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Call {
+    Check,
+    GetMode,
+    GetModeEnable,
+    SetModeEnable,
+    SetPerformanceProfile,
+    GetTdp,
+    SetTdp,
+    GetFanMinSpeed,
+    GetFanSpeed,
+    ReadAllStatus,
+    SetFanSpeed,
+    SetFansAuto,
+}
+
+impl Call {
+    const ALL: [(Call, &str); 12] = [
+        (Call::Check, "check"),
+        (Call::GetMode, "get_mode"),
+        (Call::GetModeEnable, "get_mode_enable"),
+        (Call::SetModeEnable, "set_mode_enable"),
+        (Call::SetPerformanceProfile, "set_performance_profile"),
+        (Call::GetTdp, "get_tdp"),
+        (Call::SetTdp, "set_tdp"),
+        (Call::GetFanMinSpeed, "get_fan_min_speed"),
+        (Call::GetFanSpeed, "get_fan_speed"),
+        (Call::ReadAllStatus, "read_all_status"),
+        (Call::SetFanSpeed, "set_fan_speed"),
+        (Call::SetFansAuto, "set_fans_auto"),
+    ];
+}
+
+/// What goes wrong in a faulty call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail with an OS error, such as `EBUSY` or `EIO`.
+    Errno(i32),
+
+    /// Block for this long before making the call, like an EC that stops
+    /// responding.
+    Hang(Duration),
+
+    /// Return a nonsensical value without making the call. Writes pretend to
+    /// succeed without doing anything.
+    Garbage,
+}
+
+/// Error numbers that can be named in a fault, as the driver is known to
+/// return them.
+const ERRNO_NAMES: [(&str, i32); 8] = [
+    ("EAGAIN", libc::EAGAIN),
+    ("EBUSY", libc::EBUSY),
+    ("EINVAL", libc::EINVAL),
+    ("EIO", libc::EIO),
+    ("ENODEV", libc::ENODEV),
+    ("ENOENT", libc::ENOENT),
+    ("ENOTTY", libc::ENOTTY),
+    ("EPERM", libc::EPERM),
+];
+
+/// A fault injected into a call, given on the command line as
+/// `CALL=FAULT[*TIMES]`, such as `set_fan_speed=EIO*3`,
+/// `read_all_status=hang:6000` or `get_fan_speed=garbage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultSpec {
+    pub call: Call,
+    pub fault: Fault,
+
+    /// How many calls fail before the fault goes away, or `None` to fail all
+    /// of them.
+    pub times: Option<usize>,
+}
+
+impl FromStr for FaultSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (call, fault) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected CALL=FAULT, got {s:?}"))?;
+        let call = Call::ALL
+            .iter()
+            .find(|(_, name)| *name == call)
+            .map(|(call, _)| *call)
+            .ok_or_else(|| format!("unknown call {call:?}"))?;
+
+        let (fault, times) = match fault.split_once('*') {
+            Some((fault, times)) => (
+                fault,
+                Some(
+                    times
+                        .parse()
+                        .map_err(|_| format!("invalid count {times:?}"))?,
+                ),
+            ),
+            None => (fault, None),
+        };
+
+        let fault = if fault == "garbage" {
+            Fault::Garbage
+        } else if let Some(millis) = fault.strip_prefix("hang:") {
+            Fault::Hang(Duration::from_millis(
+                millis
+                    .parse()
+                    .map_err(|_| format!("invalid hang duration {millis:?}"))?,
+            ))
+        } else {
+            ERRNO_NAMES
+                .iter()
+                .find(|(name, _)| *name == fault)
+                .map(|(_, errno)| Fault::Errno(*errno))
+                .ok_or_else(|| format!("unknown fault {fault:?}"))?
+        };
+
+        Ok(Self { call, fault, times })
+    }
+}
+
+impl fmt::Display for FaultSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, call) = Call::ALL
+            .iter()
+            .find(|(call, _)| *call == self.call)
+            .expect("every call is named");
+
+        write!(f, "{call}=")?;
+        match self.fault {
+            Fault::Errno(errno) => write!(f, "{}", Error::from_raw_os_error(errno))?,
+            Fault::Hang(duration) => write!(f, "hang for {duration:?}")?,
+            Fault::Garbage => write!(f, "garbage")?,
+        }
+        match self.times {
+            Some(times) => write!(f, " for {times} calls"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Faults to inject, shared between the code setting them up and the backends
+/// they are injected into. The first fault given for a call is used until it
+/// runs out.
+#[derive(Clone, Default)]
+pub struct Faults(Arc<Mutex<Vec<FaultSpec>>>);
+
+impl Faults {
+    pub fn inject(&self, spec: FaultSpec) {
+        self.0.lock().unwrap().push(spec);
+    }
+
+    /// Take the fault to apply to a call, if any.
+    fn take(&self, call: Call) -> Option<Fault> {
+        let mut specs = self.0.lock().unwrap();
+        let index = specs.iter().position(|spec| spec.call == call)?;
+        let spec = &mut specs[index];
+        let fault = spec.fault;
+
+        match &mut spec.times {
+            Some(1) => {
+                specs.remove(index);
+            }
+            Some(times) => *times -= 1,
+            None => {}
+        }
+
+        Some(fault)
+    }
+}
+
+/// A value returned by a call that produced garbage.
+trait Garbage {
+    fn garbage() -> Self;
+}
+
+impl Garbage for () {
+    fn garbage() -> Self {}
+}
+
+impl Garbage for bool {
+    fn garbage() -> Self {
+        true
+    }
+}
+
+impl Garbage for u8 {
+    fn garbage() -> Self {
+        u8::MAX
+    }
+}
+
+impl Garbage for u32 {
+    fn garbage() -> Self {
+        u32::MAX
+    }
+}
+
+impl Garbage for i32 {
+    fn garbage() -> Self {
+        -1
+    }
+}
+
+impl Garbage for f64 {
+    fn garbage() -> Self {
+        // A raw speed of 0xffff, far beyond what any EC reports.
+        f64::from(u16::MAX) * 100.0 / 200.0
+    }
+}
+
+impl Garbage for FanStatusSnapshot {
+    fn garbage() -> Self {
+        Self {
+            speeds: [f64::garbage(); 2],
+            temperatures: [u8::garbage(); 2],
+            mode: Some(i32::garbage()),
+            mode_enable: Some(bool::garbage()),
+            fans_off_available: false,
+            tdp: [Some(u32::garbage()); 3],
+        }
+    }
+}
+
+/// Wraps a backend to make the calls given in [`Faults`] misbehave, so that
+/// recovery from driver failures can be tried out without broken hardware.
+pub struct FaultyBackend {
+    inner: Box<dyn FanBackend>,
+    faults: Faults,
+}
+
+impl FaultyBackend {
+    pub fn new(inner: Box<dyn FanBackend>, faults: Faults) -> Self {
+        Self { inner, faults }
+    }
+
+    fn call<T: Garbage>(&self, call: Call, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        match self.faults.take(call) {
+            None => f(),
+            Some(Fault::Errno(errno)) => Err(Error::from_raw_os_error(errno)),
+            Some(Fault::Hang(duration)) => {
+                thread::sleep(duration);
+                f()
+            }
+            Some(Fault::Garbage) => Ok(T::garbage()),
+        }
+    }
+}
+
+impl FanBackend for FaultyBackend {
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn check(&self) -> Result<()> {
+        self.call(Call::Check, || self.inner.check())
+    }
+
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        self.inner.get_tdp_bounds(tdp)
+    }
+
+    fn fans_off_available(&self) -> bool {
+        self.inner.fans_off_available()
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        self.inner.performance_profiles_available()
+    }
+
+    fn get_mode(&self) -> Result<i32> {
+        self.call(Call::GetMode, || self.inner.get_mode())
+    }
+
+    fn get_mode_enable(&self) -> Result<bool> {
+        self.call(Call::GetModeEnable, || self.inner.get_mode_enable())
+    }
+
+    fn set_mode_enable(&self, enabled: bool) -> Result<()> {
+        self.call(Call::SetModeEnable, || self.inner.set_mode_enable(enabled))
+    }
+
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()> {
+        self.call(Call::SetPerformanceProfile, || {
+            self.inner.set_performance_profile(profile)
+        })
+    }
+
+    fn get_tdp(&self, tdp: Tdp) -> Result<u32> {
+        self.call(Call::GetTdp, || self.inner.get_tdp(tdp))
+    }
+
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()> {
+        self.call(Call::SetTdp, || self.inner.set_tdp(tdp, watts))
+    }
+
+    fn get_fan_min_speed(&self) -> Result<u8> {
+        self.call(Call::GetFanMinSpeed, || self.inner.get_fan_min_speed())
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        self.call(Call::GetFanSpeed, || self.inner.get_fan_speed(fan))
+    }
+
+    fn read_all_status(&self) -> Result<FanStatusSnapshot> {
+        self.call(Call::ReadAllStatus, || self.inner.read_all_status())
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        self.call(Call::SetFanSpeed, || {
+            self.inner.set_fan_speed(fan, percentage)
+        })
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        self.inner.fan_speed_value(percentage)
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        self.call(Call::SetFansAuto, || self.inner.set_fans_auto())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,58 @@
+use super::*;
+
+#[test]
+fn fault_specs_parse() {
+    assert_eq!(
+        "set_fan_speed=EIO*3".parse(),
+        Ok(FaultSpec {
+            call: Call::SetFanSpeed,
+            fault: Fault::Errno(libc::EIO),
+            times: Some(3),
+        })
+    );
+    assert_eq!(
+        "read_all_status=hang:6000".parse(),
+        Ok(FaultSpec {
+            call: Call::ReadAllStatus,
+            fault: Fault::Hang(Duration::from_secs(6)),
+            times: None,
+        })
+    );
+    assert_eq!(
+        "get_fan_speed=garbage*1".parse(),
+        Ok(FaultSpec {
+            call: Call::GetFanSpeed,
+            fault: Fault::Garbage,
+            times: Some(1),
+        })
+    );
+
+    for invalid in [
+        "set_fan_speed",
+        "set_fan_sped=EIO",
+        "set_fan_speed=EWHAT",
+        "set_fan_speed=hang:soon",
+        "set_fan_speed=EIO*many",
+    ] {
+        assert!(invalid.parse::<FaultSpec>().is_err(), "{invalid} parsed");
+    }
+}
+
+#[test]
+fn faults_run_out_after_the_given_number_of_calls() {
+    let faults = Faults::default();
+    faults.inject("set_fans_auto=EBUSY*2".parse().unwrap());
+    faults.inject("set_fans_auto=garbage".parse().unwrap());
+
+    assert_eq!(
+        faults.take(Call::SetFansAuto),
+        Some(Fault::Errno(libc::EBUSY))
+    );
+    assert_eq!(
+        faults.take(Call::SetFansAuto),
+        Some(Fault::Errno(libc::EBUSY))
+    );
+    assert_eq!(faults.take(Call::SetFansAuto), Some(Fault::Garbage));
+    assert_eq!(faults.take(Call::SetFansAuto), Some(Fault::Garbage));
+    assert_eq!(faults.take(Call::SetFanSpeed), None);
+}
@@ -0,0 +1,120 @@
+use crate::tuxedo_io::Fan;
+
+/// How the values reported through the status RPC are filtered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSettings {
+    /// Weight of a new fan duty reading in the moving average, from 0 to 1.
+    /// At 1 readings are not averaged.
+    pub duty_alpha: f64,
+
+    /// Weight of a new temperature reading in the moving average, from 0 to
+    /// 1. At 1 readings are not averaged.
+    pub temp_alpha: f64,
+
+    /// How far a fan duty reading has to move in percent before the reported
+    /// duty changes.
+    pub duty_deadband: f64,
+
+    /// How far a temperature reading has to move in degrees Celsius before the
+    /// reported temperature changes.
+    pub temp_deadband: f64,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            duty_alpha: 1.0,
+            temp_alpha: 1.0,
+            duty_deadband: 0.0,
+            temp_deadband: 0.0,
+        }
+    }
+}
+
+/// Filters applied to the values reported by the status RPC, so that noise in
+/// the readings does not show up in CoolerControl. Readings are averaged
+/// first, and the average is then held within the deadband.
+#[derive(Default)]
+pub struct StatusFilters {
+    speeds: [Filter; 2],
+    temperatures: [Filter; 2],
+}
+
+impl StatusFilters {
+    pub fn new(settings: FilterSettings) -> Self {
+        let mut filters = Self::default();
+        filters.configure(settings);
+        filters
+    }
+
+    /// Change the settings, for readings from now on.
+    pub fn configure(&mut self, settings: FilterSettings) {
+        for filter in &mut self.speeds {
+            filter.average.alpha = settings.duty_alpha;
+            filter.deadband.width = settings.duty_deadband;
+        }
+        for filter in &mut self.temperatures {
+            filter.average.alpha = settings.temp_alpha;
+            filter.deadband.width = settings.temp_deadband;
+        }
+    }
+
+    /// Get the duty to report for a fan, given a new reading.
+    pub fn speed(&mut self, fan: Fan, duty: f64) -> f64 {
+        self.speeds[fan as usize].apply(duty)
+    }
+
+    /// Get the temperature to report for a sensor, identified by the fan it is
+    /// associated with, given a new reading.
+    pub fn temperature(&mut self, fan: Fan, temp: f64) -> f64 {
+        self.temperatures[fan as usize].apply(temp)
+    }
+}
+
+/// The filters of a single value.
+#[derive(Default)]
+struct Filter {
+    average: MovingAverage,
+    deadband: Deadband,
+}
+
+impl Filter {
+    fn apply(&mut self, value: f64) -> f64 {
+        self.deadband.apply(self.average.apply(value))
+    }
+}
+
+/// Exponential moving average of the readings of a value.
+#[derive(Default)]
+struct MovingAverage {
+    alpha: f64,
+    average: Option<f64>,
+}
+
+impl MovingAverage {
+    fn apply(&mut self, value: f64) -> f64 {
+        match self.average {
+            Some(average) if self.alpha < 1.0 => *self
+                .average
+                .insert(average + self.alpha * (value - average)),
+            _ => *self.average.insert(value),
+        }
+    }
+}
+
+/// Holds a reported value steady until the readings move far enough away from
+/// it.
+#[derive(Default)]
+struct Deadband {
+    width: f64,
+    reported: Option<f64>,
+}
+
+impl Deadband {
+    fn apply(&mut self, value: f64) -> f64 {
+        match self.reported {
+            Some(reported) if (value - reported).abs() < self.width => reported,
+            _ => *self.reported.insert(value),
+        }
+    }
+}
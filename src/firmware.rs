@@ -0,0 +1,61 @@
+use crate::modprobe::DRIVER_MODULE;
+use std::{fs, path::Path};
+
+const DMI_DIR: &str = "/sys/class/dmi/id";
+const MODULE_DIR: &str = "/sys/module";
+
+/// Versions of the hardware, firmware and driver in use, for telling exactly
+/// what the plugin is driving in bug reports.
+///
+/// Anything that can't be read is left out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FirmwareInfo {
+    pub product: Option<String>,
+    pub bios_version: Option<String>,
+    pub bios_date: Option<String>,
+    pub ec_version: Option<String>,
+    pub driver_version: Option<String>,
+}
+
+impl FirmwareInfo {
+    /// Read the versions from the DMI data and the loaded driver module.
+    pub fn read() -> Self {
+        let dmi = Path::new(DMI_DIR);
+
+        Self {
+            product: read_value(&dmi.join("product_name")),
+            bios_version: read_value(&dmi.join("bios_version")),
+            bios_date: read_value(&dmi.join("bios_date")),
+            ec_version: read_value(&dmi.join("ec_firmware_release")),
+            driver_version: read_value(&Path::new(MODULE_DIR).join(DRIVER_MODULE).join("version")),
+        }
+    }
+
+    /// Describe the versions on one line, such as `BIOS 1.07.04 (01/15/2025),
+    /// EC 1.4, tuxedo_io 0.3.6`. Returns `None` if none are known.
+    pub fn versions(&self) -> Option<String> {
+        let mut parts = Vec::new();
+
+        if let Some(version) = &self.bios_version {
+            parts.push(match &self.bios_date {
+                Some(date) => format!("BIOS {version} ({date})"),
+                None => format!("BIOS {version}"),
+            });
+        }
+        if let Some(version) = &self.ec_version {
+            parts.push(format!("EC {version}"));
+        }
+        if let Some(version) = &self.driver_version {
+            parts.push(format!("{DRIVER_MODULE} {version}"));
+        }
+
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+fn read_value(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    let value = value.trim();
+
+    (!value.is_empty()).then(|| value.to_owned())
+}
@@ -0,0 +1,93 @@
+use crate::device_service::v1::health_response;
+use std::sync::{
+    Mutex,
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+};
+use tonic::Status;
+use tracing::{info, warn};
+
+/// Number of consecutive failed driver accesses after which the plugin reports
+/// itself as unhealthy.
+const FAILURE_THRESHOLD: u32 = 3;
+
+/// Tracks whether the driver is currently reachable, based on the outcome of
+/// recent requests.
+#[derive(Default)]
+pub struct HealthMonitor {
+    consecutive_failures: AtomicU32,
+    total_failures: AtomicU64,
+
+    /// Message of the most recent failure, kept after recovering so that it
+    /// can still be looked up.
+    last_error: Mutex<Option<String>>,
+
+    /// Whether the driver is being reopened after the device was lost.
+    recovering: AtomicBool,
+}
+
+impl HealthMonitor {
+    /// Record the outcome of a request that accessed the driver.
+    pub fn record<T>(&self, result: &Result<T, Status>) {
+        match result {
+            Ok(_) => self.record_success(),
+            Err(e) => self.record_failure(e),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let failures = self.consecutive_failures.swap(0, Ordering::Relaxed);
+
+        if failures >= FAILURE_THRESHOLD {
+            info!("Driver access recovered after {failures} consecutive failures");
+        }
+    }
+
+    pub fn record_failure(&self, error: &Status) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        self.total_failures.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error.message().to_owned());
+
+        if failures == FAILURE_THRESHOLD {
+            warn!(
+                "Driver access failed {failures} times in a row, reporting degraded health: {}",
+                error.message()
+            );
+        }
+    }
+
+    /// Report the device as unreachable while it is being recovered, however
+    /// requests in the meantime turn out.
+    pub fn set_recovering(&self, recovering: bool) {
+        self.recovering.store(recovering, Ordering::Relaxed);
+    }
+
+    /// Whether the device is there to be talked to, even if requests to it
+    /// are failing.
+    pub fn reachable(&self) -> bool {
+        !self.recovering.load(Ordering::Relaxed)
+    }
+
+    /// Number of driver accesses that failed since the last one succeeded.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of driver accesses that failed since the plugin started.
+    pub fn total_failures(&self) -> u64 {
+        self.total_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    pub fn status(&self) -> health_response::Status {
+        if !self.reachable() {
+            health_response::Status::Error
+        } else if self.consecutive_failures() >= FAILURE_THRESHOLD {
+            health_response::Status::Warning
+        } else {
+            health_response::Status::Ok
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp},
+};
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+const HWMON_CLASS_DIR: &str = "/sys/class/hwmon";
+
+/// Fan sensors exposed by a hwmon device in sysfs.
+///
+/// The set of available inputs is resolved once up front. Reading an input
+/// later on may still fail, in which case the value is simply unavailable.
+#[derive(Debug)]
+pub struct Hwmon {
+    dir: PathBuf,
+    fan_inputs: [Option<PathBuf>; 2],
+}
+
+impl Hwmon {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let fan_inputs = Fan::ALL.map(|fan| {
+            let path = dir.join(format!("fan{}_input", fan as usize + 1));
+            path.exists().then_some(path)
+        });
+
+        Self { dir, fan_inputs }
+    }
+
+    /// Find the hwmon device registered by the TUXEDO drivers, if there is
+    /// one.
+    pub fn find() -> Option<Self> {
+        fs::read_dir(HWMON_CLASS_DIR)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|dir| {
+                fs::read_to_string(dir.join("name"))
+                    .is_ok_and(|name| name.trim().starts_with("tuxedo"))
+            })
+            .map(Self::new)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Read the current speed of a fan in RPM.
+    pub fn read_fan_rpm(&self, fan: Fan) -> Option<u32> {
+        let path = self.fan_inputs[fan as usize].as_ref()?;
+
+        match fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse().ok(),
+            Err(e) => {
+                debug!("Failed to read {}: {e}", path.display());
+                None
+            }
+        }
+    }
+}
+
+/// PWM value of a fan running at full speed.
+const PWM_MAX: u32 = 255;
+
+/// `pwmN_enable` values understood by the TUXEDO drivers.
+const PWM_ENABLE_MANUAL: &str = "1";
+const PWM_ENABLE_AUTO: &str = "2";
+
+/// Fan backend that uses the PWM controls of the hwmon device registered by
+/// newer TUXEDO drivers, for when `/dev/tuxedo_io` is not available.
+///
+/// Only fans and temperatures are exposed this way, so power limits and
+/// performance profiles are not supported.
+pub struct HwmonBackend {
+    dir: PathBuf,
+}
+
+impl HwmonBackend {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Find the hwmon device registered by the TUXEDO drivers, if it has PWM
+    /// controls.
+    pub fn open() -> io::Result<Self> {
+        let hwmon = Hwmon::find()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no TUXEDO hwmon device found"))?;
+
+        if !hwmon.dir.join("pwm1").exists() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("{} has no PWM controls", hwmon.dir.display()),
+            ));
+        }
+
+        Ok(Self::new(hwmon.dir))
+    }
+
+    fn read(&self, name: &str) -> io::Result<i64> {
+        fs::read_to_string(self.dir.join(name))?
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{name}: {e}")))
+    }
+
+    fn write(&self, name: &str, value: &str) -> io::Result<()> {
+        fs::write(self.dir.join(name), value)
+    }
+
+    /// Get the temperature of the sensor associated with a fan, in degrees
+    /// Celsius.
+    fn get_fan_temperature(&self, fan: Fan) -> io::Result<u8> {
+        let millidegrees = self.read(&format!("temp{}_input", fan as usize + 1))?;
+
+        Ok((millidegrees / 1000).clamp(0, u8::MAX.into()) as u8)
+    }
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        "not supported by the hwmon interface",
+    )
+}
+
+impl FanBackend for HwmonBackend {
+    fn model(&self) -> String {
+        "hwmon".into()
+    }
+
+    fn check(&self) -> io::Result<()> {
+        self.read("pwm1").map(|_| ())
+    }
+
+    fn get_tdp_bounds(&self, _tdp: Tdp) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn get_tdp(&self, _tdp: Tdp) -> io::Result<u32> {
+        Err(unsupported())
+    }
+
+    fn set_tdp(&self, _tdp: Tdp, _watts: u32) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    fn get_mode(&self) -> io::Result<i32> {
+        Err(unsupported())
+    }
+
+    fn get_mode_enable(&self) -> io::Result<bool> {
+        Err(unsupported())
+    }
+
+    fn set_mode_enable(&self, _enabled: bool) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    fn set_performance_profile(&self, _profile: PerformanceProfile) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    fn fans_off_available(&self) -> bool {
+        false
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        false
+    }
+
+    fn get_fan_min_speed(&self) -> io::Result<u8> {
+        // The driver enforces its own minimum, and does not report it.
+        Ok(0)
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> io::Result<f64> {
+        let pwm = self.read(&format!("pwm{}", fan as usize + 1))?;
+
+        Ok(pwm.clamp(0, PWM_MAX.into()) as f64 * 100.0 / PWM_MAX as f64)
+    }
+
+    fn read_all_status(&self) -> io::Result<FanStatusSnapshot> {
+        Ok(FanStatusSnapshot {
+            speeds: [
+                self.get_fan_speed(Fan::Fan1)?,
+                self.get_fan_speed(Fan::Fan2)?,
+            ],
+            temperatures: [
+                self.get_fan_temperature(Fan::Fan1)?,
+                self.get_fan_temperature(Fan::Fan2)?,
+            ],
+            ..Default::default()
+        })
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> io::Result<i32> {
+        let pwm = self.fan_speed_value(percentage);
+        let n = fan as usize + 1;
+
+        self.write(&format!("pwm{n}_enable"), PWM_ENABLE_MANUAL)?;
+        self.write(&format!("pwm{n}"), &pwm.to_string())?;
+
+        Ok(pwm)
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        (PWM_MAX as f64 * percentage.clamp(0.0, 100.0) / 100.0).round() as i32
+    }
+
+    fn set_fans_auto(&self) -> io::Result<()> {
+        for fan in Fan::ALL {
+            self.write(&format!("pwm{}_enable", fan as usize + 1), PWM_ENABLE_AUTO)?;
+        }
+
+        Ok(())
+    }
+}
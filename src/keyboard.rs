@@ -0,0 +1,104 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const LEDS_CLASS_DIR: &str = "/sys/class/leds";
+
+/// Keyboard backlight LEDs exposed in sysfs.
+///
+/// Keyboards with several lighting zones register one LED per zone, which are
+/// all set to the same color.
+#[derive(Debug)]
+pub struct KeyboardBacklight {
+    zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+struct Zone {
+    dir: PathBuf,
+    max_brightness: u32,
+
+    /// Whether the zone accepts an RGB color, rather than only a brightness.
+    multicolor: bool,
+}
+
+impl KeyboardBacklight {
+    /// Use the LEDs in the given directories, skipping any that cannot be
+    /// read. Returns `None` if none of them are usable.
+    pub fn new(dirs: impl IntoIterator<Item = PathBuf>) -> Option<Self> {
+        let mut zones = dirs
+            .into_iter()
+            .filter_map(|dir| {
+                let max_brightness = fs::read_to_string(dir.join("max_brightness"))
+                    .ok()?
+                    .trim()
+                    .parse()
+                    .ok()?;
+                let multicolor = dir.join("multi_intensity").exists();
+
+                Some(Zone {
+                    dir,
+                    max_brightness,
+                    multicolor,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        zones.sort_by(|a, b| a.dir.cmp(&b.dir));
+
+        (!zones.is_empty()).then_some(Self { zones })
+    }
+
+    /// Find the keyboard backlight LEDs, if there are any.
+    pub fn find() -> Option<Self> {
+        Self::new(
+            fs::read_dir(LEDS_CLASS_DIR)
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .contains("kbd_backlight")
+                })
+                .map(|entry| entry.path()),
+        )
+    }
+
+    /// Directory of the first LED, for logging.
+    pub fn dir(&self) -> &Path {
+        &self.zones[0].dir
+    }
+
+    /// Directories of every LED, which are written to when setting the color.
+    pub fn dirs(&self) -> impl Iterator<Item = &Path> {
+        self.zones.iter().map(|zone| zone.dir.as_path())
+    }
+
+    /// Turn the backlight off.
+    pub fn set_off(&self) -> io::Result<()> {
+        for zone in &self.zones {
+            fs::write(zone.dir.join("brightness"), "0")?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the backlight to a color. Keyboards that only support a single
+    /// color use the brightest channel as the brightness.
+    pub fn set_color(&self, r: u8, g: u8, b: u8) -> io::Result<()> {
+        for zone in &self.zones {
+            let brightness = if zone.multicolor {
+                fs::write(zone.dir.join("multi_intensity"), format!("{r} {g} {b}"))?;
+                zone.max_brightness
+            } else {
+                (zone.max_brightness * r.max(g).max(b) as u32).div_ceil(u8::MAX as u32)
+            };
+
+            fs::write(zone.dir.join("brightness"), brightness.to_string())?;
+        }
+
+        Ok(())
+    }
+}
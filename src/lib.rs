@@ -0,0 +1,128 @@
+//! Safe bindings to the ioctl interface of the TUXEDO drivers, as used by the
+//! CoolerControl plugin.
+//!
+//! [`TuxedoIo`](tuxedo_io::TuxedoIo) controls the fans, power limits and
+//! performance profiles of Uniwill laptops such as the InfinityBook Gen10
+//! through `/dev/tuxedo_io`, and can be used without the rest of the plugin:
+//!
+//! ```no_run
+//! use tuxedo_infinitybook_gen10_cc_plugin::tuxedo_io::{DEFAULT_DEVICE_PATH, Fan, TuxedoIo};
+//!
+//! let tuxedo_io = TuxedoIo::open(DEFAULT_DEVICE_PATH.as_ref())?;
+//! println!("Fan 1 is at {}%", tuxedo_io.get_fan_speed(Fan::Fan1)?);
+//! tuxedo_io.set_fan_speed(Fan::Fan1, 60.0)?;
+//! tuxedo_io.set_fans_auto()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The raw ioctl requests are available in [`sys::ioctl`] for anything not
+//! covered, such as the Clevo interface.
+//!
+//! With the `mock` feature, the rest of the plugin's service is built into the
+//! library as well, so that the benchmarks can use it. It is not meant to be
+//! used by anything else, and is tested as part of the binary instead.
+
+pub mod sys;
+pub mod tuxedo_io;
+
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod activity;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod backend;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod backlight;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod battery;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod clevo;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod client;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod curve;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod faults;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod filter;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod firmware;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod health;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod hwmon;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod keyboard;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod limiter;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod log_limit;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod metrics;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod mock;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod modprobe;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod queue;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod ramp;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod service;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod simulated;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod stats;
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod uds;
+
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub const SERVICE_ID: &str = "tuxedo-infinitybook-gen10";
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod models {
+    pub mod v1 {
+        tonic::include_proto!("coolercontrol.models.v1");
+    }
+}
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod device_service {
+    pub mod v1 {
+        tonic::include_proto!("coolercontrol.device_service.v1");
+    }
+}
+#[cfg(all(feature = "mock", not(test)))]
+#[doc(hidden)]
+pub mod tuxedo {
+    pub mod v1 {
+        tonic::include_proto!("tuxedo.v1");
+    }
+}
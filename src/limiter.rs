@@ -0,0 +1,94 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Enforces a minimum interval between writes to a single fan.
+///
+/// Writes that arrive too soon after the previous one are not queued. Instead
+/// only the most recently requested duty is remembered, and it is applied once
+/// the interval has elapsed.
+pub struct WriteLimiter {
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    interval: Duration,
+    last_write: Option<Instant>,
+    pending: Option<f64>,
+}
+
+/// What to do with a requested write.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    /// The write may be applied right away.
+    Now,
+    /// The write was deferred, and should be flushed after the given delay.
+    Schedule(Duration),
+    /// The write replaced a deferred value that is already scheduled to be
+    /// flushed.
+    Coalesced,
+}
+
+impl WriteLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                interval,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Change the minimum interval, for writes from now on.
+    pub fn set_interval(&self, interval: Duration) {
+        self.state.lock().unwrap().interval = interval;
+    }
+
+    /// Decide whether a write of the given duty can be applied now.
+    pub fn admit(&self, duty: f64) -> Admission {
+        let mut state = self.state.lock().unwrap();
+        if state.interval.is_zero() {
+            return Admission::Now;
+        }
+
+        let now = Instant::now();
+        let interval = state.interval;
+
+        match state.last_write {
+            Some(last) if now < last + interval => {
+                if state.pending.replace(duty).is_some() {
+                    Admission::Coalesced
+                } else {
+                    Admission::Schedule(last + interval - now)
+                }
+            }
+            _ => {
+                // Anything still pending is older than this write, so it must
+                // not be applied after it.
+                state.pending = None;
+                state.last_write = Some(now);
+                Admission::Now
+            }
+        }
+    }
+
+    /// The duty deferred until the interval has elapsed, if any.
+    pub fn pending(&self) -> Option<f64> {
+        self.state.lock().unwrap().pending
+    }
+
+    /// Take the deferred duty to be flushed, if it hasn't been superseded.
+    pub fn take_pending(&self) -> Option<f64> {
+        let mut state = self.state.lock().unwrap();
+        let duty = state.pending.take()?;
+        state.last_write = Some(Instant::now());
+        Some(duty)
+    }
+
+    /// Discard any deferred write without applying it.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().pending = None;
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result, bail};
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    process,
+};
+
+/// Held for as long as the plugin runs, so that a second copy can't open the
+/// driver and fight over the EC with it.
+///
+/// The lock is a `flock` on a file that also holds the pid of its owner. The
+/// kernel releases it however the process ends, so a file left behind by a
+/// crash never keeps the plugin from starting.
+pub struct InstanceLock {
+    _file: Flock<File>,
+}
+
+impl InstanceLock {
+    /// Take the lock on the given file, creating it if needed, or fail if
+    /// another instance holds it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))?;
+
+        let file = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(file) => file,
+            Err((_, Errno::EWOULDBLOCK)) => {
+                match fs::read_to_string(path)
+                    .ok()
+                    .and_then(|pid| pid.trim().parse::<u32>().ok())
+                {
+                    Some(pid) => bail!(
+                        "Another instance (pid {pid}) is already running, as it holds the lock on {}",
+                        path.display()
+                    ),
+                    None => bail!(
+                        "Another instance is already running, as it holds the lock on {}",
+                        path.display()
+                    ),
+                }
+            }
+            Err((_, errno)) => {
+                return Err(errno).with_context(|| format!("failed to lock {}", path.display()));
+            }
+        };
+
+        // Only the owner of the lock writes to the file, so nobody reads a
+        // half-written pid.
+        file.set_len(0)
+            .and_then(|()| writeln!(&*file, "{}", process::id()))
+            .with_context(|| format!("failed to write pid to {}", path.display()))?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests;
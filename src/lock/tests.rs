@@ -0,0 +1,22 @@
+use super::*;
+use std::env;
+
+#[test]
+fn only_one_instance_holds_the_lock() {
+    let path = env::temp_dir().join(format!("tuxedo-lock-test-{}.pid", process::id()));
+    let _ = fs::remove_file(&path);
+
+    let lock = InstanceLock::acquire(&path).unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!("{}\n", process::id())
+    );
+
+    let error = InstanceLock::acquire(&path).err().unwrap().to_string();
+    assert!(error.contains(&format!("pid {}", process::id())), "{error}");
+
+    // The lock goes with its holder, while the file stays behind.
+    drop(lock);
+    InstanceLock::acquire(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+/// How often a failure that keeps repeating is logged again, with the number of
+/// times it happened in between.
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(300);
+
+static FAILURES: LazyLock<RepeatedFailures> =
+    LazyLock::new(|| RepeatedFailures::new(SUMMARY_INTERVAL));
+
+/// Record a failure of the operation described by `what`, such as `Setting
+/// fan1`, and decide whether to log it. Only the first failure is logged right
+/// away, and then a summary at most every few minutes, so that a missing
+/// driver does not flood the journal with the same error every second.
+pub fn failure(what: &str) -> Option<Repeats> {
+    FAILURES.failure(what, Instant::now())
+}
+
+/// Record that the operation described by `what` worked, logging that it
+/// recovered if it had been failing.
+pub fn success(what: &str) {
+    if let Some(failures) = FAILURES.success(what) {
+        info!("{what} works again after failing {failures} times");
+    }
+}
+
+/// Failures that were not logged since the last time one was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Repeats {
+    pub suppressed: u64,
+    pub period: Duration,
+}
+
+impl fmt::Display for Repeats {
+    /// Describe the failures left out, to be appended to the logged one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.suppressed {
+            0 => Ok(()),
+            1 => write!(f, " (and once more in the last {}s)", self.period.as_secs()),
+            n => write!(
+                f,
+                " (and {n} more times in the last {}s)",
+                self.period.as_secs()
+            ),
+        }
+    }
+}
+
+struct RepeatedFailures {
+    interval: Duration,
+    failing: Mutex<HashMap<String, Failing>>,
+}
+
+struct Failing {
+    failures: u64,
+    suppressed: u64,
+    logged_at: Instant,
+}
+
+impl RepeatedFailures {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            failing: Mutex::default(),
+        }
+    }
+
+    fn failure(&self, what: &str, now: Instant) -> Option<Repeats> {
+        let mut failing = self.failing.lock().unwrap();
+
+        let Some(entry) = failing.get_mut(what) else {
+            failing.insert(
+                what.to_owned(),
+                Failing {
+                    failures: 1,
+                    suppressed: 0,
+                    logged_at: now,
+                },
+            );
+            return Some(Repeats {
+                suppressed: 0,
+                period: Duration::ZERO,
+            });
+        };
+
+        entry.failures += 1;
+        let period = now.duration_since(entry.logged_at);
+
+        if period < self.interval {
+            entry.suppressed += 1;
+            return None;
+        }
+
+        let repeats = Repeats {
+            suppressed: entry.suppressed,
+            period,
+        };
+        entry.suppressed = 0;
+        entry.logged_at = now;
+
+        Some(repeats)
+    }
+
+    /// Forget about an operation that works again, returning how many times in
+    /// a row it failed if it ever did.
+    fn success(&self, what: &str) -> Option<u64> {
+        let mut failing = self.failing.lock().unwrap();
+
+        failing.remove(what).map(|entry| entry.failures)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,65 @@
+use super::*;
+
+#[test]
+fn repeated_failures_are_summarized() {
+    let failures = RepeatedFailures::new(Duration::from_secs(300));
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    assert_eq!(
+        failures.failure("Setting fan1", at(0)),
+        Some(Repeats {
+            suppressed: 0,
+            period: Duration::ZERO,
+        })
+    );
+    for second in 1..240 {
+        assert_eq!(failures.failure("Setting fan1", at(second)), None);
+    }
+    assert_eq!(
+        failures.failure("Setting fan1", at(300)),
+        Some(Repeats {
+            suppressed: 239,
+            period: Duration::from_secs(300),
+        })
+    );
+
+    // Other operations are limited separately.
+    assert!(failures.failure("Setting fan2", at(301)).is_some());
+    assert_eq!(failures.failure("Setting fan1", at(301)), None);
+}
+
+#[test]
+fn success_resets_failures() {
+    let failures = RepeatedFailures::new(Duration::from_secs(300));
+    let now = Instant::now();
+
+    assert_eq!(failures.success("Setting fan1"), None);
+
+    failures.failure("Setting fan1", now);
+    failures.failure("Setting fan1", now);
+    assert_eq!(failures.success("Setting fan1"), Some(2));
+
+    assert_eq!(
+        failures.failure("Setting fan1", now),
+        Some(Repeats {
+            suppressed: 0,
+            period: Duration::ZERO,
+        })
+    );
+}
+
+#[test]
+fn repeats_describe_suppressed_failures() {
+    let repeats = |suppressed| Repeats {
+        suppressed,
+        period: Duration::from_secs(300),
+    };
+
+    assert_eq!(repeats(0).to_string(), "");
+    assert_eq!(repeats(1).to_string(), " (and once more in the last 300s)");
+    assert_eq!(
+        repeats(239).to_string(),
+        " (and 239 more times in the last 300s)"
+    );
+}
@@ -0,0 +1,108 @@
+use crate::{LogFormat, VERSION};
+use anyhow::Result;
+use std::{
+    env,
+    io::{self, IsTerminal},
+    os::fd::AsFd,
+    str::FromStr,
+};
+use tracing::warn;
+use tracing_subscriber::{EnvFilter, filter::LevelFilter, fmt, prelude::*};
+
+/// Environment variable through which CoolerControl passes its log level.
+pub const ENV_CC_LOG: &str = "CC_LOG";
+
+/// Log to the journal when running under systemd, or to standard error in the
+/// given format otherwise.
+///
+/// The CoolerControl daemon passes its current log level in `CC_LOG`. If it is
+/// not set the level is info, or debug with `debug`.
+pub fn setup(debug: bool, format: LogFormat) -> Result<()> {
+    let cc_log = env::var(ENV_CC_LOG).ok();
+    let cc_log_filter = cc_log.as_deref().map(parse_log_filter);
+    let level = if debug {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+
+    let builder = EnvFilter::builder().with_default_directive(LevelFilter::INFO.into());
+    let directives = match &cc_log_filter {
+        Some(Some(directives)) => directives.as_str(),
+        _ => "",
+    };
+    let mut filter = builder.parse(directives)?;
+    if debug {
+        filter = filter.add_directive(LevelFilter::DEBUG.into());
+    }
+
+    let registry = tracing_subscriber::registry().with(filter);
+    if connected_to_journal() {
+        let journal = tracing_journald::layer()?
+            .with_field_prefix(None)
+            .with_custom_fields([("VERSION", VERSION)]);
+        registry.with(journal).try_init()?;
+    } else {
+        let output = fmt::layer()
+            .with_writer(io::stderr)
+            .with_ansi(io::stderr().is_terminal());
+        match format {
+            LogFormat::Plain => registry.with(output).try_init()?,
+            LogFormat::Json => registry
+                .with(output.json().flatten_event(true).with_current_span(false))
+                .try_init()?,
+        }
+    }
+
+    // Only now that logging is set up can we complain about the level.
+    if let (Some(value), Some(None)) = (&cc_log, cc_log_filter) {
+        warn!("Ignoring unrecognized {ENV_CC_LOG} value {value:?}, using log level {level}");
+    }
+    Ok(())
+}
+
+/// Whether standard error is connected to the journal, as systemd tells its
+/// services in `JOURNAL_STREAM`.
+fn connected_to_journal() -> bool {
+    let Ok(stream) = env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Ok(stat) = nix::sys::stat::fstat(io::stderr().as_fd()) else {
+        return false;
+    };
+
+    stream == format!("{}:{}", stat.st_dev, stat.st_ino)
+}
+
+/// Parse the log filter given in `CC_LOG`, which is either a log level or
+/// filter directives per target such as `info,tuxedo_io=trace`.
+pub fn parse_log_filter(value: &str) -> Option<String> {
+    match parse_log_level(value) {
+        Some(level) => Some(level.to_string()),
+        None => EnvFilter::builder()
+            .parse(value)
+            .is_ok()
+            .then(|| value.to_owned()),
+    }
+}
+
+/// Parse a log level, accepting the names understood by `tracing` in any case
+/// as well as a few common aliases and numeric levels.
+pub fn parse_log_level(value: &str) -> Option<LevelFilter> {
+    let value = value.trim();
+    if let Ok(level) = LevelFilter::from_str(value) {
+        return Some(level);
+    }
+    match value.to_ascii_lowercase().as_str() {
+        "0" | "none" => Some(LevelFilter::OFF),
+        "1" | "err" | "critical" => Some(LevelFilter::ERROR),
+        "2" | "warning" => Some(LevelFilter::WARN),
+        "3" => Some(LevelFilter::INFO),
+        "4" => Some(LevelFilter::DEBUG),
+        "5" => Some(LevelFilter::TRACE),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
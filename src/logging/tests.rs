@@ -0,0 +1,42 @@
+use super::*;
+
+#[test]
+fn levels_parse_in_any_case() {
+    assert_eq!(parse_log_level("info"), Some(LevelFilter::INFO));
+    assert_eq!(parse_log_level("WARN"), Some(LevelFilter::WARN));
+    assert_eq!(parse_log_level(" Debug "), Some(LevelFilter::DEBUG));
+    assert_eq!(parse_log_level("off"), Some(LevelFilter::OFF));
+}
+
+#[test]
+fn level_aliases_and_numbers_parse() {
+    assert_eq!(parse_log_level("none"), Some(LevelFilter::OFF));
+    assert_eq!(parse_log_level("critical"), Some(LevelFilter::ERROR));
+    assert_eq!(parse_log_level("err"), Some(LevelFilter::ERROR));
+    assert_eq!(parse_log_level("warning"), Some(LevelFilter::WARN));
+    assert_eq!(parse_log_level("0"), Some(LevelFilter::OFF));
+    assert_eq!(parse_log_level("3"), Some(LevelFilter::INFO));
+    assert_eq!(parse_log_level("5"), Some(LevelFilter::TRACE));
+    assert_eq!(parse_log_level("6"), None);
+    assert_eq!(parse_log_level("verbose"), None);
+}
+
+#[test]
+fn levels_become_filters() {
+    assert_eq!(parse_log_filter("WARNING").as_deref(), Some("warn"));
+    assert_eq!(parse_log_filter("4").as_deref(), Some("debug"));
+}
+
+#[test]
+fn directives_per_target_are_kept() {
+    for directives in ["info,tuxedo_io=trace", "warn,rpc=debug,telemetry=debug"] {
+        assert_eq!(parse_log_filter(directives).as_deref(), Some(directives));
+    }
+}
+
+#[test]
+fn invalid_filters_are_rejected() {
+    for value in ["info,rpc=loud", "tuxedo_io=5x", "[unclosed"] {
+        assert_eq!(parse_log_filter(value), None, "{value:?} was accepted");
+    }
+}
@@ -0,0 +1,47 @@
+use crate::service::TuxedoService;
+use tonic::codegen::tokio_stream::StreamExt;
+use tracing::{info, warn};
+use zbus::{Connection, proxy};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Reinitialize the device whenever the system resumes from sleep, as reported
+/// by logind.
+///
+/// CoolerControl also calls `initialize_device` after resuming, but not
+/// necessarily right away, and the fans run at firmware defaults until then.
+///
+/// This only returns if logind cannot be reached, so it should be raced
+/// against shutdown.
+pub async fn watch_resume(service: &TuxedoService) -> zbus::Result<()> {
+    let connection = Connection::system().await?;
+    let manager = ManagerProxy::new(&connection).await?;
+    let mut signals = manager.receive_prepare_for_sleep().await?;
+
+    while let Some(signal) = signals.next().await {
+        // The signal is sent with `true` before sleeping and `false` after
+        // resuming.
+        if signal.args()?.start {
+            continue;
+        }
+
+        info!("Resumed from sleep, reinitializing device");
+
+        if let Err(e) = service.reinitialize().await {
+            warn!(
+                "Failed to reinitialize device after resume: {}",
+                e.message()
+            );
+        }
+    }
+
+    Ok(())
+}
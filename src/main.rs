@@ -1,25 +1,118 @@
+mod access;
+mod activity;
+mod backend;
+mod backlight;
+mod battery;
+mod clevo;
+mod client;
+mod config;
+mod curve;
+mod faults;
+mod filter;
+mod firmware;
+mod health;
+mod hwmon;
+mod keyboard;
+mod limiter;
+mod lock;
+mod log_limit;
+mod logging;
+mod logind;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod modprobe;
+mod privileges;
+mod probe;
+mod queue;
+mod ramp;
+mod rpc_log;
+mod sandbox;
+mod sd_listen;
+mod sd_notify;
+mod self_test;
 mod service;
-mod sys;
-mod tuxedo_io;
+mod simulated;
+mod stats;
+mod status_stream;
+mod uds;
+mod vsock;
+
+// The driver bindings live in the library, and are imported at the root so
+// that the modules of the plugin can refer to them through `crate`.
+use tuxedo_infinitybook_gen10_cc_plugin::{sys, tuxedo_io};
 
 use crate::{
-    device_service::v1::device_service_server::DeviceServiceServer, service::TuxedoService,
+    access::AccessPolicy,
+    backlight::DisplayBacklight,
+    battery::ChargeLimit,
+    config::{Config, DEFAULT_CONFIG_PATH, RuntimeFlavor},
+    device_service::v1::device_service_server::DeviceServiceServer,
+    faults::{FaultSpec, Faults, FaultyBackend},
+    firmware::FirmwareInfo,
+    hwmon::Hwmon,
+    keyboard::KeyboardBacklight,
+    lock::InstanceLock,
+    metrics::{MetricsAddress, MetricsListener, RpcStats},
+    rpc_log::RpcLogLayer,
+    service::{ServiceConfig, TuxedoService},
+    simulated::SimulatedBackend,
+    status_stream::StatusStreamService,
+    tuxedo::v1::status_stream_server::StatusStreamServer,
+    tuxedo_io::DEFAULT_DEVICE_PATH,
+    vsock::VsockListener,
+};
+use anyhow::{Context, Result, bail};
+use clap::{Parser, ValueEnum};
+use nix::unistd::{Group, Uid};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io,
+    net::SocketAddr,
+    panic,
+    path::{Path, PathBuf},
+    pin::pin,
+    sync::{Arc, Weak},
+    time::Duration,
 };
-use anyhow::Result;
-use clap::Parser;
-use log::{LevelFilter, error, info};
-use std::str::FromStr;
-use systemd_journal_logger::{JournalLog, connected_to_journal};
 use tokio::{
-    net::UnixListener,
-    signal::{self, unix::SignalKind},
+    net::{TcpListener, UnixListener},
+    runtime,
+    signal::{
+        self,
+        unix::{Signal, SignalKind},
+    },
+    time::{Instant, timeout_at},
 };
 use tokio_util::sync::CancellationToken;
-use tonic::{codegen::tokio_stream::wrappers::UnixListenerStream, transport::Server};
+use tonic::{
+    body::Body,
+    codegen::{
+        http,
+        tokio_stream::{
+            StreamExt,
+            wrappers::{TcpListenerStream, UnixListenerStream},
+        },
+    },
+    transport::Server,
+};
+use tower::ServiceBuilder;
+use tracing::{error, info, warn};
 
 pub const SERVICE_ID: &str = "tuxedo-infinitybook-gen10";
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
-const ENV_CC_LOG: &str = "CC_LOG";
+
+/// Seconds to wait for the driver to appear when starting, unless configured
+/// otherwise.
+const DEFAULT_DEVICE_WAIT_SECS: u64 = 30;
+
+/// Milliseconds to wait for a driver call, unless configured otherwise.
+const DEFAULT_IO_TIMEOUT_MS: u64 = 5000;
+
+/// Seconds to let calls in progress finish when stopping, unless configured
+/// otherwise.
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
 
 pub mod models {
     pub mod v1 {
@@ -31,30 +124,593 @@ pub mod device_service {
         tonic::include_proto!("coolercontrol.device_service.v1");
     }
 }
+pub mod tuxedo {
+    pub mod v1 {
+        tonic::include_proto!("tuxedo.v1");
+    }
+}
+
+/// Descriptors of the protocol, for gRPC reflection.
+#[cfg(feature = "reflection")]
+const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("device_service_descriptor");
 
 /// A CoolerControl Device Service Plugin
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[clap(version, about, long_about = None)]
 struct Args {
     /// Enable debug logging
     #[clap(short, long)]
     debug: bool,
+
+    /// Read settings from this file instead of
+    /// /etc/tuxedo-infinitybook-gen10-cc-plugin/config.toml. Options given on
+    /// the command line override the file
+    #[clap(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Maximum time in milliseconds to wait for a driver call to complete,
+    /// before giving up on it [default: 5000]
+    #[clap(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..))]
+    io_timeout_ms: Option<u64>,
+
+    /// How long in milliseconds to reuse a status read from the driver for,
+    /// when CoolerControl polls faster than that
+    #[clap(long, default_value_t = 500)]
+    status_cache_ms: u64,
+
+    /// Never run the fans slower than this percentage
+    #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    fan_floor: Option<u8>,
+
+    /// Run the fans at full speed whenever a temperature reaches this many
+    /// degrees Celsius, from 50 to 110, whatever they were set to
+    #[clap(long, value_name = "CELSIUS", value_parser = clap::value_parser!(u8).range(50..=110))]
+    critical_temp: Option<u8>,
+
+    /// Raw fan speed value the EC treats as full speed, if your firmware
+    /// differs from the default
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..))]
+    max_fan_raw: Option<i32>,
+
+    /// Speed of the fans at 100% in RPM, to estimate the RPM from the duty
+    /// when it can't be read from hwmon
+    #[clap(long, value_name = "RPM", value_parser = clap::value_parser!(u32).range(1..))]
+    max_rpm: Option<u32>,
+
+    /// Print the detected hardware capabilities and exit
+    #[clap(long)]
+    probe: bool,
+
+    /// Make every driver call the plugin uses, print whether each one worked
+    /// and exit
+    #[clap(long)]
+    self_test: bool,
+
+    /// Also write the values read back unchanged during the self test, then
+    /// hand the fans back to the firmware
+    #[clap(long, requires = "self_test")]
+    self_test_writes: bool,
+
+    /// Leave power limits and the performance profile as they are when
+    /// stopping, instead of restoring them
+    #[clap(long)]
+    keep_power_settings: bool,
+
+    /// Drop root privileges to this user once the driver is open and the
+    /// socket is bound. Fan control through hwmon, the keyboard and display
+    /// backlights and the battery charge limit stop working, as they need
+    /// root for every change
+    #[clap(long, value_name = "NAME")]
+    user: Option<String>,
+
+    /// Don't restrict the service with the sandbox enabled in the config
+    /// file, for debugging
+    #[clap(long)]
+    no_sandbox: bool,
+
+    /// Simulate a device instead of using the real hardware, for development
+    #[clap(long)]
+    mock: bool,
+
+    /// Make a call to the simulated device fail, such as `set_fan_speed=EIO*3`,
+    /// `read_all_status=hang:6000` or `get_fan_speed=garbage`. Can be given
+    /// more than once
+    #[clap(long, value_name = "CALL=FAULT[*TIMES]", requires = "mock")]
+    mock_fault: Vec<FaultSpec>,
+
+    /// Minimum time in milliseconds between speed changes of the same fan
+    /// [default: 0]
+    #[clap(long, value_name = "MS")]
+    min_write_interval_ms: Option<u64>,
+
+    /// How many seconds to wait for the driver to appear when starting, in
+    /// case it has not been loaded yet [default: 30]
+    #[clap(long, value_name = "SECS")]
+    device_wait: Option<u64>,
+
+    /// Don't try to load the tuxedo_io kernel module when its device is
+    /// missing
+    #[clap(long)]
+    no_modprobe: bool,
+
+    /// Change fan speeds by at most this many percent per second, moving them
+    /// gradually to the requested speed [default: no limit]
+    #[clap(long, value_name = "PERCENT", value_parser = config::parse_ramp_rate)]
+    ramp_rate: Option<f64>,
+
+    /// Path of the TUXEDO driver's ioctl device [default: /dev/tuxedo_io]
+    #[clap(long, value_name = "PATH")]
+    device_path: Option<PathBuf>,
+
+    /// Read fan RPM from this hwmon directory instead of detecting it
+    #[clap(long, value_name = "DIR")]
+    hwmon: Option<PathBuf>,
+
+    /// Path of the Unix socket to serve on, instead of the one in the bundled
+    /// plugin manifest
+    #[clap(long, value_name = "PATH")]
+    socket_path: Option<String>,
+
+    /// File to lock while running, so that only one instance drives the
+    /// hardware at a time [default: /run/tuxedo-infinitybook-gen10.pid as
+    /// root, otherwise in $XDG_RUNTIME_DIR or /tmp]
+    #[clap(long, value_name = "PATH")]
+    lock_file: Option<PathBuf>,
+
+    /// Also serve on the given TCP address, for a CoolerControl daemon in a
+    /// container or on another host. This is unauthenticated, so only use it
+    /// on a trusted network!
+    #[clap(long, value_name = "ADDR")]
+    listen_tcp: Option<SocketAddr>,
+
+    /// Also serve virtual machines on the given vsock port, for a
+    /// CoolerControl daemon in a guest. This is unauthenticated, so any guest
+    /// can control the fans!
+    #[clap(long, value_name = "PORT")]
+    listen_vsock: Option<u32>,
+
+    /// Serve Prometheus metrics over HTTP on the given TCP address or Unix
+    /// socket path. Anyone who can reach it can read the fan speeds and
+    /// temperatures
+    #[clap(long, value_name = "ADDR")]
+    metrics_listen: Option<MetricsAddress>,
+
+    /// Check that the driver is reachable every this many seconds, so that
+    /// problems are reported even while idle
+    #[clap(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    self_check_interval: Option<u64>,
+
+    /// Log a snapshot of the fans, temperatures and power limits every this
+    /// many seconds, at debug level with the telemetry target
+    #[clap(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    telemetry_interval: Option<u64>,
+
+    /// Return the fans to firmware control if CoolerControl sends no requests
+    /// for this many seconds
+    #[clap(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    watchdog_timeout: Option<u64>,
+
+    /// How many seconds to let calls in progress finish when stopping, before
+    /// returning the fans to firmware control regardless [default: 10]
+    #[clap(long, value_name = "SECS")]
+    shutdown_timeout: Option<u64>,
+
+    /// Serve requests from one thread, or from a worker thread per CPU
+    /// [default: current-thread]
+    #[clap(long, value_enum)]
+    runtime: Option<RuntimeFlavor>,
+
+    /// Format of log output when not running under systemd
+    #[clap(long, value_enum, default_value_t = LogFormat::Plain)]
+    log_format: LogFormat,
+
+    #[clap(subcommand)]
+    command: Option<client::Command>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Plain,
+    /// One JSON object per line, for log aggregators
+    Json,
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.into());
+    let (config, config_found) = match Config::load(&config_path)? {
+        Some(config) => (config, true),
+        None if args.config.is_some() => {
+            bail!("config file {} does not exist", config_path.display())
+        }
+        None => (Config::default(), false),
+    };
+
+    // Taken before the runtime starts any threads, as it changes the
+    // environment.
+    let activated = sd_listen::take_listener()
+        .context("failed to use the socket passed by the service manager")?;
+
+    let mut runtime = match args.runtime.or(config.startup.runtime).unwrap_or_default() {
+        RuntimeFlavor::CurrentThread => runtime::Builder::new_current_thread(),
+        RuntimeFlavor::MultiThread => runtime::Builder::new_multi_thread(),
+    };
+    runtime
+        .enable_all()
+        .build()
+        .context("failed to start the async runtime")?
+        .block_on(run(args, config, config_path, config_found, activated))
+}
+
+async fn run(
+    args: Args,
+    config: Config,
+    config_path: PathBuf,
+    config_found: bool,
+    activated: Option<std::os::unix::net::UnixListener>,
+) -> Result<()> {
+    let device_path = args
+        .device_path
+        .clone()
+        .or(config.startup.device_path.clone())
+        .unwrap_or_else(|| DEFAULT_DEVICE_PATH.into());
+    let max_fan_raw = args.max_fan_raw.or(config.limits.max_fan_raw);
+
+    if args.probe {
+        return probe::probe(&device_path, max_fan_raw);
+    }
+    if args.self_test {
+        return self_test::self_test(&device_path, max_fan_raw, args.self_test_writes);
+    }
+    if let Some(command) = args.command.clone() {
+        let uds_path = args
+            .socket_path
+            .clone()
+            .or(config.socket_path.clone())
+            .unwrap_or_else(default_socket_path);
+        return client::run(command, &uds_path).await;
+    }
+
     let run_token = setup_termination_signals();
-    setup_logging()?;
+    // Installed before anything slow, as SIGHUP would otherwise kill us.
+    let hangup =
+        signal::unix::signal(SignalKind::hangup()).context("failed to install SIGHUP handler")?;
+    logging::setup(args.debug, args.log_format)?;
     info!("Starting {SERVICE_ID} v{VERSION}");
 
-    let service = TuxedoService::new();
+    if config_found {
+        info!("Loaded configuration from {}", config_path.display());
+    }
 
     // The default socket path for device services requires privileged access. Using the following
     // will work for both privileged and non-privileged services.
     // Make sure it's also correct in the manifest.toml
-    let uds_path = format!("/tmp/{SERVICE_ID}.sock");
-    cleanup_uds(&uds_path).await;
-    let uds = match UnixListener::bind(&uds_path) {
+    let activated = activated
+        .map(UnixListener::from_std)
+        .transpose()
+        .context("failed to use the socket passed by the service manager")?;
+    let socket_activated = activated.is_some();
+
+    let uds_path = match (
+        &activated,
+        args.socket_path.clone().or(config.socket_path.clone()),
+    ) {
+        (Some(listener), _) => {
+            let path = uds::describe(&listener.local_addr()?.into());
+            info!("Using socket {path} passed by the service manager");
+            path
+        }
+        (None, Some(path)) => {
+            // The installed manifest is expected to have been changed to match.
+            info!("Binding to socket {path}, make sure the plugin manifest's address matches");
+            path
+        }
+        (None, None) => {
+            let path = default_socket_path();
+            let manifest_address = manifest_address();
+            if manifest_address != Some(path.as_str()) {
+                warn!(
+                    "Binding to socket {path}, but manifest.toml declares address {manifest_address:?}. \
+                    CoolerControl will not be able to connect to this plugin!"
+                );
+            }
+            path
+        }
+    };
+
+    // Check for a running instance before touching the hardware, as it would
+    // otherwise have its fans reset when we bail out. The lock catches one
+    // serving on another socket, and is held until we exit. A simulated device
+    // can run next to the real one.
+    let _instance_lock = if args.mock {
+        None
+    } else {
+        let lock_file = args
+            .lock_file
+            .clone()
+            .or(config.lock_file.clone())
+            .unwrap_or_else(default_lock_file);
+        Some(InstanceLock::acquire(&lock_file)?)
+    };
+    // The service manager owns a socket that it passed to us.
+    if !socket_activated {
+        cleanup_stale_uds(&uds_path).await?;
+    }
+
+    let max_rpm = args.max_rpm.or(config.limits.max_rpm);
+
+    // A simulated device must never touch the real sensors or LEDs.
+    let (hwmon, keyboard, charge_limit, display) = if args.mock {
+        (None, None, None, None)
+    } else {
+        let hwmon = match &args.hwmon {
+            Some(dir) => Some(Hwmon::new(dir)),
+            None => Hwmon::find(),
+        };
+        match &hwmon {
+            Some(hwmon) => info!("Reading fan RPM from {}", hwmon.dir().display()),
+            None if max_rpm.is_some() => {
+                info!("No hwmon device found, fan RPM will be estimated from the duty")
+            }
+            None => info!("No hwmon device found, fan RPM will not be reported"),
+        }
+
+        let keyboard = KeyboardBacklight::find();
+        match &keyboard {
+            Some(keyboard) => info!("Found keyboard backlight at {}", keyboard.dir().display()),
+            None => info!("No keyboard backlight found, lighting will not be available"),
+        }
+
+        let charge_limit = ChargeLimit::find();
+        match &charge_limit {
+            Some(charge_limit) => {
+                info!(
+                    "Found battery charge limit at {}",
+                    charge_limit.path().display()
+                )
+            }
+            None => info!("No battery charge limit found, it will not be available"),
+        }
+
+        let display = DisplayBacklight::find();
+        match &display {
+            Some(backlight) => info!("Found display backlight at {}", backlight.dir().display()),
+            None => info!("No display backlight found, brightness will not be available"),
+        }
+
+        (hwmon, keyboard, charge_limit, display)
+    };
+
+    // The sysfs attributes the service writes to, which are all the sandbox
+    // allows writing. Fan control through hwmon uses the device it finds
+    // itself, rather than one given with --hwmon.
+    let mut sysfs_paths = hwmon
+        .iter()
+        .map(Hwmon::dir)
+        .chain(keyboard.iter().flat_map(KeyboardBacklight::dirs))
+        .chain(charge_limit.iter().map(ChargeLimit::path))
+        .chain(display.iter().map(DisplayBacklight::dir))
+        .map(Path::to_path_buf)
+        .collect::<Vec<_>>();
+    if args.hwmon.is_some() && !args.mock {
+        sysfs_paths.extend(Hwmon::find().map(|hwmon| hwmon.dir().to_path_buf()));
+    }
+
+    let firmware = if args.mock {
+        FirmwareInfo::default()
+    } else {
+        FirmwareInfo::read()
+    };
+    if let Some(versions) = firmware.versions() {
+        info!("Firmware and driver: {versions}");
+    }
+
+    let user = args.user.clone().or(config.startup.user.clone());
+    let fan_floor = args.fan_floor.or(config.limits.fan_floor);
+    let service_config = ServiceConfig {
+        device_path: device_path.clone(),
+        io_timeout: Duration::from_millis(
+            args.io_timeout_ms
+                .or(config.startup.io_timeout_ms)
+                .unwrap_or(DEFAULT_IO_TIMEOUT_MS),
+        ),
+        min_write_interval: min_write_interval(&args, &config),
+        ramp_rate: ramp_rate(&args, &config),
+        status_filters: config.smoothing.filter_settings(),
+        fan_floor,
+        channel_floors: config.limits.min_duty_floor.clone(),
+        labels: config.labels,
+        hwmon,
+        keyboard,
+        charge_limit,
+        display,
+        uid_info: (!args.mock).then(service::machine_uid).flatten(),
+        firmware,
+        max_fan_raw,
+        max_rpm,
+        status_ttl: Duration::from_millis(args.status_cache_ms),
+        restore_power_settings: !(args.keep_power_settings || config.startup.keep_power_settings),
+        keep_driver_open: user.is_some(),
+        driver_module: (!args.no_modprobe && config.startup.modprobe.unwrap_or(true))
+            .then(|| modprobe::DRIVER_MODULE.into()),
+    };
+    let service = Arc::new(if args.mock {
+        warn!("Using a simulated device instead of the real hardware");
+
+        let faults = Faults::default();
+        for spec in &args.mock_fault {
+            warn!("Injecting fault {spec}");
+            faults.inject(*spec);
+        }
+
+        TuxedoService::with_backend(service_config, move || {
+            Ok(Box::new(FaultyBackend::new(
+                Box::new(SimulatedBackend::new()),
+                faults.clone(),
+            )))
+        })
+    } else {
+        TuxedoService::new(service_config)
+    });
+    install_panic_hook(Arc::downgrade(&service));
+
+    // Open the device now rather than on the first request, so that we only
+    // report being ready once it is usable.
+    let device_wait = args
+        .device_wait
+        .or(config.startup.device_wait)
+        .unwrap_or(DEFAULT_DEVICE_WAIT_SECS);
+    service
+        .open_waiting(Duration::from_secs(device_wait))
+        .await
+        .context("failed to open the fan driver")?;
+
+    check_fan_floors(&service, fan_floor, &config.limits.min_duty_floor).await?;
+
+    if let Some(enabled) = config.startup.mode_enable {
+        service
+            .set_mode_enable(enabled)
+            .await
+            .context("failed to set mode enable")?;
+    }
+
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+        let args = args.clone();
+        let config_path = config_path.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(reload_on_hangup(&service, &args, &config_path, hangup))
+                .await;
+        });
+    }
+
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token.run_until_cancelled(service.run_profiles()).await;
+        });
+    }
+
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_device_recovery())
+                .await;
+        });
+    }
+
+    // There is no real hardware to be reset by sleeping when simulated.
+    if !args.mock {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            let result = run_token
+                .run_until_cancelled(logind::watch_resume(&service))
+                .await;
+
+            if let Some(Err(e)) = result {
+                warn!("Not watching for resume from sleep: {e}");
+            }
+        });
+    }
+
+    if let Some(critical) = args.critical_temp.or(config.limits.critical_temp) {
+        info!("Running fans at full speed from {critical}°C");
+
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_thermal_failsafe(critical))
+                .await;
+        });
+    }
+
+    if let Some(secs) = args
+        .self_check_interval
+        .or(config.startup.self_check_interval)
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_self_checks(Duration::from_secs(secs)))
+                .await;
+        });
+    }
+
+    if let Some(secs) = args
+        .telemetry_interval
+        .or(config.startup.telemetry_interval)
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_telemetry(Duration::from_secs(secs)))
+                .await;
+        });
+    }
+
+    let watchdog_timeout = args.watchdog_timeout.or(config.startup.watchdog_timeout);
+    if watchdog_timeout.is_some() && config.fallback.is_some() {
+        bail!("A watchdog timeout and a fallback curve cannot both be used");
+    }
+
+    if let Some(secs) = watchdog_timeout {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_watchdog(Duration::from_secs(secs)))
+                .await;
+        });
+    }
+
+    if let Some(fallback) = &config.fallback {
+        let service = service.clone();
+        let run_token = run_token.clone();
+        let period = Duration::from_secs(fallback.timeout);
+        let curve = fallback.curve()?;
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_fallback(period, curve))
+                .await;
+        });
+    }
+
+    let socket_group = config
+        .access
+        .socket_group
+        .as_deref()
+        .map(|name| {
+            Group::from_name(name)
+                .with_context(|| format!("failed to look up group {name}"))?
+                .with_context(|| format!("group {name} does not exist"))
+                .map(|group| group.gid.as_raw())
+        })
+        .transpose()?;
+
+    let uds = match activated.map_or_else(|| uds::bind(&uds_path), Ok) {
         Ok(listener) => listener,
         Err(err) => {
             error!(
@@ -64,38 +720,302 @@ async fn main() -> Result<()> {
             return Err(err.into());
         }
     };
-    let uds_stream = UnixListenerStream::new(uds);
-    Server::builder()
-        .add_service(DeviceServiceServer::new(service))
-        .serve_with_incoming_shutdown(uds_stream, run_token.cancelled())
-        .await?;
-    cleanup_uds(&uds_path).await;
+    // The service manager sets up the sockets it passes us itself.
+    let socket_mode = config.access.socket_mode;
+    if !socket_activated && (socket_mode.is_some() || socket_group.is_some()) {
+        match uds::file(&uds_path) {
+            Some(file) => uds::set_permissions(file, socket_mode, socket_group)
+                .with_context(|| format!("failed to set permissions of socket {uds_path}"))?,
+            None => warn!("Ignoring socket_mode and socket_group for abstract socket {uds_path}"),
+        }
+    }
+    // Bound before dropping privileges, in case the address needs them.
+    let rpc_stats = Arc::new(RpcStats::default());
+    let metrics_address = match args.metrics_listen.clone() {
+        Some(address) => Some(address),
+        None => config.metrics.address()?,
+    };
+    let metrics_listener = match metrics_address {
+        Some(address) => {
+            let listener = MetricsListener::bind(&address)
+                .await
+                .with_context(|| format!("failed to listen for metrics on {address}"))?;
+            info!("Serving metrics on {address}");
+            Some(listener)
+        }
+        None => None,
+    };
 
-    Ok(())
+    let tcp_listener = match args.listen_tcp.or(config.listen_tcp) {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("failed to listen on TCP address {addr}"))?;
+            warn!(
+                "Listening on TCP address {addr} without any authentication. Anyone who can \
+                reach this address can control the fans!"
+            );
+            Some(listener)
+        }
+        None => None,
+    };
+
+    let vsock_listener = match args.listen_vsock.or(config.listen_vsock) {
+        Some(port) => {
+            let listener = VsockListener::bind(port)
+                .with_context(|| format!("failed to listen on vsock port {port}"))?;
+            warn!(
+                "Listening on vsock port {port} without any authentication. Any virtual \
+                machine on this host can control the fans!"
+            );
+            Some(listener)
+        }
+        None => None,
+    };
+
+    if let Some(user) = &user {
+        privileges::drop_to(user)?;
+    }
+    if config.startup.sandbox && !args.no_sandbox {
+        let socket_path = uds::file(&uds_path).filter(|_| !socket_activated);
+        sandbox::apply(&device_path, &config_path, socket_path, sysfs_paths)?;
+    }
+    sd_notify::ready();
+
+    let mut allowed_gids = config.access.gids;
+    allowed_gids.extend(socket_group);
+    let access = AccessPolicy::new(config.access.uids, allowed_gids);
+    let uds_stream = UnixListenerStream::new(uds).filter(move |conn| match conn {
+        Ok(stream) => access.allows_connection(stream),
+        Err(_) => true,
+    });
+    let rpc_layer = ServiceBuilder::new()
+        .layer(RpcLogLayer)
+        .map_request({
+            let rpc_stats = rpc_stats.clone();
+
+            move |request: http::Request<Body>| {
+                rpc_stats.record_call(request.uri().path());
+                request
+            }
+        })
+        .map_response({
+            let rpc_stats = rpc_stats.clone();
+
+            move |response: http::Response<Body>| {
+                // Failed calls carry their status in the headers, successful
+                // ones only in the trailers.
+                if let Some(code) = response.headers().get("grpc-status") {
+                    if code != "0" {
+                        rpc_stats.record_error(code.to_str().unwrap_or("invalid"));
+                    }
+                }
+                response
+            }
+        });
+
+    let (health_reporter, health_server) = tonic_health::server::health_reporter();
+    {
+        let service = service.clone();
+        let run_token = run_token.clone();
+
+        tokio::spawn(async move {
+            run_token
+                .run_until_cancelled(service.run_health_reporter(health_reporter))
+                .await;
+        });
+    }
+
+    #[cfg(feature = "reflection")]
+    let reflection_server = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(FILE_DESCRIPTOR_SET)
+        .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        .build_v1()
+        .context("failed to set up gRPC reflection")?;
+
+    let status_stream_server =
+        StatusStreamServer::new(StatusStreamService::new(service.clone(), run_token.clone()));
+
+    // Every listener serves the same services.
+    let router = || {
+        let router = Server::builder()
+            .layer(rpc_layer.clone())
+            .add_service(health_server.clone())
+            .add_service(DeviceServiceServer::from_arc(service.clone()))
+            .add_service(status_stream_server.clone());
+        #[cfg(feature = "reflection")]
+        let router = router.add_service(reflection_server.clone());
+        router
+    };
+
+    let uds_server = router().serve_with_incoming_shutdown(uds_stream, run_token.cancelled());
+    let tcp_server = tcp_listener.map(|listener| {
+        router()
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), run_token.cancelled())
+    });
+    let vsock_server = vsock_listener
+        .map(|listener| router().serve_with_incoming_shutdown(listener, run_token.cancelled()));
+
+    let metrics_server = metrics_listener.map(|listener| async {
+        run_token
+            .run_until_cancelled(metrics::serve(listener, service.clone(), rpc_stats.clone()))
+            .await;
+        Ok::<_, Infallible>(())
+    });
+
+    let shutdown_timeout = Duration::from_secs(
+        args.shutdown_timeout
+            .or(config.startup.shutdown_timeout)
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+    );
+
+    // Every listener is served until shutdown, or until one of them fails,
+    // which then shuts the others down as well.
+    let mut serve = pin!(async {
+        let (uds_result, tcp_result, vsock_result, metrics_result) = tokio::join!(
+            serve_until_failure(Some(uds_server), &run_token),
+            serve_until_failure(tcp_server, &run_token),
+            serve_until_failure(vsock_server, &run_token),
+            serve_until_failure(metrics_server, &run_token),
+        );
+        uds_result
+            .and(tcp_result)
+            .and(vsock_result)
+            .and(metrics_result)
+    });
+    let result = tokio::select! {
+        result = &mut serve => Some(result),
+        () = run_token.cancelled() => None,
+    };
+
+    // Once stopping, the calls being served and the driver calls they made
+    // share the time left to finish.
+    sd_notify::stopping();
+    let deadline = Instant::now() + shutdown_timeout;
+    let result = match result {
+        Some(result) => result,
+        None => timeout_at(deadline, serve).await.unwrap_or_else(|_| {
+            warn!("Calls still in progress after {shutdown_timeout:?}, stopping anyway");
+            Ok(())
+        }),
+    };
+    if !service.drain(deadline).await {
+        warn!("Driver calls still in progress after {shutdown_timeout:?}, stopping anyway");
+    }
+
+    // Whatever the reason for stopping, never leave the fans pinned at a manual
+    // speed.
+    match service.release_fans().await {
+        Ok(()) => info!("Returned fan control to firmware"),
+        Err(e) => warn!("Failed to return fan control to firmware: {e}"),
+    }
+    info!(
+        "Driver access totals over {}s: {}",
+        service.uptime().as_secs(),
+        service.io_stats()
+    );
+    if !socket_activated {
+        cleanup_uds(&uds_path).await;
+    }
+
+    result
 }
 
-/// The CoolerControl daemon will pass the current daemon's log level as an environment variable.
-/// If it is not set, it will default to Info.
-fn setup_logging() -> Result<()> {
-    let args: Args = Args::parse();
-    let log_level = if args.debug {
-        LevelFilter::Debug
-    } else if let Ok(log_lvl) = std::env::var(ENV_CC_LOG) {
-        LevelFilter::from_str(&log_lvl).unwrap_or(LevelFilter::Info)
-    } else {
-        LevelFilter::Info
+/// Run a server for a listener that may not have been configured, cancelling
+/// the token if it fails so that the plugin doesn't keep running with only
+/// some of its listeners.
+async fn serve_until_failure<E: Into<anyhow::Error>>(
+    server: Option<impl Future<Output = Result<(), E>>>,
+    run_token: &CancellationToken,
+) -> Result<()> {
+    let Some(server) = server else {
+        return Ok(());
     };
-    if connected_to_journal() {
-        JournalLog::new()?
-            .with_extra_fields(vec![("VERSION", VERSION)])
-            .install()?;
-        log::set_max_level(log_level);
-    } else {
-        env_logger::Builder::new().filter_level(log_level).init();
+
+    server.await.map_err(Into::into).inspect_err(|e| {
+        error!("Stopping, as a listener failed: {e}");
+        run_token.cancel();
+    })
+}
+
+/// Minimum time between speed changes of the same fan, from the command line
+/// or else the config file.
+fn min_write_interval(args: &Args, config: &Config) -> Duration {
+    let millis = args
+        .min_write_interval_ms
+        .or(config.smoothing.min_write_interval_ms);
+
+    Duration::from_millis(millis.unwrap_or(0))
+}
+
+/// Largest change of a fan's speed in percent per second, from the command
+/// line or else the config file.
+fn ramp_rate(args: &Args, config: &Config) -> f64 {
+    args.ramp_rate.or(config.smoothing.ramp_rate).unwrap_or(0.0)
+}
+
+/// Check that the fans are able to run as slowly as the given floors.
+async fn check_fan_floors(
+    service: &TuxedoService,
+    fan_floor: Option<u8>,
+    channel_floors: &HashMap<String, u8>,
+) -> Result<()> {
+    let Some(fan_floor) = fan_floor
+        .into_iter()
+        .chain(channel_floors.values().copied())
+        .min()
+    else {
+        return Ok(());
+    };
+    let min_speed = service
+        .hardware_min_speed()
+        .await
+        .context("failed to read the hardware minimum fan speed to validate the fan floor")?;
+
+    if fan_floor < min_speed {
+        bail!("fan floor of {fan_floor}% is below the hardware minimum fan speed of {min_speed}%");
     }
     Ok(())
 }
 
+/// Read the config file again whenever SIGHUP is received, and apply the
+/// settings that can be changed without restarting.
+///
+/// A file that fails to load or validate is logged and otherwise ignored, so
+/// that a typo does not take the fans out of CoolerControl's hands.
+async fn reload_on_hangup(service: &TuxedoService, args: &Args, path: &Path, mut hangup: Signal) {
+    while hangup.recv().await.is_some() {
+        let config = match Config::load(path) {
+            Ok(config) => config.unwrap_or_default(),
+            Err(e) => {
+                warn!("Not reloading configuration: {e:#}");
+                continue;
+            }
+        };
+
+        let fan_floor = args.fan_floor.or(config.limits.fan_floor);
+        let channel_floors = &config.limits.min_duty_floor;
+        if let Err(e) = check_fan_floors(service, fan_floor, channel_floors).await {
+            warn!("Not reloading configuration: {e:#}");
+            continue;
+        }
+
+        service.reconfigure(
+            fan_floor,
+            channel_floors,
+            min_write_interval(args, &config),
+            ramp_rate(args, &config),
+            config.smoothing.filter_settings(),
+            config.labels,
+        );
+        info!(
+            "Reloaded configuration from {}. Settings other than the labels, fan floors and \
+            smoothing only change on restart",
+            path.display()
+        );
+    }
+}
+
 /// Sets up signal handlers for termination and interrupt signals,
 /// and returns a `CancellationToken` that is triggered when any of
 /// those signals are received, allowing the caller to handle the
@@ -144,10 +1064,73 @@ fn setup_termination_signals() -> CancellationToken {
     run_token
 }
 
+/// Return the fans to firmware control if the plugin panics, so they are not
+/// left pinned at a manual speed if the process dies before shutting down.
+fn install_panic_hook(service: Weak<TuxedoService>) {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        if let Some(service) = service.upgrade() {
+            service.release_fans_now();
+        }
+    }));
+}
+
+/// Get the socket address declared in the plugin manifest that CoolerControl
+/// will connect to.
+fn manifest_address() -> Option<&'static str> {
+    include_str!("../manifest.toml").lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        (key.trim() == "address").then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Path of the socket in the bundled plugin manifest.
+fn default_socket_path() -> String {
+    format!("/tmp/{SERVICE_ID}.sock")
+}
+
+/// Path of the lock file. Only root can write to /run, so like the socket, an
+/// unprivileged instance uses a directory of its own or /tmp instead.
+fn default_lock_file() -> PathBuf {
+    let dir = if Uid::effective().is_root() {
+        "/run".into()
+    } else {
+        std::env::var_os("XDG_RUNTIME_DIR").map_or_else(|| "/tmp".into(), PathBuf::from)
+    };
+
+    dir.join(format!("{SERVICE_ID}.pid"))
+}
+
+/// Cleanup a UDS file left behind by an unclean shutdown.
+///
+/// If another instance is still accepting connections on the socket then the file is left alone,
+/// so that two services never end up fighting over the same device.
+async fn cleanup_stale_uds(uds_path: &str) -> Result<()> {
+    match uds::remove_stale(uds_path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            bail!("Another instance is already listening on {uds_path}, refusing to start")
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            bail!("{uds_path} exists but is not a socket, refusing to replace it")
+        }
+        Err(e) => Err(e).with_context(|| {
+            format!(
+                "failed to check for another instance listening on {uds_path}, refusing to start"
+            )
+        }),
+    }
+}
+
 /// Cleanup the UDS file if it exists
 ///
 /// If a system goes down unexpectedly, an existing file can block a service restart
 /// from binding to it again.
 async fn cleanup_uds(uds_path: &str) {
-    let _ = tokio::fs::remove_file(uds_path).await;
+    if let Some(file) = uds::file(uds_path) {
+        let _ = tokio::fs::remove_file(file).await;
+    }
 }
@@ -0,0 +1,204 @@
+use crate::service::TuxedoService;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Write as _},
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UnixListener},
+    spawn,
+};
+use tracing::{debug, warn};
+
+/// Longest request head that will be read before giving up on a client.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Where to serve metrics from.
+#[derive(Debug, Clone)]
+pub enum MetricsAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl Display for MetricsAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            Self::Unix(path) => path.display().fmt(f),
+        }
+    }
+}
+
+impl FromStr for MetricsAddress {
+    type Err = String;
+
+    /// Parse either a TCP address, or an absolute path to a Unix socket.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('/') {
+            return Ok(Self::Unix(s.into()));
+        }
+
+        s.parse()
+            .map(Self::Tcp)
+            .map_err(|_| format!("{s:?} is neither a TCP address nor an absolute socket path"))
+    }
+}
+
+/// Listener for metrics scrapes.
+pub enum MetricsListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl MetricsListener {
+    pub async fn bind(address: &MetricsAddress) -> io::Result<Self> {
+        match address {
+            MetricsAddress::Tcp(addr) => TcpListener::bind(addr).await.map(Self::Tcp),
+            MetricsAddress::Unix(path) => {
+                // Unlike the plugin socket, nothing but scrapers connects here,
+                // so a leftover socket can be replaced without checking.
+                let _ = std::fs::remove_file(path);
+                UnixListener::bind(path).map(Self::Unix)
+            }
+        }
+    }
+}
+
+/// Counts of gRPC requests, by method and by status.
+#[derive(Default)]
+pub struct RpcStats {
+    calls: Mutex<BTreeMap<String, u64>>,
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+impl RpcStats {
+    /// Count a call to the method at the given request path.
+    pub fn record_call(&self, path: &str) {
+        let method = path.rsplit('/').next().unwrap_or(path);
+        *self.calls.lock().unwrap().entry(method.into()).or_default() += 1;
+    }
+
+    /// Count a call that failed with the given gRPC status code.
+    pub fn record_error(&self, code: &str) {
+        *self.errors.lock().unwrap().entry(code.into()).or_default() += 1;
+    }
+
+    fn write(&self, metrics: &mut Exposition) {
+        metrics.family("tuxedo_rpc_calls_total", "counter", "gRPC calls received.");
+        for (method, count) in self.calls.lock().unwrap().iter() {
+            metrics.sample("tuxedo_rpc_calls_total", &[("method", method)], count);
+        }
+
+        metrics.family(
+            "tuxedo_rpc_errors_total",
+            "counter",
+            "gRPC calls that failed, by status code.",
+        );
+        for (code, count) in self.errors.lock().unwrap().iter() {
+            metrics.sample("tuxedo_rpc_errors_total", &[("code", code)], count);
+        }
+    }
+}
+
+/// Metrics in the Prometheus text exposition format.
+#[derive(Default)]
+pub struct Exposition(String);
+
+impl Exposition {
+    /// Start a metric family. Its samples must follow before the next one.
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.0.push_str(name);
+
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(key, value)| format!("{key}={value:?}"))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(self.0, "{{{labels}}}");
+        }
+
+        let _ = writeln!(self.0, " {value}");
+    }
+}
+
+impl Display for Exposition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Serve metrics over plain HTTP until cancelled.
+pub async fn serve(listener: MetricsListener, service: Arc<TuxedoService>, rpc: Arc<RpcStats>) {
+    loop {
+        let service = service.clone();
+        let rpc = rpc.clone();
+
+        let result = match &listener {
+            MetricsListener::Tcp(listener) => listener.accept().await.map(|(stream, _)| {
+                spawn(async move { handle(stream, &service, &rpc).await });
+            }),
+            MetricsListener::Unix(listener) => listener.accept().await.map(|(stream, _)| {
+                spawn(async move { handle(stream, &service, &rpc).await });
+            }),
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to accept metrics connection: {e}");
+        }
+    }
+}
+
+async fn handle(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin,
+    service: &TuxedoService,
+    rpc: &RpcStats,
+) {
+    if let Err(e) = respond(&mut stream, service, rpc).await {
+        debug!("Failed to serve metrics: {e}");
+    }
+}
+
+async fn respond(
+    stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    service: &TuxedoService,
+    rpc: &RpcStats,
+) -> io::Result<()> {
+    let mut request = Vec::new();
+
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_SIZE || stream.read_buf(&mut request).await? == 0 {
+            return Ok(());
+        }
+    }
+
+    let (status, body) = if request.starts_with(b"GET /metrics ") {
+        let mut metrics = Exposition::default();
+        service.write_metrics(&mut metrics).await;
+        rpc.write(&mut metrics);
+
+        ("200 OK", metrics.to_string())
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+        Content-Type: text/plain; version=0.0.4\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
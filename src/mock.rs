@@ -0,0 +1,199 @@
+//! A fan backend for the tests and benchmarks of the service, which records
+//! what the service asked of it instead of talking to the driver.
+
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    faults::{Faults, FaultyBackend},
+    service::{ServiceConfig, TuxedoService},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp},
+};
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Fan backend that records what the service asked of it.
+#[derive(Clone, Default)]
+pub struct MockBackend(Arc<Mutex<MockState>>);
+
+/// What the service asked of a [`MockBackend`], and what the backend reports.
+#[derive(Default)]
+pub struct MockState {
+    /// Lowest fan speed the firmware allows, as a percentage.
+    pub min_speed: u8,
+    /// Allowed range of each TDP rail, or `None` if it is not supported.
+    pub tdp_bounds: [Option<(u32, u32)>; 3],
+    /// The power limit set for each TDP rail.
+    pub tdp: [u32; 3],
+    /// The raw performance mode.
+    pub mode: i32,
+    /// Whether the firmware controls the performance mode, or `None` if that is
+    /// not supported.
+    pub mode_enable: Option<bool>,
+    /// The speed of each fan, as a percentage.
+    pub speeds: [f64; 2],
+    /// The temperature reported for each fan.
+    pub temperatures: [u8; 2],
+    /// Whether the fans may be turned off completely.
+    pub fans_off_available: bool,
+    /// Whether performance profiles are not supported.
+    pub no_performance_profiles: bool,
+    /// How many times the fans were set back to automatic control.
+    pub auto_calls: usize,
+    /// How many times the service opened the backend.
+    pub opens: usize,
+    /// An error for every driver call to fail with.
+    pub errno: Option<i32>,
+    /// How long every fan speed write takes.
+    pub write_delay: Duration,
+}
+
+impl MockBackend {
+    /// Get the state of the backend, to change or inspect.
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.0.lock().unwrap()
+    }
+
+    fn maybe_fail(&self) -> io::Result<()> {
+        match self.state().errno {
+            Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+            None => Ok(()),
+        }
+    }
+
+    /// Create a service using the backend.
+    pub fn service(&self) -> TuxedoService {
+        self.service_with(ServiceConfig::default())
+    }
+
+    /// Create a service using the backend with the given configuration.
+    pub fn service_with(&self, config: ServiceConfig) -> TuxedoService {
+        let backend = self.clone();
+
+        TuxedoService::with_backend(config, move || {
+            backend.state().opens += 1;
+            Ok(Box::new(backend.clone()))
+        })
+    }
+
+    /// Create a service whose calls fail as given in `faults`.
+    pub fn faulty_service(&self, config: ServiceConfig, faults: &Faults) -> TuxedoService {
+        let backend = self.clone();
+        let faults = faults.clone();
+
+        TuxedoService::with_backend(config, move || {
+            backend.state().opens += 1;
+            Ok(Box::new(FaultyBackend::new(
+                Box::new(backend.clone()),
+                faults.clone(),
+            )))
+        })
+    }
+}
+
+impl FanBackend for MockBackend {
+    fn model(&self) -> String {
+        "Mock".into()
+    }
+
+    fn check(&self) -> io::Result<()> {
+        self.maybe_fail()
+    }
+
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        self.state().tdp_bounds[tdp as usize]
+    }
+
+    fn fans_off_available(&self) -> bool {
+        self.state().fans_off_available
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        !self.state().no_performance_profiles
+    }
+
+    fn get_mode(&self) -> io::Result<i32> {
+        self.maybe_fail()?;
+        Ok(self.state().mode)
+    }
+
+    fn get_mode_enable(&self) -> io::Result<bool> {
+        self.maybe_fail()?;
+        self.state()
+            .mode_enable
+            .ok_or_else(|| io::ErrorKind::Unsupported.into())
+    }
+
+    fn set_mode_enable(&self, enabled: bool) -> io::Result<()> {
+        self.maybe_fail()?;
+        let mut state = self.state();
+        if state.mode_enable.is_none() {
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+        state.mode_enable = Some(enabled);
+        Ok(())
+    }
+
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> io::Result<()> {
+        if self.state().no_performance_profiles {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        }
+        self.maybe_fail()?;
+        self.state().mode = profile as i32;
+        Ok(())
+    }
+
+    fn get_tdp(&self, tdp: Tdp) -> io::Result<u32> {
+        self.maybe_fail()?;
+        Ok(self.state().tdp[tdp as usize])
+    }
+
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> io::Result<()> {
+        self.maybe_fail()?;
+        self.state().tdp[tdp as usize] = watts;
+        Ok(())
+    }
+
+    fn get_fan_min_speed(&self) -> io::Result<u8> {
+        self.maybe_fail()?;
+        Ok(self.state().min_speed)
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> io::Result<f64> {
+        self.maybe_fail()?;
+        Ok(self.state().speeds[fan as usize])
+    }
+
+    fn read_all_status(&self) -> io::Result<FanStatusSnapshot> {
+        self.maybe_fail()?;
+        let state = self.state();
+        Ok(FanStatusSnapshot {
+            speeds: state.speeds,
+            temperatures: state.temperatures,
+            mode_enable: state.mode_enable,
+            fans_off_available: state.fans_off_available,
+            tdp: Tdp::ALL
+                .map(|tdp| state.tdp_bounds[tdp as usize].map(|_| state.tdp[tdp as usize])),
+            ..Default::default()
+        })
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> io::Result<i32> {
+        self.maybe_fail()?;
+        let write_delay = self.state().write_delay;
+        std::thread::sleep(write_delay);
+        self.state().speeds[fan as usize] = percentage;
+        Ok(percentage.round() as i32)
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        percentage.round() as i32
+    }
+
+    fn set_fans_auto(&self) -> io::Result<()> {
+        self.maybe_fail()?;
+        self.state().auto_calls += 1;
+        Ok(())
+    }
+}
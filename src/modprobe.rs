@@ -0,0 +1,21 @@
+use std::{io, process::Command};
+use tracing::info;
+
+/// Kernel module that provides the TUXEDO driver's ioctl device.
+pub const DRIVER_MODULE: &str = "tuxedo_io";
+
+/// Load a kernel module with `modprobe`, for when its device node is missing.
+pub fn load(module: &str) -> io::Result<()> {
+    info!("Loading kernel module {module}");
+
+    let output = Command::new("modprobe").arg(module).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    Err(io::Error::other(format!(
+        "modprobe {module} failed ({}): {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
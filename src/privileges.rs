@@ -0,0 +1,28 @@
+use anyhow::{Context, Result, bail};
+use nix::unistd::{Uid, User, setgid, setgroups, setuid};
+use tracing::info;
+
+/// Switch to running as the given user and their primary group, for the rest
+/// of the life of the process.
+///
+/// Anything that needs root, such as opening the driver or binding the
+/// socket, must have been done before.
+pub fn drop_to(name: &str) -> Result<()> {
+    let user = User::from_name(name)
+        .with_context(|| format!("failed to look up user {name}"))?
+        .with_context(|| format!("user {name} does not exist"))?;
+
+    setgroups(&[user.gid]).context("failed to drop supplementary groups")?;
+    setgid(user.gid).with_context(|| format!("failed to switch to group {}", user.gid))?;
+    setuid(user.uid).with_context(|| format!("failed to switch to user {name}"))?;
+
+    if !user.uid.is_root() && setuid(Uid::from_raw(0)).is_ok() {
+        bail!("still able to regain root after switching to user {name}");
+    }
+
+    info!(
+        "Dropped privileges to user {name} (uid {}, gid {})",
+        user.uid, user.gid
+    );
+    Ok(())
+}
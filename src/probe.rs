@@ -0,0 +1,95 @@
+use crate::{
+    backend::FanBackend,
+    firmware::FirmwareInfo,
+    tuxedo_io::{Fan, Tdp, TuxedoIo},
+};
+use anyhow::{Context, Result};
+use std::{fmt::Display, io, path::Path};
+
+/// Print everything the plugin can detect about the hardware to stdout.
+///
+/// This is meant as a one-shot diagnostic for bug reports, so individual
+/// queries that fail are reported inline rather than aborting the probe. Only
+/// failing to open the device at all is treated as an error.
+pub fn probe(device_path: &Path, max_fan_raw: Option<i32>) -> Result<()> {
+    let mut tuxedo_io = TuxedoIo::open(device_path)
+        .with_context(|| format!("failed to open {}", device_path.display()))?;
+
+    if let Some(max_fan_raw) = max_fan_raw {
+        tuxedo_io.set_max_fan_speed(max_fan_raw);
+    }
+
+    let firmware = FirmwareInfo::read();
+    for (label, value) in [
+        ("Product", &firmware.product),
+        ("BIOS version", &firmware.bios_version),
+        ("BIOS date", &firmware.bios_date),
+        ("EC version", &firmware.ec_version),
+        ("Driver version", &firmware.driver_version),
+    ] {
+        print_line(
+            label,
+            value
+                .as_deref()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound)),
+        );
+    }
+
+    print_line("Interface", Ok(tuxedo_io.model()));
+    print_line(
+        "Fan minimum speed",
+        tuxedo_io
+            .get_fan_min_speed()
+            .map(|speed| format!("{speed}%")),
+    );
+    print_line(
+        "Fan maximum speed",
+        Ok(format!("100% (raw {})", tuxedo_io.max_fan_speed())),
+    );
+    print_line(
+        "Fans off available",
+        tuxedo_io
+            .get_fans_off_available()
+            .map(|available| if available { "yes" } else { "no" }),
+    );
+
+    for (label, fan) in [("Fan 1", Fan::Fan1), ("Fan 2", Fan::Fan2)] {
+        print_line(
+            &format!("{label} speed"),
+            tuxedo_io
+                .get_fan_speed(fan)
+                .map(|speed| format!("{speed:.1}%")),
+        );
+        print_line(
+            &format!("{label} temperature"),
+            tuxedo_io
+                .get_fan_temperature(fan)
+                .map(|temp| format!("{temp} °C")),
+        );
+    }
+
+    print_line("Performance mode", tuxedo_io.get_mode());
+    print_line(
+        "OS controls policy",
+        tuxedo_io
+            .get_mode_enable()
+            .map(|enabled| if enabled { "yes" } else { "no" }),
+    );
+
+    for tdp in Tdp::ALL {
+        let bounds = match tuxedo_io.get_tdp_bounds(tdp) {
+            Some((min, max)) => format!("{min}-{max} W"),
+            None => "unsupported".into(),
+        };
+        print_line(&format!("{tdp:?} range"), Ok(bounds));
+    }
+
+    Ok(())
+}
+
+fn print_line(label: &str, value: io::Result<impl Display>) {
+    match value {
+        Ok(value) => println!("{:<20} {value}", format!("{label}:")),
+        Err(e) => println!("{:<20} unavailable ({e})", format!("{label}:")),
+    }
+}
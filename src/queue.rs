@@ -0,0 +1,93 @@
+use std::sync::{
+    Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+/// Speeds waiting to be written to a single fan.
+///
+/// Writing a fan speed blocks until the fan reaches it, so requests do not wait
+/// for the write. Only the most recently requested duty is kept, and it is
+/// written once the write before it has finished.
+#[derive(Default)]
+pub struct WriteQueue {
+    state: Mutex<QueueState>,
+
+    /// Incremented whenever the queue is cleared, so that a write that was
+    /// already taken from the queue can tell it has been cancelled.
+    generation: AtomicU64,
+
+    /// Raw value last written to the fan from the queue, if it is known to
+    /// still be set.
+    written: Mutex<Option<i32>>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: Option<f64>,
+
+    /// Whether a writer is currently draining the queue.
+    busy: bool,
+}
+
+impl WriteQueue {
+    /// Queue a duty to be written, replacing any that is still waiting.
+    ///
+    /// Returns `true` if the caller must start a writer to drain the queue, or
+    /// `false` if one is already running and will pick the duty up.
+    pub fn push(&self, duty: f64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.pending = Some(duty);
+
+        !std::mem::replace(&mut state.busy, true)
+    }
+
+    /// Take the next duty to write, along with the generation it was queued
+    /// in. Once this returns `None` the writer must stop.
+    pub fn pop(&self) -> Option<(f64, u64)> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.pending.take() {
+            Some(duty) => Some((duty, self.generation.load(Ordering::SeqCst))),
+            None => {
+                state.busy = false;
+                None
+            }
+        }
+    }
+
+    /// The duty waiting to be written, if any.
+    pub fn pending(&self) -> Option<f64> {
+        self.state.lock().unwrap().pending
+    }
+
+    /// Whether a duty taken from the queue in the given generation should
+    /// still be written.
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
+
+    /// Discard any waiting duty, and cancel one that is about to be written.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.pending = None;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.forget_written();
+    }
+
+    /// Whether the given raw value is the one last written to the fan, so
+    /// writing it again would change nothing.
+    pub fn is_written(&self, raw: i32) -> bool {
+        *self.written.lock().unwrap() == Some(raw)
+    }
+
+    /// Remember the raw value written to the fan, or `None` if it is unknown.
+    pub fn set_written(&self, raw: Option<i32>) {
+        *self.written.lock().unwrap() = raw;
+    }
+
+    /// Forget the raw value last written, for when the fan has been set some
+    /// other way.
+    pub fn forget_written(&self) {
+        self.set_written(None);
+    }
+}
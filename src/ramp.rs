@@ -0,0 +1,113 @@
+use std::{sync::Mutex, time::Duration};
+
+/// Moves the speed of a single fan gradually towards the requested one, as
+/// sudden jumps are audible on some ECs.
+///
+/// The ramp only keeps track of the speeds. Whoever starts it is expected to
+/// call [`Ramp::step`] periodically and write the speeds it returns.
+pub struct Ramp {
+    state: Mutex<RampState>,
+}
+
+#[derive(Default)]
+struct RampState {
+    /// Largest change in percent per second, or zero to not ramp.
+    rate: f64,
+
+    /// Speed last handed out to be written, if known.
+    current: Option<f64>,
+
+    /// Speed being ramped towards.
+    target: Option<f64>,
+
+    /// Whether something is stepping the ramp.
+    running: bool,
+}
+
+/// What to do with a requested speed.
+#[derive(Debug, PartialEq)]
+pub enum RampAction {
+    /// The speed may be written right away.
+    Now(f64),
+    /// The ramp must be stepped until it reaches the speed.
+    Start,
+    /// The ramp is already being stepped and will pick up the new speed.
+    Retargeted,
+}
+
+impl Ramp {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            state: Mutex::new(RampState {
+                rate,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Change the rate, for steps from now on.
+    pub fn set_rate(&self, rate: f64) {
+        self.state.lock().unwrap().rate = rate;
+    }
+
+    /// Decide how to get to the given speed.
+    pub fn request(&self, duty: f64) -> RampAction {
+        let mut state = self.state.lock().unwrap();
+        state.target = Some(duty);
+
+        match state.current {
+            Some(current) if state.rate > 0.0 && current != duty => {
+                if std::mem::replace(&mut state.running, true) {
+                    RampAction::Retargeted
+                } else {
+                    RampAction::Start
+                }
+            }
+            // Without a known starting point there is nothing to ramp from.
+            _ => {
+                state.current = Some(duty);
+                RampAction::Now(duty)
+            }
+        }
+    }
+
+    /// Move towards the target by as much as the rate allows in the given
+    /// time, returning the speed to write. Once this returns `None` the ramp
+    /// must no longer be stepped.
+    pub fn step(&self, elapsed: Duration) -> Option<f64> {
+        let mut state = self.state.lock().unwrap();
+
+        let (Some(current), Some(target)) = (state.current, state.target) else {
+            state.running = false;
+            return None;
+        };
+        if current == target {
+            state.running = false;
+            return None;
+        }
+
+        let max_change = state.rate * elapsed.as_secs_f64();
+        let next = if state.rate <= 0.0 || (target - current).abs() <= max_change {
+            target
+        } else {
+            current + max_change.copysign(target - current)
+        };
+        state.current = Some(next);
+
+        Some(next)
+    }
+
+    /// Whether the fan has yet to reach the requested speed.
+    pub fn is_ramping(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.running && state.current != state.target
+    }
+
+    /// Forget the speeds, for when the fan has been handed back to the
+    /// firmware. This stops any ramp in progress.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.current = None;
+        state.target = None;
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+use tonic::{body::Body, codegen::http};
+use tower::{Layer, Service};
+use tracing::{Instrument, debug, debug_span, field};
+
+/// Runs every gRPC call in an `rpc` span that records its duration and status,
+/// and logs it at debug level with the `rpc` target, so that slow calls can be
+/// found with `CC_LOG=info,rpc=debug`.
+#[derive(Clone, Copy, Default)]
+pub struct RpcLogLayer;
+
+impl<S> Layer<S> for RpcLogLayer {
+    type Service = RpcLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcLog { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcLog<S> {
+    inner: S,
+}
+
+impl<S> Service<http::Request<Body>> for RpcLog<S>
+where
+    S: Service<http::Request<Body>, Response = http::Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let path = request.uri().path();
+        let method = path.rsplit('/').next().unwrap_or(path).to_owned();
+        let span = debug_span!(
+            target: "rpc",
+            "rpc",
+            method = method.as_str(),
+            elapsed_us = field::Empty,
+            status = field::Empty,
+        );
+        let start = Instant::now();
+        let response = span.in_scope(|| self.inner.call(request));
+
+        let call = span.clone();
+        Box::pin(
+            async move {
+                let response = response.await;
+                let elapsed = start.elapsed();
+                let elapsed_us = elapsed.as_micros() as u64;
+
+                if let Ok(response) = &response {
+                    // Failed calls carry their status in the headers, successful
+                    // ones only in the trailers.
+                    let status = response
+                        .headers()
+                        .get("grpc-status")
+                        .and_then(|code| code.to_str().ok())
+                        .unwrap_or("0");
+
+                    call.record("elapsed_us", elapsed_us);
+                    call.record("status", status);
+                    debug!(
+                        target: "rpc",
+                        "{method} finished with status {status} in {elapsed:?}"
+                    );
+                }
+
+                response
+            }
+            .instrument(span),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,129 @@
+use super::*;
+use std::{
+    convert::Infallible,
+    io,
+    sync::{Arc, Mutex},
+};
+use tower::{ServiceExt, service_fn};
+use tracing::info;
+use tracing_subscriber::fmt::{MakeWriter, format::FmtSpan};
+
+/// Log output collected by a test, with a line for every event and for every
+/// span as it closes.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Output {
+    fn lines(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Output {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self {
+        self.clone()
+    }
+}
+
+/// Make a call through the layer to a service that logs while handling it and
+/// responds with the given gRPC status header, if any. Returns the log output.
+async fn call(path: &str, status: Option<&'static str>) -> Vec<String> {
+    let output = Output::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(output.clone())
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(false)
+        .without_time()
+        .finish();
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let service = RpcLogLayer.layer(service_fn(|_request: http::Request<Body>| async move {
+        info!("Handling the call");
+
+        let mut response = http::Response::new(Body::empty());
+        if let Some(status) = status {
+            response
+                .headers_mut()
+                .insert("grpc-status", http::HeaderValue::from_static(status));
+        }
+        Ok::<_, Infallible>(response)
+    }));
+    let request = http::Request::builder()
+        .uri(path)
+        .body(Body::empty())
+        .unwrap();
+    service.oneshot(request).await.unwrap();
+
+    output.lines()
+}
+
+#[tokio::test]
+async fn calls_run_in_a_span_named_after_the_method() {
+    let lines = call(
+        "/coolercontrol.device_service.v1.DeviceService/Status",
+        None,
+    )
+    .await;
+
+    assert!(
+        lines[0].contains(r#"rpc{method="Status"}: "#) && lines[0].ends_with("Handling the call"),
+        "{lines:#?}"
+    );
+}
+
+#[tokio::test]
+async fn spans_record_the_duration_and_status() {
+    let lines = call(
+        "/coolercontrol.device_service.v1.DeviceService/FixedDuty",
+        Some("3"),
+    )
+    .await;
+
+    // The event finishing the call and the span closing both carry the
+    // fields recorded on the span.
+    let fields = r#"rpc{method="FixedDuty" elapsed_us="#;
+    assert_eq!(lines.len(), 3, "{lines:#?}");
+    for line in &lines[1..] {
+        assert!(line.starts_with(&format!("DEBUG {fields}")), "{lines:#?}");
+        assert!(line.contains(r#" status="3"}: rpc: "#), "{lines:#?}");
+    }
+    assert!(
+        lines[1].contains("FixedDuty finished with status 3 in "),
+        "{lines:#?}"
+    );
+    assert!(lines[2].ends_with(": rpc: close"), "{lines:#?}");
+}
+
+#[tokio::test]
+async fn successful_calls_have_status_zero() {
+    let lines = call(
+        "/coolercontrol.device_service.v1.DeviceService/Health",
+        None,
+    )
+    .await;
+
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains(r#" status="0"}: rpc: close"#)),
+        "{lines:#?}"
+    );
+}
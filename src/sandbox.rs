@@ -0,0 +1,323 @@
+use anyhow::{Context, Result, bail};
+use landlock::{
+    ABI, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreated, RulesetCreatedAttr, RulesetError,
+    RulesetStatus, path_beneath_rules,
+};
+use nix::libc;
+use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io, mem,
+    os::fd::{AsRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// Newest Landlock ABI whose access rights we know how to grant. Older
+/// kernels enforce what they support.
+const LANDLOCK_ABI: ABI = ABI::V5;
+
+/// How long to wait for another thread to restrict itself before giving up.
+const THREAD_RESTRICT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Ruleset that other threads restrict themselves with from the signal handler.
+static RULESET_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Threads that have run the signal handler, and those that failed to restrict
+/// themselves in it.
+static HANDLED_THREADS: AtomicUsize = AtomicUsize::new(0);
+static FAILED_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// System calls made by the service once it has started, by tokio, tonic,
+/// the loggers and the driver backends.
+const SYSCALLS: &[libc::c_long] = &[
+    // Memory and threads.
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_futex,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_prctl,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_geteuid,
+    libc::SYS_tgkill,
+    // Signals.
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_restart_syscall,
+    // Time and randomness.
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_getrandom,
+    // Files, for the driver, sysfs and reloading the config.
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_unlinkat,
+    libc::SYS_memfd_create,
+    // The event loop.
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_pipe2,
+    libc::SYS_ppoll,
+    // Sockets, for serving and for talking to journald and systemd.
+    libc::SYS_socket,
+    libc::SYS_connect,
+    libc::SYS_accept4,
+    libc::SYS_shutdown,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+];
+
+struct AllowedFiles {
+    device_path: PathBuf,
+    config_path: PathBuf,
+    socket_path: Option<PathBuf>,
+    sysfs_paths: Vec<PathBuf>,
+}
+
+/// Restrict the process to what the service needs once it has started, so
+/// that a compromise of the gRPC surface can do little else.
+///
+/// Landlock limits the files that can be opened to the driver, sysfs and the
+/// config file, and only allows writing to the sysfs attributes in
+/// `sysfs_paths`, which are the directories or files of the devices the service
+/// found at startup. Seccomp limits the system calls that can be made. System
+/// calls outside of the list fail with `EPERM` rather than killing the
+/// process, so that a missed one shows up as an error instead of the fans
+/// being left without control.
+pub fn apply(
+    device_path: &Path,
+    config_path: &Path,
+    socket_path: Option<&Path>,
+    sysfs_paths: Vec<PathBuf>,
+) -> Result<()> {
+    let files = AllowedFiles {
+        device_path: device_path.into(),
+        config_path: config_path.into(),
+        socket_path: socket_path.map(Into::into),
+        sysfs_paths,
+    };
+    restrict_files(&files)?;
+    restrict_syscalls()?;
+    Ok(())
+}
+
+fn ruleset(files: &AllowedFiles) -> Result<RulesetCreated, RulesetError> {
+    let read = AccessFs::from_read(LANDLOCK_ABI);
+    let write = AccessFs::from_write(LANDLOCK_ABI);
+
+    let mut ruleset = Ruleset::default()
+        .handle_access(AccessFs::from_all(LANDLOCK_ABI))?
+        .create()?
+        .add_rules(path_beneath_rules(
+            [Path::new("/proc"), Path::new("/sys"), &files.config_path],
+            read,
+        ))?
+        .add_rules(path_beneath_rules(&files.sysfs_paths, AccessFs::WriteFile))?
+        .add_rules(path_beneath_rules([&files.device_path], read | write))?;
+
+    // Allow removing the socket when stopping.
+    if let Some(dir) = files.socket_path.as_deref().and_then(Path::parent) {
+        ruleset = ruleset.add_rules(path_beneath_rules([dir], AccessFs::RemoveFile))?;
+    }
+
+    Ok(ruleset)
+}
+
+fn restrict_files(files: &AllowedFiles) -> Result<()> {
+    let ruleset = ruleset(files).context("failed to create Landlock ruleset")?;
+    let others = ruleset
+        .try_clone()
+        .context("failed to duplicate Landlock ruleset")?;
+    let status = ruleset
+        .restrict_self()
+        .context("failed to apply Landlock ruleset")?;
+
+    // A ruleset only covers the thread that applies it and those it starts
+    // from then on, so the threads tokio has already started are made to
+    // apply it too. There is no file descriptor when Landlock is unsupported.
+    if let Some(fd) = Option::<OwnedFd>::from(others) {
+        restrict_other_threads(&fd).context("failed to apply Landlock ruleset to every thread")?;
+    }
+
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => info!("Restricted file access with Landlock"),
+        RulesetStatus::PartiallyEnforced => {
+            info!("Restricted file access with Landlock, as far as the kernel supports")
+        }
+        RulesetStatus::NotEnforced => {
+            warn!("Not restricting file access, Landlock is not supported by the kernel")
+        }
+    }
+    Ok(())
+}
+
+/// Have every other thread of the process restrict itself with the given
+/// ruleset, the way glibc applies `setuid` to every thread: each one is sent a
+/// signal in turn, whose handler applies the ruleset.
+fn restrict_other_threads(ruleset: &OwnedFd) -> Result<()> {
+    let signal = libc::SIGRTMIN();
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = restrict_thread as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+
+    RULESET_FD.store(ruleset.as_raw_fd(), Ordering::SeqCst);
+    if unsafe { libc::sigaction(signal, &action, &mut previous) } != 0 {
+        return Err(io::Error::last_os_error()).context("failed to install signal handler");
+    }
+
+    let result = signal_other_threads(signal);
+
+    unsafe {
+        libc::sigaction(signal, &previous, std::ptr::null_mut());
+    }
+    RULESET_FD.store(-1, Ordering::SeqCst);
+
+    let restricted = result?;
+    match FAILED_THREADS.load(Ordering::SeqCst) {
+        0 => {
+            debug!("Restricted {restricted} other threads with Landlock");
+            Ok(())
+        }
+        failed => bail!("{failed} threads failed to apply the ruleset"),
+    }
+}
+
+/// Signal every thread other than the calling one until none are left, as
+/// threads started by one that has not been signalled yet are not covered.
+/// Returns the number of threads that handled the signal.
+fn signal_other_threads(signal: libc::c_int) -> Result<usize> {
+    let pid = std::process::id() as libc::pid_t;
+    let mut signalled = BTreeSet::from([unsafe { libc::gettid() }]);
+
+    loop {
+        let pending = thread_ids()?
+            .into_iter()
+            .filter(|tid| !signalled.contains(tid))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return Ok(HANDLED_THREADS.load(Ordering::SeqCst));
+        }
+
+        for tid in pending {
+            signalled.insert(tid);
+            let handled = HANDLED_THREADS.load(Ordering::SeqCst);
+
+            if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) } != 0 {
+                // The thread has exited since it was listed.
+                continue;
+            }
+
+            let deadline = Instant::now() + THREAD_RESTRICT_TIMEOUT;
+            while HANDLED_THREADS.load(Ordering::SeqCst) == handled {
+                if !Path::new(&format!("/proc/self/task/{tid}")).exists() {
+                    break;
+                }
+                if Instant::now() > deadline {
+                    bail!("thread {tid} did not handle the signal in time");
+                }
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+fn thread_ids() -> Result<Vec<libc::pid_t>> {
+    Ok(fs::read_dir("/proc/self/task")
+        .context("failed to list threads")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect())
+}
+
+/// Signal handler that applies the ruleset to the thread it runs on. Only
+/// makes system calls, so that it is safe to run in between anything.
+extern "C" fn restrict_thread(_signal: libc::c_int) {
+    unsafe {
+        let errno = *libc::__errno_location();
+        let restricted = libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+            && libc::syscall(
+                libc::SYS_landlock_restrict_self,
+                RULESET_FD.load(Ordering::SeqCst),
+                0,
+            ) == 0;
+
+        if !restricted {
+            FAILED_THREADS.fetch_add(1, Ordering::SeqCst);
+        }
+        HANDLED_THREADS.fetch_add(1, Ordering::SeqCst);
+        *libc::__errno_location() = errno;
+    }
+}
+
+fn restrict_syscalls() -> Result<()> {
+    let arch = TargetArch::try_from(std::env::consts::ARCH)
+        .context("seccomp filters are not supported on this architecture")?;
+    let rules = SYSCALLS
+        .iter()
+        .map(|&syscall| (syscall, Vec::new()))
+        .collect::<BTreeMap<_, _>>();
+
+    let filter: BpfProgram = SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(libc::EPERM as u32),
+        SeccompAction::Allow,
+        arch,
+    )?
+    .try_into()?;
+
+    // Tokio has already started threads, which must be restricted too.
+    seccompiler::apply_filter_all_threads(&filter).context("failed to apply seccomp filter")?;
+    info!("Restricted system calls with seccomp");
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use nix::fcntl::{FcntlArg, FdFlag, fcntl};
+use std::{
+    env, io,
+    os::{fd::FromRawFd, unix::net::UnixListener},
+    process,
+};
+
+/// First file descriptor passed by the service manager.
+const LISTEN_FDS_START: i32 = 3;
+
+/// Variables the service manager describes the passed sockets with.
+const LISTEN_VARS: [&str; 3] = ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"];
+
+/// Take the listening socket passed by the service manager when socket
+/// activated, or `None` if we were started normally.
+///
+/// Like `sd_listen_fds` with `unset_environment` set, this removes the
+/// variables describing the sockets, and the socket is not inherited by the
+/// processes we start, such as modprobe. As it changes the environment, it
+/// must be called before the process starts any other threads.
+pub fn take_listener() -> io::Result<Option<UnixListener>> {
+    // The variables may have been inherited from a parent that was activated,
+    // in which case the sockets are not ours.
+    let for_us = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse().ok())
+        .is_some_and(|pid: u32| pid == process::id());
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+
+    for var in LISTEN_VARS {
+        // SAFETY: The process has a single thread, so nothing else can be
+        // reading the environment.
+        unsafe { env::remove_var(var) };
+    }
+
+    if !for_us {
+        return Ok(None);
+    }
+
+    match count {
+        0 => return Ok(None),
+        1 => {}
+        _ => {
+            return Err(io::Error::other(format!(
+                "expected one socket from the service manager, but got {count}"
+            )));
+        }
+    }
+
+    // SAFETY: The service manager hands the descriptor to us, and nothing
+    // else in the process knows about it.
+    let listener = unsafe { UnixListener::from_raw_fd(LISTEN_FDS_START) };
+
+    // Fails if the socket is not a Unix socket.
+    listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    fcntl(&listener, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+
+    Ok(Some(listener))
+}
@@ -0,0 +1,47 @@
+use std::{
+    env, io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+};
+use tracing::{debug, warn};
+
+/// Tell the service manager that startup has finished.
+pub fn ready() {
+    notify("READY=1");
+}
+
+/// Tell the service manager that we are shutting down.
+pub fn stopping() {
+    notify("STOPPING=1");
+}
+
+/// Send a state change to the service manager, if it asked for them by
+/// setting `NOTIFY_SOCKET`. Failures are only logged, as the service works
+/// the same either way.
+fn notify(state: &str) {
+    if let Err(e) = send(state) {
+        warn!("Failed to notify the service manager of {state}: {e}");
+    }
+}
+
+fn send(state: &str) -> io::Result<()> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+
+    // A leading @ denotes a socket in the abstract namespace.
+    let addr = match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    debug!("Notified the service manager of {state}");
+
+    Ok(())
+}
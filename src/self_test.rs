@@ -0,0 +1,113 @@
+use crate::tuxedo_io::{Fan, Tdp, TuxedoIo};
+use anyhow::{Context, Result, bail};
+use std::{fmt::Display, io, path::Path};
+
+/// Make every driver call the plugin uses and print whether each one worked.
+///
+/// Every read is made. With `writes`, the values that were read are also
+/// written back unchanged, and the fans are handed back to the firmware at the
+/// end. Unlike [`crate::probe::probe`], failures are reported through the
+/// result, so that the exit status tells whether everything passed.
+pub fn self_test(device_path: &Path, max_fan_raw: Option<i32>, writes: bool) -> Result<()> {
+    let mut tuxedo_io = TuxedoIo::open(device_path)
+        .with_context(|| format!("failed to open {}", device_path.display()))?;
+
+    if let Some(max_fan_raw) = max_fan_raw {
+        tuxedo_io.set_max_fan_speed(max_fan_raw);
+    }
+
+    let mut results = Results::default();
+
+    results.check("Hardware check", Ok(tuxedo_io.hwcheck_code()));
+    results.check("Fans off available", tuxedo_io.get_fans_off_available());
+    results.check("Fan minimum speed", tuxedo_io.get_fan_min_speed());
+
+    for (label, fan) in [("Fan 1", Fan::Fan1), ("Fan 2", Fan::Fan2)] {
+        results.check(&format!("{label} speed"), tuxedo_io.get_fan_speed(fan));
+        results.check(
+            &format!("{label} temperature"),
+            tuxedo_io.get_fan_temperature(fan),
+        );
+    }
+
+    results.check("Performance mode", tuxedo_io.get_mode());
+    let mode_enable = results.check("Mode enable", tuxedo_io.get_mode_enable());
+
+    let mut tdps = Vec::new();
+    for tdp in Tdp::ALL {
+        match tuxedo_io.get_tdp_bounds(tdp) {
+            Some((min, max)) => {
+                results.check(&format!("{tdp:?} range"), Ok(format!("{min}-{max} W")));
+                if let Some(watts) = results.check(&format!("{tdp:?}"), tuxedo_io.get_tdp(tdp)) {
+                    tdps.push((tdp, watts));
+                }
+            }
+            None => results.skip(&format!("{tdp:?}"), "unsupported"),
+        }
+    }
+
+    if writes {
+        if let Some(enabled) = mode_enable {
+            results.check(
+                "Write mode enable",
+                tuxedo_io.set_mode_enable(enabled).map(|()| enabled),
+            );
+        }
+
+        for (tdp, watts) in tdps {
+            results.check(
+                &format!("Write {tdp:?}"),
+                tuxedo_io.set_tdp(tdp, watts).map(|()| format!("{watts} W")),
+            );
+        }
+
+        for (label, fan) in [("Fan 1", Fan::Fan1), ("Fan 2", Fan::Fan2)] {
+            let result = tuxedo_io
+                .get_fan_speed(fan)
+                .and_then(|speed| tuxedo_io.set_fan_speed(fan, speed))
+                .map(|raw| format!("raw {raw}"));
+            results.check(&format!("Write {label} speed"), result);
+        }
+
+        results.check(
+            "Fans to firmware control",
+            tuxedo_io.set_fans_auto().map(|()| "ok"),
+        );
+    }
+
+    match results.failures {
+        0 => Ok(()),
+        1 => bail!("1 check failed"),
+        failures => bail!("{failures} checks failed"),
+    }
+}
+
+#[derive(Default)]
+struct Results {
+    failures: usize,
+}
+
+impl Results {
+    /// Print the outcome of a call, returning its value if it worked.
+    fn check<T: Display>(&mut self, label: &str, result: io::Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                print_row(label, "PASS", &value);
+                Some(value)
+            }
+            Err(e) => {
+                print_row(label, "FAIL", &e);
+                self.failures += 1;
+                None
+            }
+        }
+    }
+
+    fn skip(&self, label: &str, reason: &str) {
+        print_row(label, "SKIP", &reason);
+    }
+}
+
+fn print_row(label: &str, outcome: &str, detail: &dyn Display) {
+    println!("{label:<28} {outcome}  {detail}");
+}
@@ -1,71 +1,1520 @@
 use crate::{
     SERVICE_ID, VERSION,
+    activity::ActivityTracker,
+    backend::{FanBackend, FanStatusSnapshot},
+    backlight::DisplayBacklight,
+    battery::{ChargeLimit, MIN_CHARGE_LIMIT},
+    clevo::ClevoIo,
+    curve::Curve,
     device_service::v1::{
         CustomFunctionOneRequest, CustomFunctionOneResponse, EnableManualFanControlRequest,
         EnableManualFanControlResponse, FixedDutyRequest, FixedDutyResponse, HealthRequest,
         HealthResponse, InitializeDeviceRequest, InitializeDeviceResponse, LcdRequest, LcdResponse,
-        LightingRequest, LightingResponse, ListDevicesRequest, ListDevicesResponse,
-        ResetChannelRequest, ResetChannelResponse, ShutdownRequest, ShutdownResponse,
-        SpeedProfileRequest, SpeedProfileResponse, StatusRequest, StatusResponse,
-        device_service_server::DeviceService, health_response,
+        LightingRequest, LightingResponse, LightingSetting, ListDevicesRequest,
+        ListDevicesResponse, ResetChannelRequest, ResetChannelResponse, ShutdownRequest,
+        ShutdownResponse, SpeedProfileRequest, SpeedProfileResponse, StatusRequest, StatusResponse,
+        device_service_server::{DeviceService, DeviceServiceServer},
+        health_response,
     },
+    filter::{FilterSettings, StatusFilters},
+    firmware::FirmwareInfo,
+    health::HealthMonitor,
+    hwmon::{Hwmon, HwmonBackend},
+    keyboard::KeyboardBacklight,
+    limiter::{Admission, WriteLimiter},
+    log_limit,
+    metrics::Exposition,
     models::{
         self,
         v1::{
-            ChannelInfo, Device, DeviceInfo, SpeedOptions, channel_info::Options, status::FanSpeed,
+            ChannelInfo, Device, DeviceInfo, DriverInfo, LcdInfo, LightingModes, SpeedOptions,
+            TempInfo, channel_info::Options, lcd_info::LcdModes, lighting_modes::LightingMode,
+            status::FanSpeed,
         },
     },
-    tuxedo_io::{Fan, TuxedoIo},
+    modprobe::{self, DRIVER_MODULE},
+    queue::WriteQueue,
+    ramp::{Ramp, RampAction},
+    stats::{CountingBackend, IoStats, IoStatsSnapshot},
+    tuxedo_io::{DEFAULT_DEVICE_PATH, Fan, PerformanceProfile, Tdp, TuxedoIo},
+};
+use nix::libc;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
 };
-use std::{collections::HashMap, io, sync::Arc};
 use sysinfo::Product;
-use tokio::{sync::Mutex, task::spawn_blocking, time::Instant};
-use tonic::{Request, Response, Status};
+use tokio::{
+    spawn,
+    sync::{Mutex, OwnedMutexGuard},
+    task::{self, JoinHandle},
+    time::{Instant, MissedTickBehavior, interval, sleep, timeout, timeout_at},
+};
+use tonic::{Code, Request, Response, Status, metadata::MetadataValue};
+use tonic_health::server::HealthReporter;
+use tracing::{Level, Span, debug, enabled, info, warn};
+
+pub const DEVICE_ID: &str = "tuxedo";
+pub const REACHABLE_METADATA: &str = "tuxedo-device-reachable";
+pub const CONSECUTIVE_FAILURES_METADATA: &str = "tuxedo-consecutive-failures";
+pub const TOTAL_FAILURES_METADATA: &str = "tuxedo-total-failures";
+pub const LAST_ERROR_METADATA: &str = "tuxedo-last-error";
+const DEFAULT_DEVICE_NAME: &str = "TUXEDO InfinityBook Gen10";
+pub const FAN_1_CHANNEL_ID: &str = "fan1";
+pub const FAN_2_CHANNEL_ID: &str = "fan2";
+const KEYBOARD_CHANNEL_ID: &str = "keyboard";
+const CHARGE_LIMIT_CHANNEL_ID: &str = "charge_limit";
+const DISPLAY_CHANNEL_ID: &str = "display";
+const LCD_MODE_BRIGHTNESS: &str = "brightness";
+const LIGHTING_MODE_OFF: &str = "off";
+const LIGHTING_MODE_FIXED: &str = "fixed";
+const PERFORMANCE_CHANNEL_ID: &str = "performance";
+const TDP_CHANNELS: [(Tdp, &str, &str); 3] = [
+    (Tdp::Tdp0, "tdp0", "Sustained Power Limit"),
+    (Tdp::Tdp1, "tdp1", "Boost Power Limit"),
+    (Tdp::Tdp2, "tdp2", "Peak Power Limit"),
+];
+
+/// Firmware performance profiles, offered as modes of the performance
+/// channel since the protocol has no other way to pick from a list.
+const PERFORMANCE_PROFILES: [(PerformanceProfile, &str, &str); 3] = [
+    (PerformanceProfile::PowerSave, "power_save", "Power Save"),
+    (PerformanceProfile::Enthusiast, "enthusiast", "Enthusiast"),
+    (PerformanceProfile::Overboost, "overboost", "Overboost"),
+];
+
+/// Temperature sensors, identified by the fan they are associated with.
+const TEMP_CHANNELS: [(Fan, &str, &str); 2] = [
+    (Fan::Fan1, "temp_cpu", "CPU"),
+    (Fan::Fan2, "temp_gpu", "GPU"),
+];
+
+/// How often speed profiles are evaluated against the current temperatures.
+const PROFILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the standard gRPC health service is brought up to date.
+const HEALTH_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a fan being ramped is moved closer to its requested speed.
+const RAMP_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far below the critical temperature the hottest sensor has to drop
+/// before the thermal failsafe lets go of the fans, in degrees Celsius.
+const FAILSAFE_HYSTERESIS: u8 = 5;
+
+/// Descriptions of background operations whose repeated failures are logged
+/// through [`log_limit`].
+const APPLYING_PROFILES: &str = "Applying speed profiles";
+const RUNNING_FULL_SPEED: &str = "Running fans at full speed";
+
+/// Delay before trying again to open a driver that is not there yet. This is
+/// doubled after every attempt, up to [`OPEN_RETRY_MAX_DELAY`].
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay between two attempts to open the driver.
+const OPEN_RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Tunable behavior of the service.
+#[derive(Debug)]
+pub struct ServiceConfig {
+    /// Path of the TUXEDO driver's ioctl device.
+    pub device_path: PathBuf,
+
+    /// How long to wait for a driver call to return before giving up on it.
+    pub io_timeout: Duration,
+
+    /// Minimum time between two speed changes of the same fan.
+    pub min_write_interval: Duration,
+
+    /// Largest change of a fan's speed in percent per second, or zero to
+    /// change it right away.
+    pub ramp_rate: f64,
+
+    /// How the values reported to CoolerControl are filtered.
+    pub status_filters: FilterSettings,
+
+    /// Lowest fan speed percentage that will ever be applied, regardless of
+    /// what is requested.
+    pub fan_floor: Option<u8>,
+
+    /// Lowest speed percentage of individual fans, keyed by channel ID, where
+    /// it is higher than the fan floor.
+    pub channel_floors: HashMap<String, u8>,
+
+    /// Labels to show instead of the built-in ones, keyed by channel or
+    /// temperature ID.
+    pub labels: HashMap<String, String>,
+
+    /// Where to read fan RPM from, if available.
+    pub hwmon: Option<Hwmon>,
+
+    /// Keyboard backlight to control through the lighting channel, if
+    /// available.
+    pub keyboard: Option<KeyboardBacklight>,
+
+    /// Battery charge limit to control through its own channel, if available.
+    pub charge_limit: Option<ChargeLimit>,
+
+    /// Backlight of the internal display to control through the LCD channel,
+    /// if available.
+    pub display: Option<DisplayBacklight>,
+
+    /// Identifier of the machine for CoolerControl to tell devices apart, as
+    /// found by [`machine_uid`].
+    pub uid_info: Option<String>,
+
+    /// Versions of the firmware and driver, to show in CoolerControl.
+    pub firmware: FirmwareInfo,
+
+    /// Raw fan speed value that the driver treats as 100%, if it differs from
+    /// the usual one.
+    pub max_fan_raw: Option<i32>,
+
+    /// Speed of the fans at 100% in RPM, to estimate the RPM from the duty
+    /// when there is no hwmon device to read it from.
+    pub max_rpm: Option<u32>,
+
+    /// How long a status read from the driver is reused for, so that frequent
+    /// polling does not keep the EC busy.
+    pub status_ttl: Duration,
+
+    /// Whether to put back power limits and the performance profile changed
+    /// through the service when handing control back to the firmware.
+    pub restore_power_settings: bool,
+
+    /// Keep the driver open when handing the fans back to the firmware, for
+    /// when the process would not be allowed to open it again.
+    pub keep_driver_open: bool,
+
+    /// Kernel module to load if the driver's device is missing, if any.
+    pub driver_module: Option<String>,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            device_path: DEFAULT_DEVICE_PATH.into(),
+            io_timeout: Duration::from_secs(5),
+            min_write_interval: Duration::ZERO,
+            ramp_rate: 0.0,
+            status_filters: FilterSettings::default(),
+            fan_floor: None,
+            channel_floors: HashMap::new(),
+            labels: HashMap::new(),
+            hwmon: None,
+            keyboard: None,
+            charge_limit: None,
+            display: None,
+            uid_info: None,
+            firmware: FirmwareInfo::default(),
+            max_fan_raw: None,
+            max_rpm: None,
+            status_ttl: Duration::from_millis(500),
+            restore_power_settings: true,
+            keep_driver_open: false,
+            driver_module: Some(DRIVER_MODULE.into()),
+        }
+    }
+}
+
+pub struct TuxedoService {
+    start_time: Instant,
+    health: Arc<HealthMonitor>,
+    fan_floors: std::sync::Mutex<[Option<u8>; 2]>,
+    labels: std::sync::Mutex<HashMap<String, String>>,
+    hwmon: Option<Arc<Hwmon>>,
+    max_rpm: Option<u32>,
+    keyboard: Option<Arc<KeyboardBacklight>>,
+    charge_limit: Option<Arc<ChargeLimit>>,
+    display: Option<Arc<DisplayBacklight>>,
+    uid_info: Option<String>,
+    firmware: FirmwareInfo,
+    device_path: PathBuf,
+    io: IoHandle,
+    stats: Arc<IoStats>,
+    limiters: [Arc<WriteLimiter>; 2],
+    ramps: [Arc<Ramp>; 2],
+    write_queues: [Arc<WriteQueue>; 2],
+
+    /// Last speed requested for each fan, or `None` if the fan is under
+    /// firmware control.
+    commanded: std::sync::Mutex<[Option<f64>; 2]>,
+
+    /// Speed profile currently driving each fan, if any.
+    profiles: std::sync::Mutex<[Option<SpeedProfile>; 2]>,
+
+    activity: ActivityTracker,
+
+    /// Whether the thermal failsafe is running the fans at full speed, in which
+    /// case requested speeds are only remembered.
+    failsafe_tripped: AtomicBool,
+
+    restore_power_settings: bool,
+
+    keep_driver_open: bool,
+
+    status_ttl: Duration,
+
+    /// Last status read from the driver and when it was read. Callers hold
+    /// this while reading, so concurrent callers share a single read.
+    status_cache: Mutex<Option<(Instant, FanStatusSnapshot)>>,
+
+    /// Filters smoothing the values reported by the status RPC.
+    status_filters: std::sync::Mutex<StatusFilters>,
+
+    /// Power settings from before the service first changed them.
+    original_power_settings: std::sync::Mutex<PowerSettings>,
+
+    /// Power settings last applied through the service, to apply again after
+    /// the firmware resets them.
+    applied_power_settings: std::sync::Mutex<PowerSettings>,
+}
+
+/// Power limits and firmware mode of a device, where known.
+#[derive(Debug, Default, Clone)]
+struct PowerSettings {
+    tdp: [Option<u32>; 3],
+    mode: Option<i32>,
+
+    /// Whether the fan and power policy is left to the OS.
+    mode_enable: Option<bool>,
+}
+
+impl PowerSettings {
+    fn is_empty(&self) -> bool {
+        self.tdp.iter().all(Option::is_none) && self.mode.is_none() && self.mode_enable.is_none()
+    }
+}
+
+impl TuxedoService {
+    pub fn new(config: ServiceConfig) -> Self {
+        let device_path = config.device_path.clone();
+        let max_fan_raw = config.max_fan_raw;
+        let driver_module = config.driver_module.clone();
+        let module_loaded = AtomicBool::new(false);
+
+        Self::with_backend(config, move || {
+            let mut result = TuxedoIo::open(&device_path);
+
+            // Only try loading the module once, rather than every time the
+            // driver is opened.
+            if let (Err(e), Some(module)) = (&result, &driver_module) {
+                if e.kind() == io::ErrorKind::NotFound
+                    && !module_loaded.swap(true, Ordering::Relaxed)
+                {
+                    match modprobe::load(module) {
+                        Ok(()) => result = TuxedoIo::open(&device_path),
+                        Err(e) => warn!("Failed to load kernel module {module}: {e}"),
+                    }
+                }
+            }
+
+            let mut tuxedo_io = match result {
+                Ok(tuxedo_io) => tuxedo_io,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    info!(
+                        "{} not found, falling back to hwmon fan control",
+                        device_path.display()
+                    );
+                    return match HwmonBackend::open() {
+                        Ok(hwmon) => Ok(Box::new(hwmon)),
+                        Err(e) => Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "{} not found and {e}, is the {} kernel module loaded?",
+                                device_path.display(),
+                                driver_module.as_deref().unwrap_or(DRIVER_MODULE)
+                            ),
+                        )),
+                    };
+                }
+                // Not a Uniwill device, but it may still be a Clevo one.
+                Err(e) => match ClevoIo::open(&device_path) {
+                    Ok(clevo_io) => return Ok(Box::new(clevo_io)),
+                    Err(_) => return Err(e),
+                },
+            };
+
+            if let Some(max_fan_raw) = max_fan_raw {
+                tuxedo_io.set_max_fan_speed(max_fan_raw);
+            }
+
+            Ok(Box::new(tuxedo_io))
+        })
+    }
+
+    /// Create a service that opens its fan backend using the given function.
+    ///
+    /// The backend is opened lazily on first use, and again after it has been
+    /// released.
+    pub fn with_backend(
+        config: ServiceConfig,
+        open: impl Fn() -> io::Result<Box<dyn FanBackend>> + Send + Sync + 'static,
+    ) -> Self {
+        let stats = Arc::new(IoStats::default());
+        let counted_open = {
+            let stats = stats.clone();
+
+            move || -> io::Result<Box<dyn FanBackend>> {
+                Ok(Box::new(CountingBackend::new(open()?, stats.clone())))
+            }
+        };
+
+        Self {
+            start_time: Instant::now(),
+            health: Arc::default(),
+            fan_floors: std::sync::Mutex::new(fan_floors(config.fan_floor, &config.channel_floors)),
+            labels: std::sync::Mutex::new(config.labels),
+            hwmon: config.hwmon.map(Arc::new),
+            max_rpm: config.max_rpm,
+            keyboard: config.keyboard.map(Arc::new),
+            charge_limit: config.charge_limit.map(Arc::new),
+            display: config.display.map(Arc::new),
+            uid_info: config.uid_info,
+            firmware: config.firmware,
+            device_path: config.device_path,
+            io: IoHandle::new(Arc::new(counted_open), config.io_timeout, stats.clone()),
+            stats,
+            limiters: [(); 2].map(|_| Arc::new(WriteLimiter::new(config.min_write_interval))),
+            ramps: [(); 2].map(|_| Arc::new(Ramp::new(config.ramp_rate))),
+            write_queues: Default::default(),
+            commanded: Default::default(),
+            profiles: Default::default(),
+            activity: ActivityTracker::default(),
+            failsafe_tripped: AtomicBool::new(false),
+            restore_power_settings: config.restore_power_settings,
+            keep_driver_open: config.keep_driver_open,
+            status_ttl: config.status_ttl,
+            status_cache: Mutex::new(None),
+            status_filters: std::sync::Mutex::new(StatusFilters::new(config.status_filters)),
+            original_power_settings: Default::default(),
+            applied_power_settings: Default::default(),
+        }
+    }
+
+    async fn with_io<T: Send + 'static>(
+        &self,
+        f: impl Send + FnOnce(&mut Option<Arc<dyn FanBackend>>) -> Result<T, Status> + 'static,
+    ) -> Result<T, Status> {
+        self.io.with_io(f).await
+    }
+
+    /// Run a read-only blocking operation against the driver.
+    async fn with_io_initialized<T: Send + 'static>(
+        &self,
+        f: impl Send + FnOnce(&dyn FanBackend) -> Result<T, Status> + 'static,
+    ) -> Result<T, Status> {
+        self.io.with_fans(&[], f).await
+    }
+
+    /// Run a blocking operation that changes the state of the given fans.
+    async fn with_fans<T: Send + 'static>(
+        &self,
+        fans: &[Fan],
+        f: impl Send + FnOnce(&dyn FanBackend) -> Result<T, Status> + 'static,
+    ) -> Result<T, Status> {
+        self.io.with_fans(fans, f).await
+    }
+
+    /// Hand control of the fans back to the firmware and close the driver.
+    /// Unless configured otherwise, power settings changed through the service
+    /// are put back as they were.
+    pub async fn release_fans(&self) -> Result<(), Status> {
+        self.forget_fan_speeds();
+
+        let original = std::mem::take(&mut *self.original_power_settings.lock().unwrap());
+        *self.applied_power_settings.lock().unwrap() = Default::default();
+        let restore = self.restore_power_settings;
+        let keep_open = self.keep_driver_open;
+
+        self.with_io(move |slot| {
+            // Reset the fans to auto before exiting, or they may be stuck off
+            // which could cause overheating.
+            if let Some(backend) = slot.take() {
+                backend.set_fans_auto().map_err(driver_status)?;
+
+                if restore {
+                    restore_power_settings(&*backend, &original);
+                }
+
+                // Disconnect the driver handle, unless it can't be reopened.
+                if keep_open {
+                    *slot = Some(backend);
+                }
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stop writing queued fan speeds and wait until the driver calls already
+    /// made have returned, including ones whose requests gave up on them, so
+    /// that the fans are not handed back to the firmware in the middle of a
+    /// write. Returns `false` if they did not all return before the deadline.
+    pub async fn drain(&self, deadline: Instant) -> bool {
+        self.forget_fan_speeds();
+
+        timeout_at(deadline, self.io.lock_fans(&Fan::ALL))
+            .await
+            .is_ok()
+    }
+
+    /// Hand control of the fans back to the firmware without waiting, for when
+    /// the process may be about to die. This is best effort, and does nothing
+    /// if the driver handle is in use.
+    pub fn release_fans_now(&self) {
+        // This may run inside the async runtime or a panic hook, so it must not
+        // block waiting for the lock.
+        if let Ok(mut backend) = self.io.backend.try_lock() {
+            if let Some(backend) = backend.take() {
+                let _ = backend.set_fans_auto();
+            }
+        }
+    }
+
+    /// Change the settings that can be changed while running.
+    ///
+    /// The fan floors apply to speeds set from now on, and the labels are
+    /// picked up the next time CoolerControl lists the devices.
+    pub fn reconfigure(
+        &self,
+        fan_floor: Option<u8>,
+        channel_floors: &HashMap<String, u8>,
+        min_write_interval: Duration,
+        ramp_rate: f64,
+        status_filters: FilterSettings,
+        labels: HashMap<String, String>,
+    ) {
+        *self.fan_floors.lock().unwrap() = fan_floors(fan_floor, channel_floors);
+        *self.labels.lock().unwrap() = labels;
+
+        for limiter in &self.limiters {
+            limiter.set_interval(min_write_interval);
+        }
+        for ramp in &self.ramps {
+            ramp.set_rate(ramp_rate);
+        }
+        self.status_filters
+            .lock()
+            .unwrap()
+            .configure(status_filters);
+    }
+
+    fn fan_floor(&self, fan: Fan) -> Option<u8> {
+        self.fan_floors.lock().unwrap()[fan as usize]
+    }
+
+    /// Discard any remembered or deferred fan speeds, as the fans are being
+    /// returned to firmware control.
+    fn forget_fan_speeds(&self) {
+        for fan in Fan::ALL {
+            self.forget_fan_speed(fan);
+        }
+    }
+
+    /// Discard any remembered or deferred speed of a single fan.
+    fn forget_fan_speed(&self, fan: Fan) {
+        self.commanded.lock().unwrap()[fan as usize] = None;
+        self.profiles.lock().unwrap()[fan as usize] = None;
+        self.write_queues[fan as usize].clear();
+        self.limiters[fan as usize].clear();
+        self.ramps[fan as usize].clear();
+    }
+
+    /// Reopen the driver if needed and apply the manual settings again.
+    ///
+    /// Resuming from sleep may have left the driver handle unusable and the
+    /// fans and power settings reset by the firmware.
+    pub async fn reinitialize(&self) -> Result<(), Status> {
+        self.io.reinitialize().await?;
+        self.restore_fan_speeds().await?;
+        self.reapply_power_settings().await
+    }
+
+    /// Apply the last requested speed of each fan again, for when the firmware
+    /// may have reset them.
+    async fn restore_fan_speeds(&self) -> Result<(), Status> {
+        let commanded = *self.commanded.lock().unwrap();
+
+        for fan in Fan::ALL {
+            self.write_queues[fan as usize].forget_written();
+
+            if let Some(duty) = commanded[fan as usize] {
+                self.with_fans(&[fan], move |backend| write_fan_speed(backend, fan, duty))
+                    .await?;
+                info!("Restored {fan:?} to {duty}%");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Log when the firmware switches its fan mode, which may mean it has taken
+    /// control back from the plugin.
+    fn log_firmware_mode_change(&self, previous: &FanStatusSnapshot, current: &FanStatusSnapshot) {
+        if current.mode != previous.mode {
+            if let Some(mode) = current.mode {
+                info!("Firmware switched to mode {mode}");
+            }
+        }
+
+        match (previous.mode_enable, current.mode_enable) {
+            (Some(true), Some(false)) => {
+                let manual = self.commanded.lock().unwrap().iter().any(Option::is_some);
+
+                if manual {
+                    warn!("Firmware took back control of the fan and power policy");
+                } else {
+                    info!("Firmware took back control of the fan and power policy");
+                }
+            }
+            (Some(false), Some(true)) => {
+                info!("Firmware handed the fan and power policy to the OS")
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the state of the fans, reusing the last read if it is recent
+    /// enough.
+    async fn read_status(&self) -> Result<FanStatusSnapshot, Status> {
+        let mut cache = self.status_cache.lock().await;
+
+        if let Some((read_at, snapshot)) = *cache {
+            if read_at.elapsed() < self.status_ttl {
+                return Ok(snapshot);
+            }
+        }
+
+        let snapshot = self
+            .with_io_initialized(|backend| backend.read_all_status().map_err(driver_status))
+            .await?;
+        if let Some((_, previous)) = *cache {
+            self.log_firmware_mode_change(&previous, &snapshot);
+        }
+        *cache = Some((Instant::now(), snapshot));
+
+        Ok(snapshot)
+    }
+
+    /// Hand the fan and power policy to the OS, or back to the firmware. Some
+    /// firmware only accepts power limits while the OS has it.
+    ///
+    /// Like the other power settings, this is put back as it was when the fans
+    /// are handed back to the firmware.
+    pub async fn set_mode_enable(&self, enabled: bool) -> Result<(), Status> {
+        let recorded = self
+            .original_power_settings
+            .lock()
+            .unwrap()
+            .mode_enable
+            .is_some();
+        let result = self
+            .with_io_initialized(move |backend| {
+                let original = if recorded {
+                    None
+                } else {
+                    backend.get_mode_enable().ok()
+                };
+                backend.set_mode_enable(enabled).map_err(driver_status)?;
+                Ok(original)
+            })
+            .await;
+        self.health.record(&result);
+
+        if let Some(original) = result? {
+            self.original_power_settings
+                .lock()
+                .unwrap()
+                .mode_enable
+                .get_or_insert(original);
+        }
+        self.applied_power_settings.lock().unwrap().mode_enable = Some(enabled);
+
+        if enabled {
+            info!("Handed the fan and power policy to the OS");
+        } else {
+            info!("Handed the fan and power policy to the firmware");
+        }
+        Ok(())
+    }
+
+    /// Apply the power settings last set through the service again, for when
+    /// the firmware may have reset them.
+    async fn reapply_power_settings(&self) -> Result<(), Status> {
+        let applied = self.applied_power_settings.lock().unwrap().clone();
+
+        if applied.is_empty() {
+            return Ok(());
+        }
+
+        self.with_io_initialized(move |backend| {
+            restore_power_settings(backend, &applied);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Keep applying the active speed profiles as temperatures change.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_profiles(&self) {
+        let mut ticker = interval(PROFILE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            match self.apply_profiles().await {
+                Ok(()) => log_limit::success(APPLYING_PROFILES),
+                Err(e) => {
+                    if let Some(repeats) = log_limit::failure(APPLYING_PROFILES) {
+                        warn!("Failed to apply speed profile: {}{repeats}", e.message());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set each fan that has a speed profile to the duty for the current
+    /// temperature.
+    async fn apply_profiles(&self) -> Result<(), Status> {
+        let profiles = self.profiles.lock().unwrap().clone();
+
+        if profiles.iter().all(Option::is_none) {
+            return Ok(());
+        }
+
+        let result = self
+            .with_io_initialized(|backend| backend.read_all_status().map_err(driver_status))
+            .await;
+        self.health.record(&result);
+        let snapshot = result?;
+
+        for fan in Fan::ALL {
+            if let Some(profile) = &profiles[fan as usize] {
+                let temp = snapshot.temperatures[profile.temp_source as usize];
+                let duty = profile.curve.duty_at(temp.into());
+                let floored = duty.max(self.fan_floor(fan).unwrap_or_default().into());
+
+                // Avoid writing to the EC when nothing has changed.
+                if self.commanded.lock().unwrap()[fan as usize] != Some(floored) {
+                    self.apply_fan_speed(fan, duty);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hand the fans back to the firmware if CoolerControl stops talking to us
+    /// for longer than the given period while it has them under manual
+    /// control, such as when it has crashed.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_watchdog(&self, period: Duration) {
+        loop {
+            let idle = self.activity.idle();
+
+            if idle < period {
+                sleep(period - idle).await;
+                continue;
+            }
+
+            if self.commanded.lock().unwrap().iter().any(Option::is_some) {
+                warn!("No requests received for {idle:?}, returning fan control to firmware");
+                self.forget_fan_speeds();
+
+                let result = self
+                    .with_fans(&Fan::ALL, |backend| {
+                        backend.set_fans_auto().map_err(driver_status)
+                    })
+                    .await;
+
+                if let Err(e) = result {
+                    warn!("Failed to return fan control to firmware: {}", e.message());
+                }
+            }
+
+            sleep(period).await;
+        }
+    }
+
+    /// Run the given curve on the fans under manual control if CoolerControl
+    /// stops talking to us for longer than the given period, so that they
+    /// keep following the temperatures while its daemon is not running.
+    ///
+    /// The curve stays in place until CoolerControl sets the fans again.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_fallback(&self, period: Duration, curve: Curve) {
+        let mut active = false;
+
+        loop {
+            let idle = self.activity.idle();
+
+            if idle < period {
+                if active {
+                    info!("Requests resumed, leaving the fans to CoolerControl");
+                    active = false;
+                }
+                sleep(period - idle).await;
+                continue;
+            }
+
+            if !active {
+                let commanded = *self.commanded.lock().unwrap();
+
+                if commanded.iter().any(Option::is_some) {
+                    warn!("No requests received for {idle:?}, running the fallback fan curve");
+                    let mut profiles = self.profiles.lock().unwrap();
+
+                    for fan in Fan::ALL {
+                        if commanded[fan as usize].is_some() {
+                            profiles[fan as usize] = Some(SpeedProfile {
+                                curve: curve.clone(),
+                                temp_source: fan,
+                            });
+                        }
+                    }
+                }
+                active = true;
+            }
+
+            sleep(PROFILE_INTERVAL).await;
+        }
+    }
+
+    /// Run both fans at full speed whenever a temperature reaches the given
+    /// critical value, whatever speed they were set to. Once the temperatures
+    /// have come down again, the fans are handed back to the firmware or set
+    /// to the speeds last requested.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_thermal_failsafe(&self, critical: u8) {
+        let mut ticker = interval(PROFILE_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let result = self
+                .with_io_initialized(|backend| backend.read_all_status().map_err(driver_status))
+                .await;
+            let snapshot = match result {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    debug!("Failsafe could not read temperatures: {}", e.message());
+                    continue;
+                }
+            };
+
+            let hottest = snapshot.temperatures.into_iter().max().unwrap_or_default();
+            let was_tripped = self.failsafe_tripped.load(Ordering::Relaxed);
+            let tripped = if hottest >= critical {
+                true
+            } else {
+                was_tripped && hottest >= critical.saturating_sub(FAILSAFE_HYSTERESIS)
+            };
+            self.failsafe_tripped.store(tripped, Ordering::Relaxed);
+
+            if tripped {
+                if !was_tripped {
+                    warn!(
+                        "Temperature of {hottest}°C reached critical {critical}°C, running fans at full speed"
+                    );
+                }
+
+                // Check every time, in case something else changed the speed.
+                if snapshot.speeds.iter().any(|speed| *speed < 99.0) {
+                    let result = self
+                        .with_fans(&Fan::ALL, |backend| {
+                            for fan in Fan::ALL {
+                                backend.set_fan_speed(fan, 100.0).map_err(driver_status)?;
+                            }
+                            Ok(())
+                        })
+                        .await;
+                    self.health.record(&result);
+
+                    match result {
+                        Ok(()) => log_limit::success(RUNNING_FULL_SPEED),
+                        Err(e) => {
+                            if let Some(repeats) = log_limit::failure(RUNNING_FULL_SPEED) {
+                                warn!("Failed to run fans at full speed: {}{repeats}", e.message());
+                            }
+                        }
+                    }
+                }
+            } else if was_tripped {
+                info!("Temperature back down to {hottest}°C, releasing fans from the failsafe");
+
+                let result = self
+                    .with_fans(&Fan::ALL, |backend| {
+                        backend.set_fans_auto().map_err(driver_status)
+                    })
+                    .await;
+                if let Err(e) = result.and(self.restore_fan_speeds().await) {
+                    warn!("Failed to release fans from the failsafe: {}", e.message());
+                }
+            }
+        }
+    }
+
+    /// Get how long the service has been running.
+    pub fn uptime(&self) -> Duration {
+        self.start_time.elapsed()
+    }
+
+    /// Get the number of driver accesses made so far.
+    pub fn io_stats(&self) -> IoStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Open the driver if it isn't already.
+    pub async fn open(&self) -> Result<(), Status> {
+        self.with_io_initialized(|_| Ok(())).await
+    }
+
+    /// Open the driver, waiting up to the given time for it to appear. At boot
+    /// the service may be started before the device node has been created.
+    pub async fn open_waiting(&self, wait: Duration) -> Result<(), Status> {
+        let deadline = Instant::now() + wait;
+        let mut delay = OPEN_RETRY_DELAY;
+        let mut attempt = 1;
+
+        loop {
+            match self.open().await {
+                Err(e) if e.code() == Code::NotFound && Instant::now() + delay <= deadline => {
+                    info!(
+                        "Driver not available yet on attempt {attempt} ({}), retrying in {delay:?}",
+                        e.message()
+                    );
+                    sleep(delay).await;
+                    delay = (delay * 2).min(OPEN_RETRY_MAX_DELAY);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Write the current state of the device and the driver access totals as
+    /// metrics. The device state is left out if it can't be read.
+    pub async fn write_metrics(&self, metrics: &mut Exposition) {
+        let stats = self.io_stats();
+
+        metrics.family("tuxedo_up", "gauge", "Whether the driver is responding.");
+        let healthy = self.health.status() == health_response::Status::Ok;
+        metrics.sample("tuxedo_up", &[], u8::from(healthy));
+
+        metrics.family(
+            "tuxedo_device_reachable",
+            "gauge",
+            "Whether the device is present, even if requests to it fail.",
+        );
+        metrics.sample(
+            "tuxedo_device_reachable",
+            &[],
+            u8::from(self.health.reachable()),
+        );
+
+        metrics.family(
+            "tuxedo_driver_consecutive_failures",
+            "gauge",
+            "Driver accesses that failed since the last one succeeded.",
+        );
+        metrics.sample(
+            "tuxedo_driver_consecutive_failures",
+            &[],
+            self.health.consecutive_failures(),
+        );
+
+        metrics.family(
+            "tuxedo_driver_calls_total",
+            "counter",
+            "Driver calls made, by outcome.",
+        );
+        for (outcome, count) in [
+            ("read", stats.reads),
+            ("write", stats.writes),
+            ("not_found", stats.not_found_errors),
+            ("permission_denied", stats.permission_errors),
+            ("device_error", stats.device_errors),
+            ("error", stats.other_errors),
+            ("timeout", stats.timeouts),
+        ] {
+            metrics.sample("tuxedo_driver_calls_total", &[("outcome", outcome)], count);
+        }
+
+        metrics.family(
+            "tuxedo_driver_call_seconds_total",
+            "counter",
+            "Time spent waiting for driver calls.",
+        );
+        metrics.sample(
+            "tuxedo_driver_call_seconds_total",
+            &[],
+            stats.busy.as_secs_f64(),
+        );
+
+        let commanded = *self.commanded.lock().unwrap();
+        metrics.family(
+            "tuxedo_fan_commanded_duty_percent",
+            "gauge",
+            "Duty last requested for fans under manual control.",
+        );
+        for fan in Fan::ALL {
+            if let Some(duty) = commanded[fan as usize] {
+                let labels = [("channel", fan_channel_id(fan))];
+                metrics.sample("tuxedo_fan_commanded_duty_percent", &labels, duty);
+            }
+        }
+
+        let snapshot = match self.read_status().await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                debug!("Leaving device state out of metrics: {}", e.message());
+                return;
+            }
+        };
+
+        metrics.family("tuxedo_fan_duty_percent", "gauge", "Current fan speed.");
+        for fan in Fan::ALL {
+            let labels = [("channel", fan_channel_id(fan))];
+            metrics.sample(
+                "tuxedo_fan_duty_percent",
+                &labels,
+                snapshot.speeds[fan as usize],
+            );
+        }
+
+        metrics.family("tuxedo_temperature_celsius", "gauge", "Sensor temperature.");
+        for (fan, temp_id, _) in TEMP_CHANNELS {
+            let labels = [("sensor", temp_id)];
+            let temp = snapshot.temperatures[fan as usize];
+            metrics.sample("tuxedo_temperature_celsius", &labels, temp);
+        }
+
+        if let Some(mode) = snapshot.mode {
+            metrics.family(
+                "tuxedo_firmware_mode",
+                "gauge",
+                "Raw fan and performance mode set in the firmware.",
+            );
+            metrics.sample("tuxedo_firmware_mode", &[], mode);
+        }
+        if let Some(mode_enable) = snapshot.mode_enable {
+            metrics.family(
+                "tuxedo_firmware_mode_enabled",
+                "gauge",
+                "Whether the firmware leaves the fan and power policy to the OS.",
+            );
+            metrics.sample("tuxedo_firmware_mode_enabled", &[], u8::from(mode_enable));
+        }
+
+        metrics.family("tuxedo_power_limit_watts", "gauge", "Current power limit.");
+        for (tdp, channel_id, _) in TDP_CHANNELS {
+            if let Some(watts) = snapshot.tdp[tdp as usize] {
+                metrics.sample(
+                    "tuxedo_power_limit_watts",
+                    &[("channel", channel_id)],
+                    watts,
+                );
+            }
+        }
+    }
+
+    /// Get the minimum fan speed recommended by the hardware, as a percentage.
+    pub async fn hardware_min_speed(&self) -> Result<u8, Status> {
+        self.with_io_initialized(|backend| backend.get_fan_min_speed().map_err(driver_status))
+            .await
+    }
+
+    /// Keep the status reported through the standard gRPC health service in
+    /// step with the health of the driver.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_health_reporter(&self, reporter: HealthReporter) {
+        let mut ticker = interval(HEALTH_REPORT_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut reported = None;
+
+        loop {
+            ticker.tick().await;
+
+            let healthy = self.health.status() == health_response::Status::Ok;
+            if reported == Some(healthy) {
+                continue;
+            }
+
+            if healthy {
+                reporter.set_serving::<DeviceServiceServer<Self>>().await;
+            } else {
+                reporter
+                    .set_not_serving::<DeviceServiceServer<Self>>()
+                    .await;
+            }
+            reported = Some(healthy);
+        }
+    }
+
+    /// Reopen the driver and apply the manual settings again whenever a driver
+    /// call fails with `ENODEV` or `EIO`, such as after the kernel module was
+    /// reloaded or the EC stopped responding. Health is reported as degraded
+    /// until this succeeds.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_device_recovery(&self) {
+        loop {
+            self.stats.device_lost().await;
+            warn!("Driver reported the device as lost, reinitializing");
+            self.health.set_recovering(true);
+
+            let mut delay = OPEN_RETRY_DELAY;
+            while let Err(e) = self.reinitialize().await {
+                warn!(
+                    "Failed to recover device ({}), retrying in {delay:?}",
+                    e.message()
+                );
+                sleep(delay).await;
+                delay = (delay * 2).min(OPEN_RETRY_MAX_DELAY);
+            }
+
+            self.health.set_recovering(false);
+            info!("Recovered device");
+        }
+    }
+
+    /// Periodically check that the driver is still reachable, so that problems
+    /// are reported by the health check even while no other requests are made.
+    ///
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_self_checks(&self, period: Duration) {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let result = self
+                .with_io_initialized(|backend| {
+                    backend.get_fan_speed(Fan::Fan1).map_err(driver_status)
+                })
+                .await;
+
+            if let Err(e) = &result {
+                debug!("Self check failed: {}", e.message());
+            }
+            self.health.record(&result);
+        }
+    }
+
+    /// Periodically log a snapshot of the fans, temperatures, power settings
+    /// and outstanding writes at debug level, with the `telemetry` target, so
+    /// that thermal complaints can be followed up from the log alone.
+    ///
+    /// Nothing is read from the driver while the snapshot would not be logged.
+    /// This never returns, so it should be raced against shutdown.
+    pub async fn run_telemetry(&self, period: Duration) {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            if !enabled!(target: "telemetry", Level::DEBUG) {
+                continue;
+            }
+
+            match self.read_status().await {
+                Ok(snapshot) => debug!(target: "telemetry", "{}", self.telemetry(&snapshot)),
+                Err(e) => debug!(target: "telemetry", "No snapshot: {}", e.message()),
+            }
+        }
+    }
+
+    /// Describe the current state in a single line.
+    fn telemetry(&self, snapshot: &FanStatusSnapshot) -> String {
+        let mut line = String::new();
+
+        for fan in Fan::ALL {
+            let index = fan as usize;
+            let _ = write!(
+                line,
+                "{} {:.0}% {}°C",
+                fan_channel_id(fan),
+                snapshot.speeds[index],
+                snapshot.temperatures[index]
+            );
+
+            let pending = self.write_queues[index]
+                .pending()
+                .or_else(|| self.limiters[index].pending());
+            if let Some(duty) = pending {
+                let _ = write!(line, " (pending {duty:.0}%)");
+            }
+            line.push_str(", ");
+        }
+
+        match snapshot.mode {
+            Some(mode) => {
+                let _ = write!(line, "mode {mode}");
+            }
+            None => line.push_str("mode unknown"),
+        }
+
+        let tdp = snapshot
+            .tdp
+            .iter()
+            .map(|watts| watts.map_or_else(|| "-".to_owned(), |watts| watts.to_string()))
+            .collect::<Vec<_>>()
+            .join("/");
+        let _ = write!(
+            line,
+            ", TDP {tdp} W, {} driver calls in flight",
+            self.io.in_flight.load(Ordering::Relaxed)
+        );
+
+        line
+    }
+
+    /// Set the speed of a fan, subject to the configured floor, ramp rate and
+    /// write rate limit. This returns before the speed has been written.
+    fn apply_fan_speed(&self, fan: Fan, duty: f64) {
+        let duty = match self.fan_floor(fan) {
+            Some(floor) if duty < floor.into() => {
+                debug!("Raising requested speed {duty}% for {fan:?} to floor of {floor}%");
+                floor.into()
+            }
+            _ => duty,
+        };
+
+        self.commanded.lock().unwrap()[fan as usize] = Some(duty);
+        if self.failsafe_tripped.load(Ordering::Relaxed) {
+            debug!("Not setting {fan:?} to {duty}% while the thermal failsafe is tripped");
+            return;
+        }
+
+        let ramp = self.ramps[fan as usize].clone();
+        let limiter = self.limiters[fan as usize].clone();
+        let writer = self.fan_writer(fan);
+
+        match ramp.request(duty) {
+            RampAction::Now(duty) => write_limited(&limiter, writer, duty),
+            RampAction::Start => {
+                spawn(async move {
+                    let mut ticker = interval(RAMP_INTERVAL);
+
+                    loop {
+                        ticker.tick().await;
+
+                        match ramp.step(RAMP_INTERVAL) {
+                            Some(duty) => write_limited(&limiter, writer.clone(), duty),
+                            None => break,
+                        }
+                    }
+                });
+            }
+            RampAction::Retargeted => {}
+        }
+    }
 
-const DEVICE_ID: &str = "tuxedo";
-const DEFAULT_DEVICE_NAME: &str = "TUXEDO InfinityBook Gen10";
-const FAN_1_CHANNEL_ID: &str = "fan1";
-const FAN_2_CHANNEL_ID: &str = "fan2";
+    fn fan_writer(&self, fan: Fan) -> FanWriter {
+        FanWriter {
+            fan,
+            queue: self.write_queues[fan as usize].clone(),
+            io: self.io.clone(),
+            health: self.health.clone(),
+        }
+    }
+}
 
-pub struct TuxedoService {
-    start_time: Instant,
-    tuxedo_io: Arc<Mutex<Option<TuxedoIo>>>,
+/// Writes the speeds queued for a fan, one at a time, so that requests are not
+/// held up by a slow write.
+#[derive(Clone)]
+struct FanWriter {
+    fan: Fan,
+    queue: Arc<WriteQueue>,
+    io: IoHandle,
+    health: Arc<HealthMonitor>,
 }
 
-impl TuxedoService {
-    pub fn new() -> Self {
+impl FanWriter {
+    /// Queue a duty, starting to drain the queue if it isn't already.
+    fn queue(self, duty: f64) {
+        if self.queue.push(duty) {
+            spawn(self.drain());
+        }
+    }
+
+    async fn drain(self) {
+        let fan = self.fan;
+
+        while let Some((duty, generation)) = self.queue.pop() {
+            let queue = self.queue.clone();
+            let result = self
+                .io
+                .with_fans(&[fan], move |backend| {
+                    // The fans may have been handed back to the firmware while
+                    // this write was waiting for the lock.
+                    if !queue.is_current(generation) {
+                        return Ok(());
+                    }
+
+                    // CoolerControl sends the same duty again periodically,
+                    // and each write can block for a long time.
+                    let duty = hardware_duty(backend, fan, duty)?;
+                    let raw = backend.fan_speed_value(duty);
+                    if queue.is_written(raw) {
+                        debug!("Not setting {fan:?} to {duty}%, as it is already set");
+                        return Ok(());
+                    }
+
+                    let result = send_fan_speed(backend, fan, duty);
+                    queue.set_written(result.as_ref().ok().copied());
+                    result.map(drop)
+                })
+                .await;
+            self.health.record(&result);
+        }
+    }
+}
+
+/// Queue a speed to be written to a fan, once its write rate limit allows.
+fn write_limited(limiter: &Arc<WriteLimiter>, writer: FanWriter, duty: f64) {
+    match limiter.admit(duty) {
+        Admission::Now => writer.queue(duty),
+        Admission::Schedule(delay) => {
+            let limiter = limiter.clone();
+
+            spawn(async move {
+                sleep(delay).await;
+
+                if let Some(duty) = limiter.take_pending() {
+                    writer.queue(duty);
+                }
+            });
+        }
+        Admission::Coalesced => {}
+    }
+}
+
+/// A fan curve applied by the service itself.
+#[derive(Clone)]
+struct SpeedProfile {
+    curve: Curve,
+
+    /// Sensor the curve follows, identified by the fan it is associated with.
+    temp_source: Fan,
+}
+
+/// Opens a connection to the fan hardware.
+type Opener = Arc<dyn Fn() -> io::Result<Box<dyn FanBackend>> + Send + Sync>;
+
+/// Counts an operation as in flight for as long as it is held, including when
+/// the request making it is cancelled.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn enter(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Run a blocking operation on the blocking pool, inside the span of the
+/// caller, so that the driver calls it makes are attributed to the RPC that
+/// made them.
+fn spawn_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> JoinHandle<T> {
+    let span = Span::current();
+
+    task::spawn_blocking(move || span.in_scope(f))
+}
+
+/// Shared handle to the driver that can be moved into background tasks.
+///
+/// Every fan has its own lock, which is held while an operation that affects
+/// that fan is running. This keeps writes to the same fan in order, while a
+/// slow write to one fan does not hold up the other, unless the backend
+/// serializes its commands itself.
+#[derive(Clone)]
+struct IoHandle {
+    backend: Arc<Mutex<Option<Arc<dyn FanBackend>>>>,
+    fan_locks: [Arc<Mutex<()>>; 2],
+    open: Opener,
+    timeout: Duration,
+    stats: Arc<IoStats>,
+
+    /// Number of driver operations waiting for their turn or running.
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl IoHandle {
+    fn new(open: Opener, timeout: Duration, stats: Arc<IoStats>) -> Self {
         Self {
-            start_time: Instant::now(),
-            tuxedo_io: Arc::new(Mutex::new(None)),
+            backend: Arc::new(Mutex::new(None)),
+            fan_locks: Default::default(),
+            open,
+            timeout,
+            stats,
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Run a driver operation, giving up if it does not complete in time.
+    ///
+    /// Some driver calls (setting a fan speed in particular) do not return until
+    /// the EC has finished acting on them, which may take a long time or never
+    /// happen at all. If the operation does not complete within the configured
+    /// timeout then `DeadlineExceeded` is returned. The ioctl itself cannot be
+    /// interrupted, so the orphaned blocking thread keeps holding its fan locks
+    /// until the driver eventually returns; later calls for the same fans wait
+    /// for it (bounded by the same timeout) rather than piling up on the
+    /// blocking pool.
+    async fn bounded<T>(
+        &self,
+        invocation: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, Status> {
+        let _in_flight = InFlight::enter(&self.in_flight);
+
+        match timeout(self.timeout, invocation).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.stats.record_timeout();
+                Err(Status::deadline_exceeded(format!(
+                    "driver did not respond within {:?}",
+                    self.timeout
+                )))
+            }
+        }
+    }
+
+    /// Run a blocking write to a sysfs attribute outside of the driver, such as
+    /// the battery charge limit or the backlights, giving up if it does not complete in time.
+    async fn with_sysfs<T: Send + 'static>(
+        &self,
+        what: &'static str,
+        f: impl Send + FnOnce() -> io::Result<T> + 'static,
+    ) -> Result<T, Status> {
+        self.bounded(async {
+            spawn_blocking(f)
+                .await
+                .map_err(|e| Status::from_error(Box::new(e)))?
+                .map_err(|e| sysfs_status(what, e))
+        })
+        .await
+    }
+
+    /// Acquire the locks of the given fans, which must be in [`Fan::ALL`] order.
+    async fn lock_fans(&self, fans: &[Fan]) -> Vec<OwnedMutexGuard<()>> {
+        let mut guards = Vec::with_capacity(fans.len());
+
+        for fan in fans {
+            guards.push(self.fan_locks[*fan as usize].clone().lock_owned().await);
         }
+
+        guards
     }
 
+    /// Run a blocking operation that opens, closes, or replaces the driver
+    /// handle. This waits for operations on all fans to finish first.
     async fn with_io<T: Send + 'static>(
         &self,
-        f: impl Send + FnOnce(&mut Option<TuxedoIo>) -> Result<T, Status> + 'static,
+        f: impl Send + FnOnce(&mut Option<Arc<dyn FanBackend>>) -> Result<T, Status> + 'static,
     ) -> Result<T, Status> {
-        let arc = self.tuxedo_io.clone();
+        self.bounded(async {
+            let fan_guards = self.lock_fans(&Fan::ALL).await;
+            let mut backend = self.backend.clone().lock_owned().await;
+
+            spawn_blocking(move || {
+                let _fan_guards = fan_guards;
+                f(&mut backend)
+            })
+            .await
+            .map_err(|e| Status::from_error(Box::new(e)))?
+        })
+        .await
+    }
+
+    /// Get the driver handle, opening it if necessary.
+    async fn backend(&self) -> Result<Arc<dyn FanBackend>, Status> {
+        let mut backend = self.backend.clone().lock_owned().await;
+
+        if let Some(backend) = backend.as_ref() {
+            return Ok(backend.clone());
+        }
+
+        let open = self.open.clone();
 
         spawn_blocking(move || {
-            let mut tuxedo_io = arc.blocking_lock();
-            f(&mut *tuxedo_io)
+            Ok(backend
+                .insert(Arc::from(open().map_err(driver_status)?))
+                .clone())
         })
         .await
-        .map_err(|e| Status::from_error(Box::new(e)))
-        .flatten()
+        .map_err(|e| Status::from_error(Box::new(e)))?
     }
 
-    async fn with_io_initialized<T: Send + 'static>(
+    /// Verify that the driver handle still works, replacing it with a freshly
+    /// opened one if it does not.
+    async fn reinitialize(&self) -> Result<(), Status> {
+        let open = self.open.clone();
+
+        self.with_io(move |backend| {
+            if let Some(current) = backend.as_ref() {
+                match current.check() {
+                    Ok(()) => return Ok(()),
+                    Err(e) => warn!("Driver handle failed hardware check, reopening: {e}"),
+                }
+            }
+
+            // Keep the old handle if a new one can't be opened, such as after
+            // dropping privileges.
+            let stale = backend.replace(Arc::from(open().map_err(driver_status)?));
+
+            if stale.is_some() {
+                info!("Replaced stale driver handle");
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run a blocking operation against the driver while holding the locks of
+    /// the given fans, which must be in [`Fan::ALL`] order.
+    async fn with_fans<T: Send + 'static>(
         &self,
-        f: impl Send + FnOnce(&TuxedoIo) -> Result<T, Status> + 'static,
+        fans: &[Fan],
+        f: impl Send + FnOnce(&dyn FanBackend) -> Result<T, Status> + 'static,
     ) -> Result<T, Status> {
-        self.with_io(move |tuxedo_io| {
-            let tuxedo_io = match tuxedo_io.as_mut() {
-                Some(io) => io,
-                None => tuxedo_io.insert(TuxedoIo::open()?),
-            };
+        self.bounded(async {
+            // Lock the fans first, so a write can never be applied through a
+            // handle that has since been released.
+            let fan_guards = self.lock_fans(fans).await;
+            let backend = self.backend().await?;
 
-            f(tuxedo_io)
+            spawn_blocking(move || {
+                let _fan_guards = fan_guards;
+                f(backend.as_ref())
+            })
+            .await
+            .map_err(|e| Status::from_error(Box::new(e)))?
         })
         .await
     }
@@ -80,18 +1529,65 @@ impl DeviceService for TuxedoService {
         let reply = HealthResponse {
             name: SERVICE_ID.to_string(),
             version: VERSION.to_string(),
-            status: health_response::Status::Ok.into(),
-            uptime_seconds: self.start_time.elapsed().as_secs(),
+            status: self.health.status().into(),
+            uptime_seconds: self.uptime().as_secs(),
         };
-        Ok(Response::new(reply))
+
+        // The health message has no room for details, so they are sent along
+        // as metadata for clients that know to look for them.
+        let mut response = Response::new(reply);
+        let metadata = response.metadata_mut();
+        metadata.insert(
+            REACHABLE_METADATA,
+            MetadataValue::from_static(if self.health.reachable() {
+                "true"
+            } else {
+                "false"
+            }),
+        );
+        metadata.insert(
+            CONSECUTIVE_FAILURES_METADATA,
+            self.health.consecutive_failures().into(),
+        );
+        metadata.insert(TOTAL_FAILURES_METADATA, self.health.total_failures().into());
+        if let Some(value) = self
+            .health
+            .last_error()
+            .and_then(|error| MetadataValue::try_from(error).ok())
+        {
+            metadata.insert(LAST_ERROR_METADATA, value);
+        }
+
+        Ok(response)
     }
 
     async fn list_devices(
         &self,
         _request: Request<ListDevicesRequest>,
     ) -> Result<Response<ListDevicesResponse>, Status> {
-        self.with_io_initialized(|tuxedo_io| {
-            let device = get_device(tuxedo_io)?;
+        let fan_floors = *self.fan_floors.lock().unwrap();
+        let labels = self.labels.lock().unwrap().clone();
+        let peripherals = Peripherals {
+            keyboard: self.keyboard.is_some(),
+            charge_limit: self.charge_limit.is_some(),
+            display: self.display.is_some(),
+        };
+        let identity = DeviceIdentity {
+            uid_info: self.uid_info.clone(),
+            firmware: self.firmware.clone(),
+            locations: [
+                Some(self.device_path.as_path()),
+                self.hwmon.as_ref().map(|hwmon| hwmon.dir()),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|path| path.display().to_string())
+            .collect(),
+        };
+
+        self.with_io_initialized(move |backend| {
+            let device = get_device(backend, fan_floors, &labels, peripherals, identity)
+                .map_err(driver_status)?;
 
             Ok(Response::new(ListDevicesResponse {
                 devices: vec![device],
@@ -104,66 +1600,141 @@ impl DeviceService for TuxedoService {
         &self,
         _request: Request<InitializeDeviceRequest>,
     ) -> Result<Response<InitializeDeviceResponse>, Status> {
-        self.with_io_initialized(|_| {
-            // Nothing else to do, enter will ensure a connection is established.
+        self.reinitialize().await?;
 
-            Ok(Response::new(InitializeDeviceResponse {}))
-        })
-        .await
+        Ok(Response::new(InitializeDeviceResponse {}))
     }
 
     async fn shutdown(
         &self,
         _request: Request<ShutdownRequest>,
     ) -> Result<Response<ShutdownResponse>, Status> {
-        self.with_io(|tuxedo_io| {
-            // Reset the fans to auto before exiting, or they may be stuck off
-            // which could cause overheating.
-            if let Some(tuxedo_io) = tuxedo_io.take() {
-                tuxedo_io.set_fans_auto()?;
-
-                // Disconnect the driver handle.
-                drop(tuxedo_io);
-            }
+        self.release_fans().await?;
 
-            Ok(Response::new(ShutdownResponse {}))
-        })
-        .await
+        Ok(Response::new(ShutdownResponse {}))
     }
 
     async fn status(
         &self,
         _request: Request<StatusRequest>,
     ) -> Result<Response<StatusResponse>, Status> {
-        self.with_io_initialized(|tuxedo_io| {
-            Ok(Response::new(StatusResponse {
-                status: vec![
-                    models::v1::Status {
-                        id: FAN_1_CHANNEL_ID.into(),
-                        metric: Some(models::v1::status::Metric::Speed(FanSpeed {
-                            duty: Some(tuxedo_io.get_fan_speed(Fan::Fan1)? as f64),
-                            rpm: None,
-                        })),
-                    },
-                    models::v1::Status {
-                        id: FAN_2_CHANNEL_ID.into(),
+        self.activity.record();
+
+        let hwmon = self.hwmon.clone();
+        let max_rpm = self.max_rpm;
+        let rpm = move |fan, duty: Option<f64>| {
+            hwmon
+                .as_ref()
+                .and_then(|hwmon| hwmon.read_fan_rpm(fan))
+                .or_else(|| Some((duty? / 100.0 * f64::from(max_rpm?)).round() as u32))
+        };
+        let charge_limit = self.charge_limit.as_ref().and_then(|charge_limit| {
+            charge_limit
+                .get()
+                .inspect_err(|e| debug!("Failed to read battery charge limit: {e}"))
+                .ok()
+        });
+
+        let result = self.read_status().await.map(|snapshot| {
+            let commanded = *self.commanded.lock().unwrap();
+            let (speeds, temperatures) = {
+                let mut filters = self.status_filters.lock().unwrap();
+
+                (
+                    Fan::ALL.map(|fan| filters.speed(fan, snapshot.speeds[fan as usize])),
+                    Fan::ALL.map(|fan| {
+                        filters.temperature(fan, snapshot.temperatures[fan as usize].into())
+                    }),
+                )
+            };
+
+            Response::new(StatusResponse {
+                status: Fan::ALL
+                    .into_iter()
+                    .map(|fan| {
+                        // The EC may still report a small speed for a fan
+                        // that has stopped, so trust what we asked for
+                        // instead. Only an explicit 0% counts; a fan at
+                        // its minimum speed is still spinning.
+                        let off =
+                            snapshot.fans_off_available && commanded[fan as usize] == Some(0.0);
+
+                        let speed = if off {
+                            FanSpeed {
+                                duty: Some(0.0),
+                                rpm: Some(0),
+                            }
+                        } else if self.ramps[fan as usize].is_ramping() {
+                            // Report where the fan is headed rather than every
+                            // step on the way there.
+                            FanSpeed {
+                                duty: commanded[fan as usize],
+                                rpm: rpm(fan, commanded[fan as usize]),
+                            }
+                        } else {
+                            FanSpeed {
+                                duty: Some(speeds[fan as usize]),
+                                rpm: rpm(fan, Some(speeds[fan as usize])),
+                            }
+                        };
+
+                        models::v1::Status {
+                            id: fan_channel_id(fan).into(),
+                            metric: Some(models::v1::status::Metric::Speed(speed)),
+                        }
+                    })
+                    .chain(TDP_CHANNELS.into_iter().filter_map(|(tdp, tdp_id, _)| {
+                        Some(models::v1::Status {
+                            id: tdp_id.into(),
+                            metric: Some(models::v1::status::Metric::Watts(
+                                snapshot.tdp[tdp as usize]?.into(),
+                            )),
+                        })
+                    }))
+                    .chain(charge_limit.map(|percent| models::v1::Status {
+                        id: CHARGE_LIMIT_CHANNEL_ID.into(),
                         metric: Some(models::v1::status::Metric::Speed(FanSpeed {
-                            duty: Some(tuxedo_io.get_fan_speed(Fan::Fan2)? as f64),
+                            duty: Some(percent.into()),
                             rpm: None,
                         })),
-                    },
-                ],
-            }))
-        })
-        .await
+                    }))
+                    .chain(TEMP_CHANNELS.map(|(fan, temp_id, _)| models::v1::Status {
+                        id: temp_id.into(),
+                        metric: Some(models::v1::status::Metric::Temp(temperatures[fan as usize])),
+                    }))
+                    .collect(),
+            })
+        });
+        self.health.record(&result);
+
+        result
     }
 
     async fn reset_channel(
         &self,
-        _request: Request<ResetChannelRequest>,
+        request: Request<ResetChannelRequest>,
     ) -> Result<Response<ResetChannelResponse>, Status> {
-        self.with_io_initialized(|tuxedo_io| {
-            tuxedo_io.set_fans_auto()?;
+        let fan = resolve_fan(&request.get_ref().channel_id)?;
+
+        // Resets are never rate limited, and must not be undone by a deferred
+        // write that is still waiting to be applied.
+        self.forget_fan_speed(fan);
+
+        // The firmware can only take back control of all fans at once, so any
+        // other fan under manual control is set back to its speed afterwards.
+        let commanded = *self.commanded.lock().unwrap();
+        for queue in &self.write_queues {
+            queue.forget_written();
+        }
+
+        self.with_fans(&Fan::ALL, move |backend| {
+            backend.set_fans_auto().map_err(driver_status)?;
+
+            for other in Fan::ALL {
+                if let Some(duty) = commanded[other as usize] {
+                    write_fan_speed(backend, other, duty)?;
+                }
+            }
 
             Ok(Response::new(ResetChannelResponse {}))
         })
@@ -183,46 +1754,220 @@ impl DeviceService for TuxedoService {
         &self,
         request: Request<FixedDutyRequest>,
     ) -> Result<Response<FixedDutyResponse>, Status> {
-        self.with_io_initialized(move |tuxedo_io| {
-            let fan = if request.get_ref().channel_id == FAN_1_CHANNEL_ID {
-                Fan::Fan1
-            } else if request.get_ref().channel_id == FAN_2_CHANNEL_ID {
-                Fan::Fan2
-            } else {
-                return Err(Status::invalid_argument("Unknown channel ID"));
+        self.activity.record();
+
+        // Power limit channels take their value in watts rather than percent.
+        if let Some(tdp) = resolve_tdp(&request.get_ref().channel_id) {
+            let watts = request.get_ref().duty;
+            let recorded = self.original_power_settings.lock().unwrap().tdp[tdp as usize].is_some();
+            let result = self
+                .with_io_initialized(move |backend| {
+                    let original = if recorded {
+                        None
+                    } else {
+                        backend.get_tdp(tdp).ok()
+                    };
+                    write_tdp(backend, tdp, watts)?;
+                    Ok(original)
+                })
+                .await;
+            self.health.record(&result);
+
+            if let Some(original) = result? {
+                self.original_power_settings.lock().unwrap().tdp[tdp as usize]
+                    .get_or_insert(original);
+            }
+            self.applied_power_settings.lock().unwrap().tdp[tdp as usize] = Some(watts as u32);
+
+            return Ok(Response::new(FixedDutyResponse {}));
+        }
+
+        if let (CHARGE_LIMIT_CHANNEL_ID, Some(charge_limit)) =
+            (request.get_ref().channel_id.as_str(), &self.charge_limit)
+        {
+            let percent = match u8::try_from(request.get_ref().duty) {
+                Ok(percent) if (MIN_CHARGE_LIMIT..=100).contains(&percent) => percent,
+                _ => {
+                    return Err(Status::invalid_argument(format!(
+                        "Charge limit {}% is outside of {MIN_CHARGE_LIMIT}-100%",
+                        request.get_ref().duty
+                    )));
+                }
             };
+            let charge_limit = charge_limit.clone();
+            self.io
+                .with_sysfs("the battery charge limit", move || {
+                    charge_limit.set(percent)
+                })
+                .await?;
+            info!("Set battery charge limit to {percent}%");
 
-            tuxedo_io.set_fan_speed(fan, request.get_ref().duty as u8)?;
+            return Ok(Response::new(FixedDutyResponse {}));
+        }
 
-            Ok(Response::new(FixedDutyResponse {}))
-        })
-        .await
+        let fan = resolve_writable_fan(&request.get_ref().channel_id)?;
+        let duty = match u8::try_from(request.get_ref().duty) {
+            Ok(duty) if duty <= 100 => duty,
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "Duty {}% for {} is outside of 0-100%",
+                    request.get_ref().duty,
+                    fan_channel_id(fan)
+                )));
+            }
+        };
+
+        // A fixed duty replaces any profile that was driving the fan.
+        self.profiles.lock().unwrap()[fan as usize] = None;
+
+        self.apply_fan_speed(fan, duty.into());
+
+        Ok(Response::new(FixedDutyResponse {}))
     }
 
     async fn speed_profile(
         &self,
-        _request: Request<SpeedProfileRequest>,
+        request: Request<SpeedProfileRequest>,
     ) -> Result<Response<SpeedProfileResponse>, Status> {
-        // TODO: Apply a speed profile to the device channel
-        Err(Status::unimplemented("No Firmware Profiles"))
+        self.activity.record();
+
+        let request = request.into_inner();
+        let fan = resolve_writable_fan(&request.channel_id)?;
+        let temp_source = resolve_temp_source(request.temp_source_id.as_deref(), fan)?;
+        let curve = Curve::new(
+            request
+                .speed_profile
+                .iter()
+                .map(|point| (point.temp, point.duty))
+                .collect(),
+        )?;
+
+        self.profiles.lock().unwrap()[fan as usize] = Some(SpeedProfile { curve, temp_source });
+
+        // Apply the profile right away rather than waiting for the next tick.
+        self.apply_profiles().await?;
+
+        Ok(Response::new(SpeedProfileResponse {}))
     }
 
     async fn lighting(
         &self,
-        _request: Request<LightingRequest>,
+        request: Request<LightingRequest>,
     ) -> Result<Response<LightingResponse>, Status> {
-        Err(Status::unimplemented("No Lighting Channels"))
+        let request = request.into_inner();
+        let setting = request.setting.unwrap_or_default();
+
+        match (request.channel_id.as_str(), &self.keyboard) {
+            (KEYBOARD_CHANNEL_ID, Some(keyboard)) => {
+                set_keyboard_lighting(&self.io, keyboard, &setting).await?
+            }
+            (PERFORMANCE_CHANNEL_ID, _) => {
+                let Some((profile, _, _)) = PERFORMANCE_PROFILES
+                    .into_iter()
+                    .find(|(_, name, _)| *name == setting.mode)
+                else {
+                    return Err(Status::invalid_argument(format!(
+                        "Unknown performance profile: {}",
+                        setting.mode
+                    )));
+                };
+
+                let recorded = self.original_power_settings.lock().unwrap().mode.is_some();
+                let result = self
+                    .with_io_initialized(move |backend| {
+                        let original = if recorded {
+                            None
+                        } else {
+                            backend.get_mode().ok()
+                        };
+                        backend
+                            .set_performance_profile(profile)
+                            .map_err(driver_status)?;
+                        Ok((original, backend.get_mode().map_err(driver_status)?))
+                    })
+                    .await;
+                self.health.record(&result);
+                let (original, mode) = result?;
+
+                if let Some(original) = original {
+                    self.original_power_settings
+                        .lock()
+                        .unwrap()
+                        .mode
+                        .get_or_insert(original);
+                }
+                self.applied_power_settings.lock().unwrap().mode = Some(profile as i32);
+
+                info!("Switched to {profile:?} performance profile (firmware mode {mode})");
+            }
+            (channel_id, _) => {
+                return Err(Status::invalid_argument(format!(
+                    "Unknown lighting channel ID: {channel_id}"
+                )));
+            }
+        }
+
+        Ok(Response::new(LightingResponse {}))
     }
 
-    async fn lcd(&self, _request: Request<LcdRequest>) -> Result<Response<LcdResponse>, Status> {
-        Err(Status::unimplemented("No LCD Channels"))
+    async fn lcd(&self, request: Request<LcdRequest>) -> Result<Response<LcdResponse>, Status> {
+        self.activity.record();
+
+        let request = request.into_inner();
+        let (DISPLAY_CHANNEL_ID, Some(display)) = (request.channel_id.as_str(), &self.display)
+        else {
+            return Err(Status::invalid_argument(format!(
+                "Unknown LCD channel ID: {}",
+                request.channel_id
+            )));
+        };
+        let setting = request.setting.unwrap_or_default();
+
+        if setting.mode != LCD_MODE_BRIGHTNESS {
+            return Err(Status::invalid_argument(format!(
+                "Unknown LCD mode: {}",
+                setting.mode
+            )));
+        }
+        let brightness = match setting.brightness.map(u8::try_from) {
+            Some(Ok(brightness)) if brightness <= 100 => brightness,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Brightness must be given as 0-100%",
+                ));
+            }
+        };
+
+        let display = display.clone();
+        self.io
+            .with_sysfs("the display brightness", move || {
+                display.set_brightness(brightness)
+            })
+            .await?;
+        debug!("Set display brightness to {brightness}%");
+
+        Ok(Response::new(LcdResponse {}))
     }
 
     async fn custom_function_one(
         &self,
         _request: Request<CustomFunctionOneRequest>,
     ) -> Result<Response<CustomFunctionOneResponse>, Status> {
-        Err(Status::unimplemented("No Custom Function"))
+        // Both the request and response of this function are empty messages in
+        // the device service protocol, so it cannot be used as a passthrough
+        // for anything that needs arguments or returns a value, such as reading
+        // or writing a TDP rail or returning the driver access totals. Those
+        // totals are logged on shutdown instead. Toggling needs neither, so it
+        // switches who owns the fan and power policy.
+        self.activity.record();
+
+        let result = self
+            .with_io_initialized(|backend| backend.get_mode_enable().map_err(driver_status))
+            .await;
+        self.health.record(&result);
+        self.set_mode_enable(!result?).await?;
+
+        Ok(Response::new(CustomFunctionOneResponse {}))
     }
 }
 
@@ -230,14 +1975,363 @@ impl Drop for TuxedoService {
     fn drop(&mut self) {
         // Ensure that fan control is always relinquished to the firmware when we
         // stop controlling it, even if a proper shutdown sequence did not occur.
-        if let Some(tuxedo_io) = self.tuxedo_io.blocking_lock().take() {
-            let _ = tuxedo_io.set_fans_auto();
+        self.release_fans_now();
+    }
+}
+
+fn fan_channel_id(fan: Fan) -> &'static str {
+    match fan {
+        Fan::Fan1 => FAN_1_CHANNEL_ID,
+        Fan::Fan2 => FAN_2_CHANNEL_ID,
+    }
+}
+
+/// Check whether a channel is only advertised for information, and cannot be
+/// changed through a request such as `fixed_duty`.
+fn is_read_only_channel(channel_id: &str) -> bool {
+    TEMP_CHANNELS.iter().any(|(_, id, _)| *id == channel_id)
+}
+
+/// Find the TDP rail controlled by a channel, if it is a power limit channel.
+fn resolve_tdp(channel_id: &str) -> Option<Tdp> {
+    TDP_CHANNELS
+        .into_iter()
+        .find(|(_, id, _)| *id == channel_id)
+        .map(|(tdp, _, _)| tdp)
+}
+
+/// Find the fan controlled by a channel, for a request that changes it.
+fn resolve_writable_fan(channel_id: &str) -> Result<Fan, Status> {
+    if is_read_only_channel(channel_id) {
+        return Err(Status::failed_precondition(format!(
+            "Channel {channel_id} is read-only"
+        )));
+    }
+
+    resolve_fan(channel_id)
+}
+
+/// Find the sensor a speed profile for the given fan should follow. Unless
+/// another is requested, this is the sensor associated with the fan.
+fn resolve_temp_source(temp_source_id: Option<&str>, fan: Fan) -> Result<Fan, Status> {
+    match temp_source_id {
+        None => Ok(fan),
+        Some(temp_source_id) => TEMP_CHANNELS
+            .into_iter()
+            .find(|(_, temp_id, _)| *temp_id == temp_source_id)
+            .map(|(sensor, _, _)| sensor)
+            .ok_or_else(|| {
+                Status::invalid_argument(format!("Unknown temperature source: {temp_source_id}"))
+            }),
+    }
+}
+
+/// Find the fan controlled by a channel.
+fn resolve_fan(channel_id: &str) -> Result<Fan, Status> {
+    Fan::ALL
+        .into_iter()
+        .find(|fan| fan_channel_id(*fan) == channel_id)
+        .ok_or_else(|| Status::invalid_argument(format!("Unknown channel ID: {channel_id}")))
+}
+
+/// Turn an error from the driver into a status whose code and message say what
+/// went wrong, as they are shown to the user by CoolerControl.
+fn driver_status(e: io::Error) -> Status {
+    match e.raw_os_error() {
+        Some(libc::ENOENT) => Status::not_found(format!(
+            "Fan driver not found ({e}), is the {DRIVER_MODULE} kernel module loaded?"
+        )),
+        Some(libc::EACCES | libc::EPERM) => Status::permission_denied(format!(
+            "Not allowed to access the fan driver ({e}), the plugin needs to run as root"
+        )),
+        Some(libc::EBUSY | libc::EAGAIN) => {
+            Status::unavailable(format!("Fan driver is busy ({e}), try again later"))
+        }
+        Some(libc::ENODEV | libc::EIO) => Status::unavailable(format!(
+            "Fan driver stopped responding ({e}), reconnecting to it"
+        )),
+        Some(libc::ENOTTY | libc::EOPNOTSUPP) => Status::unimplemented(format!(
+            "Not supported by the fan driver on this device ({e})"
+        )),
+        _ => match e.kind() {
+            io::ErrorKind::NotFound => Status::not_found(e.to_string()),
+            io::ErrorKind::PermissionDenied => Status::permission_denied(e.to_string()),
+            io::ErrorKind::TimedOut => Status::deadline_exceeded(e.to_string()),
+            io::ErrorKind::Unsupported => Status::unimplemented(e.to_string()),
+            _ => Status::internal(format!("Fan driver error: {e}")),
+        },
+    }
+}
+
+/// Turn an error from writing a sysfs attribute into a status, like
+/// [`driver_status`] does for the driver.
+fn sysfs_status(what: &str, e: io::Error) -> Status {
+    match e.kind() {
+        io::ErrorKind::NotFound => Status::not_found(format!("Can't find {what} anymore ({e})")),
+        io::ErrorKind::PermissionDenied => Status::permission_denied(format!(
+            "Not allowed to change {what} ({e}), the plugin needs to run as root"
+        )),
+        io::ErrorKind::InvalidInput => {
+            Status::invalid_argument(format!("The value was rejected for {what} ({e})"))
+        }
+        io::ErrorKind::ResourceBusy => Status::unavailable(format!(
+            "Can't change {what} right now ({e}), try again later"
+        )),
+        io::ErrorKind::TimedOut => Status::deadline_exceeded(e.to_string()),
+        io::ErrorKind::Unsupported => Status::unimplemented(e.to_string()),
+        _ => Status::internal(format!("Failed to change {what}: {e}")),
+    }
+}
+
+/// Set the speed of a fan, raising it to the hardware minimum if needed.
+fn write_fan_speed(backend: &dyn FanBackend, fan: Fan, duty: f64) -> Result<(), Status> {
+    let duty = hardware_duty(backend, fan, duty)?;
+    send_fan_speed(backend, fan, duty).map(drop)
+}
+
+/// Get the duty a fan will actually be set to for a requested duty, once the
+/// hardware minimum is taken into account.
+fn hardware_duty(backend: &dyn FanBackend, fan: Fan, duty: f64) -> Result<f64, Status> {
+    let channel = fan_channel_id(fan);
+
+    // When the fans can be stopped the channels advertise a minimum of 0%, so
+    // anything between off and the hardware minimum has to be raised to it.
+    // Without fans-off support, 0% means as slow as the fans may go instead.
+    Ok(if duty == 0.0 && backend.fans_off_available() {
+        0.0
+    } else {
+        let min_speed = backend.get_fan_min_speed().map_err(driver_status)?.into();
+
+        if duty < min_speed {
+            debug!(
+                "Raising requested speed {duty}% for {channel} to hardware minimum of {min_speed}%"
+            );
+            min_speed
+        } else {
+            duty
+        }
+    })
+}
+
+/// Set a fan to a duty that the hardware accepts, returning the raw value
+/// written. The outcome is logged with structured fields that end up in the
+/// journal.
+fn send_fan_speed(backend: &dyn FanBackend, fan: Fan, duty: f64) -> Result<i32, Status> {
+    let channel = fan_channel_id(fan);
+
+    let what = format!("Setting {channel}");
+
+    match backend.set_fan_speed(fan, duty) {
+        Ok(raw) => {
+            log_limit::success(&what);
+            debug!(
+                channel,
+                duty, raw, "Set {channel} to {duty}% (raw value {raw})"
+            );
+            Ok(raw)
+        }
+        Err(e) => {
+            if let Some(repeats) = log_limit::failure(&what) {
+                let errno = e.raw_os_error();
+                warn!(
+                    channel,
+                    duty, errno, "Failed to set {channel} to {duty}%: {e}{repeats}"
+                );
+            }
+            Err(driver_status(e))
+        }
+    }
+}
+
+/// Apply a lighting setting to the keyboard backlight.
+async fn set_keyboard_lighting(
+    io: &IoHandle,
+    keyboard: &Arc<KeyboardBacklight>,
+    setting: &LightingSetting,
+) -> Result<(), Status> {
+    // The color to set, or `None` to turn the backlight off.
+    let color = match setting.mode.as_str() {
+        LIGHTING_MODE_OFF => None,
+        LIGHTING_MODE_FIXED => {
+            let [color] = setting.colors.as_slice() else {
+                return Err(Status::invalid_argument(
+                    "Fixed mode requires exactly one color",
+                ));
+            };
+            let channel = |value: u32| value.min(u8::MAX.into()) as u8;
+
+            Some((channel(color.r), channel(color.g), channel(color.b)))
+        }
+        mode => {
+            return Err(Status::invalid_argument(format!(
+                "Unknown lighting mode: {mode}"
+            )));
+        }
+    };
+
+    let keyboard = keyboard.clone();
+    io.with_sysfs("the keyboard backlight", move || match color {
+        Some((r, g, b)) => keyboard.set_color(r, g, b),
+        None => keyboard.set_off(),
+    })
+    .await
+}
+
+/// Set the power limit of a TDP rail, after checking it against the range
+/// reported by the firmware.
+fn write_tdp(backend: &dyn FanBackend, tdp: Tdp, watts: i32) -> Result<(), Status> {
+    let channel = TDP_CHANNELS[tdp as usize].1;
+    let Some((min_watts, max_watts)) = backend.get_tdp_bounds(tdp) else {
+        return Err(Status::failed_precondition(format!(
+            "Power limit {channel} is not supported by this device"
+        )));
+    };
+
+    let watts = match u32::try_from(watts) {
+        Ok(watts) if (min_watts..=max_watts).contains(&watts) => watts,
+        _ => {
+            return Err(Status::invalid_argument(format!(
+                "Power limit {watts} W for {channel} is outside of {min_watts}-{max_watts} W"
+            )));
+        }
+    };
+
+    let what = format!("Setting {channel}");
+
+    match backend.set_tdp(tdp, watts) {
+        Ok(()) => {
+            log_limit::success(&what);
+            debug!(channel, watts, "Set {channel} to {watts} W");
+            Ok(())
+        }
+        Err(e) => {
+            if let Some(repeats) = log_limit::failure(&what) {
+                let errno = e.raw_os_error();
+                warn!(
+                    channel,
+                    watts, errno, "Failed to set {channel} to {watts} W: {e}{repeats}"
+                );
+            }
+            Err(driver_status(e))
+        }
+    }
+}
+
+/// Apply power settings again, either those from before the service changed
+/// them or the last ones it applied.
+///
+/// The firmware only reports its mode rather than the active performance
+/// profile, so the profile can only be restored if the mode matches one.
+fn restore_power_settings(backend: &dyn FanBackend, original: &PowerSettings) {
+    // This comes first, as some firmware ignores power limits without it.
+    if let Some(enabled) = original.mode_enable {
+        match backend.set_mode_enable(enabled) {
+            Ok(()) => info!("Restored mode enable to {enabled}"),
+            Err(e) => warn!("Failed to restore mode enable to {enabled}: {e}"),
+        }
+    }
+
+    for tdp in Tdp::ALL {
+        let Some(watts) = original.tdp[tdp as usize] else {
+            continue;
+        };
+        let channel = TDP_CHANNELS[tdp as usize].1;
+
+        match backend.set_tdp(tdp, watts) {
+            Ok(()) => info!("Restored {channel} to {watts} W"),
+            Err(e) => warn!("Failed to restore {channel} to {watts} W: {e}"),
+        }
+    }
+
+    if let Some(mode) = original.mode {
+        match PERFORMANCE_PROFILES
+            .into_iter()
+            .find(|(profile, _, _)| *profile as i32 == mode)
+        {
+            Some((profile, _, _)) => match backend.set_performance_profile(profile) {
+                Ok(()) => info!("Restored {profile:?} performance profile"),
+                Err(e) => warn!("Failed to restore {profile:?} performance profile: {e}"),
+            },
+            None => warn!(
+                "Not restoring the performance profile, firmware mode {mode} does not match one"
+            ),
         }
     }
 }
 
-fn get_device(tuxedo_io: &TuxedoIo) -> io::Result<Device> {
-    let min_duty = tuxedo_io.get_fan_min_speed()?.into();
+/// Combine the fan floor with the floors of individual fans.
+fn fan_floors(fan_floor: Option<u8>, channel_floors: &HashMap<String, u8>) -> [Option<u8>; 2] {
+    Fan::ALL.map(|fan| fan_floor.max(channel_floors.get(fan_channel_id(fan)).copied()))
+}
+
+/// Values firmware vendors leave in DMI fields they did not fill in.
+const DMI_PLACEHOLDERS: [&str; 4] = [
+    "default string",
+    "not applicable",
+    "system serial number",
+    "to be filled by o.e.m.",
+];
+
+/// Find a stable identifier of the machine in its DMI data.
+///
+/// These can only be read by root, so this must be called before dropping
+/// privileges.
+pub fn machine_uid() -> Option<String> {
+    choose_machine_uid(Product::uuid(), Product::serial_number())
+}
+
+/// Pick the product UUID or else the serial number as the identifier of the
+/// machine, skipping placeholders.
+fn choose_machine_uid(uuid: Option<String>, serial_number: Option<String>) -> Option<String> {
+    let usable = |value: &String| {
+        !value.is_empty() && !DMI_PLACEHOLDERS.contains(&value.to_ascii_lowercase().as_str())
+    };
+    // Some firmware repeats one digit throughout the UUID, such as all zeros.
+    let uuid = uuid.map(|uuid| uuid.trim().to_owned()).filter(|uuid| {
+        let mut digits = uuid.chars().filter(char::is_ascii_hexdigit);
+        let first = digits.next();
+        first.is_some() && !digits.all(|digit| Some(digit) == first)
+    });
+
+    uuid.filter(usable)
+        .or_else(|| serial_number.map(|serial| serial.trim().to_owned()))
+        .filter(usable)
+}
+
+/// Hardware besides the fans and power limits that the service can control.
+struct Peripherals {
+    keyboard: bool,
+    charge_limit: bool,
+    display: bool,
+}
+
+/// What identifies the device and the software driving it, beyond what the
+/// backend reports.
+struct DeviceIdentity {
+    uid_info: Option<String>,
+    firmware: FirmwareInfo,
+
+    /// Paths used to access the hardware.
+    locations: Vec<String>,
+}
+
+fn get_device(
+    backend: &dyn FanBackend,
+    fan_floors: [Option<u8>; 2],
+    labels: &HashMap<String, String>,
+    peripherals: Peripherals,
+    identity: DeviceIdentity,
+) -> io::Result<Device> {
+    let hardware_min = if backend.fans_off_available() {
+        0
+    } else {
+        backend.get_fan_min_speed()?
+    };
+    let min_duty = |fan: Fan| {
+        hardware_min
+            .max(fan_floors[fan as usize].unwrap_or_default())
+            .into()
+    };
     let max_duty = 100;
 
     let mut channels = HashMap::new();
@@ -247,7 +2341,7 @@ fn get_device(tuxedo_io: &TuxedoIo) -> io::Result<Device> {
         ChannelInfo {
             label: Some("Fan 1".into()),
             options: Some(Options::SpeedOptions(SpeedOptions {
-                min_duty,
+                min_duty: min_duty(Fan::Fan1),
                 max_duty,
                 fixed_enabled: true,
                 ..Default::default()
@@ -260,7 +2354,7 @@ fn get_device(tuxedo_io: &TuxedoIo) -> io::Result<Device> {
         ChannelInfo {
             label: Some("Fan 2".into()),
             options: Some(Options::SpeedOptions(SpeedOptions {
-                min_duty,
+                min_duty: min_duty(Fan::Fan2),
                 max_duty,
                 fixed_enabled: true,
                 ..Default::default()
@@ -268,13 +2362,157 @@ fn get_device(tuxedo_io: &TuxedoIo) -> io::Result<Device> {
         },
     );
 
+    // Power limits are only advertised for rails that report a usable range,
+    // and are in watts rather than percent.
+    for (tdp, channel_id, label) in TDP_CHANNELS {
+        if let Some((min_watts, max_watts)) = backend.get_tdp_bounds(tdp) {
+            channels.insert(
+                channel_id.into(),
+                ChannelInfo {
+                    label: Some(label.into()),
+                    options: Some(Options::SpeedOptions(SpeedOptions {
+                        min_duty: min_watts,
+                        max_duty: max_watts,
+                        fixed_enabled: true,
+                        ..Default::default()
+                    })),
+                },
+            );
+        }
+    }
+
+    if peripherals.charge_limit {
+        channels.insert(
+            CHARGE_LIMIT_CHANNEL_ID.into(),
+            ChannelInfo {
+                label: Some("Battery Charge Limit".into()),
+                options: Some(Options::SpeedOptions(SpeedOptions {
+                    min_duty: MIN_CHARGE_LIMIT.into(),
+                    max_duty: 100,
+                    fixed_enabled: true,
+                    ..Default::default()
+                })),
+            },
+        );
+    }
+
+    if peripherals.display {
+        channels.insert(
+            DISPLAY_CHANNEL_ID.into(),
+            ChannelInfo {
+                label: Some("Display".into()),
+                options: Some(Options::LcdInfo(LcdInfo {
+                    lcd_modes: vec![LcdModes {
+                        name: LCD_MODE_BRIGHTNESS.into(),
+                        frontend_name: Some("Brightness".into()),
+                        brightness: true,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })),
+            },
+        );
+    }
+
+    if peripherals.keyboard {
+        channels.insert(
+            KEYBOARD_CHANNEL_ID.into(),
+            ChannelInfo {
+                label: Some("Keyboard".into()),
+                options: Some(Options::LightingModes(LightingModes {
+                    lighting_mode: vec![
+                        LightingMode {
+                            name: LIGHTING_MODE_OFF.into(),
+                            frontend_name: Some("Off".into()),
+                            min_colors: 0,
+                            max_colors: 0,
+                            ..Default::default()
+                        },
+                        LightingMode {
+                            name: LIGHTING_MODE_FIXED.into(),
+                            frontend_name: Some("Fixed".into()),
+                            min_colors: 1,
+                            max_colors: 1,
+                            ..Default::default()
+                        },
+                    ],
+                })),
+            },
+        );
+    }
+
+    if backend.performance_profiles_available() {
+        channels.insert(
+            PERFORMANCE_CHANNEL_ID.into(),
+            ChannelInfo {
+                label: Some("Performance Profile".into()),
+                options: Some(Options::LightingModes(LightingModes {
+                    lighting_mode: PERFORMANCE_PROFILES
+                        .into_iter()
+                        .map(|(_, name, label)| LightingMode {
+                            name: name.into(),
+                            frontend_name: Some(label.into()),
+                            ..Default::default()
+                        })
+                        .collect(),
+                })),
+            },
+        );
+    }
+
+    let mut temps: HashMap<_, _> = TEMP_CHANNELS
+        .into_iter()
+        .zip(1..)
+        .map(|((_, temp_id, label), number)| {
+            (
+                temp_id.into(),
+                TempInfo {
+                    label: label.into(),
+                    number,
+                },
+            )
+        })
+        .collect();
+
+    for (id, label) in labels {
+        if let Some(channel) = channels.get_mut(id) {
+            channel.label = Some(label.clone());
+        } else if let Some(temp) = temps.get_mut(id) {
+            temp.label = label.clone();
+        } else {
+            warn!("Ignoring label for unknown channel {id:?}");
+        }
+    }
+
     Ok(Device {
         id: DEVICE_ID.into(),
-        name: Product::name().unwrap_or_else(|| DEFAULT_DEVICE_NAME.into()),
-        uid_info: None,
+        name: device_name(Product::name(), &backend.model()),
+        uid_info: identity.uid_info,
         info: Some(DeviceInfo {
             channels,
+            temps,
+            model: Some(match identity.firmware.versions() {
+                Some(versions) => format!("{} ({versions})", backend.model()),
+                None => backend.model(),
+            }),
+            driver_info: Some(DriverInfo {
+                name: Some(DRIVER_MODULE.into()),
+                version: identity.firmware.driver_version,
+                locations: identity.locations,
+            }),
             ..Default::default()
         }),
     })
 }
+
+/// Name the device after the product, if the firmware reports one, and the
+/// hardware interface in use.
+fn device_name(product: Option<String>, model: &str) -> String {
+    format!(
+        "{} ({model})",
+        product.as_deref().unwrap_or(DEFAULT_DEVICE_NAME)
+    )
+}
+
+#[cfg(test)]
+mod tests;
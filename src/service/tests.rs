@@ -0,0 +1,2255 @@
+use super::*;
+use crate::device_service::v1::{
+    LcdSetting, Rgb, SpeedProfilePoint, device_service_client::DeviceServiceClient,
+    device_service_server::DeviceServiceServer, health_response,
+};
+use crate::faults::Faults;
+use crate::mock::MockBackend;
+use std::sync::Mutex as StdMutex;
+use tokio::net::UnixListener;
+use tonic::{
+    Code,
+    codegen::tokio_stream::wrappers::UnixListenerStream,
+    transport::{Channel, Server},
+};
+
+fn fixed_duty_request(channel_id: &str, duty: i32) -> Request<FixedDutyRequest> {
+    Request::new(FixedDutyRequest {
+        device_id: DEVICE_ID.into(),
+        channel_id: channel_id.into(),
+        duty,
+    })
+}
+
+fn speed_options(device: &Device, channel_id: &str) -> SpeedOptions {
+    match &device.info.as_ref().unwrap().channels[channel_id].options {
+        Some(Options::SpeedOptions(options)) => *options,
+        other => panic!("unexpected options for {channel_id}: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn list_devices_reports_both_fans() {
+    let backend = MockBackend::default();
+    backend.state().min_speed = 25;
+    let service = backend.service();
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(response.devices.len(), 1);
+    let device = &response.devices[0];
+    assert_eq!(device.id, DEVICE_ID);
+    assert_eq!(device.info.as_ref().unwrap().channels.len(), 3);
+
+    for channel_id in [FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID] {
+        let options = speed_options(device, channel_id);
+        assert_eq!(options.min_duty, 25);
+        assert_eq!(options.max_duty, 100);
+        assert!(options.fixed_enabled);
+    }
+}
+
+#[test]
+fn device_name_includes_interface() {
+    assert_eq!(
+        device_name(
+            Some("InfinityBook Pro 14 Gen10".into()),
+            "Uniwill, hwcheck code 1"
+        ),
+        "InfinityBook Pro 14 Gen10 (Uniwill, hwcheck code 1)"
+    );
+    assert_eq!(
+        device_name(None, "Mock"),
+        format!("{DEFAULT_DEVICE_NAME} (Mock)")
+    );
+}
+
+#[tokio::test]
+async fn list_devices_reports_machine_uid() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        uid_info: Some("407488fe-960a-43b5-a265-8fd0e9200b8f".into()),
+        ..Default::default()
+    });
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(
+        response.devices[0].uid_info.as_deref(),
+        Some("407488fe-960a-43b5-a265-8fd0e9200b8f")
+    );
+}
+
+#[tokio::test]
+async fn list_devices_reports_firmware_versions() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        firmware: FirmwareInfo {
+            bios_version: Some("1.07.04".into()),
+            ec_version: Some("1.4".into()),
+            driver_version: Some("0.3.6".into()),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let info = response.devices[0].info.as_ref().unwrap();
+
+    assert_eq!(
+        info.model.as_deref(),
+        Some("Mock (BIOS 1.07.04, EC 1.4, tuxedo_io 0.3.6)")
+    );
+    let driver_info = info.driver_info.as_ref().unwrap();
+    assert_eq!(driver_info.version.as_deref(), Some("0.3.6"));
+    assert_eq!(driver_info.locations, [DEFAULT_DEVICE_PATH]);
+}
+
+#[test]
+fn machine_uid_skips_placeholders() {
+    let uuid = "407488fe-960a-43b5-a265-8fd0e9200b8f";
+
+    assert_eq!(
+        choose_machine_uid(Some(uuid.into()), Some("N123".into())).as_deref(),
+        Some(uuid)
+    );
+    assert_eq!(
+        choose_machine_uid(
+            Some("00000000-0000-0000-0000-000000000000".into()),
+            Some(" N123 ".into())
+        )
+        .as_deref(),
+        Some("N123")
+    );
+    assert_eq!(
+        choose_machine_uid(
+            Some("FFFFFFFF-FFFF-FFFF-FFFF-FFFFFFFFFFFF".into()),
+            Some("To Be Filled By O.E.M.".into())
+        ),
+        None
+    );
+    assert_eq!(choose_machine_uid(None, None), None);
+}
+
+#[tokio::test]
+async fn list_devices_reports_temperature_sensors() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let temps = &response.devices[0].info.as_ref().unwrap().temps;
+    assert_eq!(temps.len(), 2);
+    assert_eq!(temps["temp_cpu"].label, "CPU");
+    assert_eq!(temps["temp_cpu"].number, 1);
+    assert_eq!(temps["temp_gpu"].label, "GPU");
+    assert_eq!(temps["temp_gpu"].number, 2);
+}
+
+#[tokio::test]
+async fn list_devices_uses_configured_labels() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        labels: HashMap::from([
+            (FAN_1_CHANNEL_ID.into(), "CPU Fan".into()),
+            ("temp_gpu".into(), "dGPU".into()),
+            ("bogus".into(), "Bogus".into()),
+        ]),
+        ..Default::default()
+    });
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let info = response.devices[0].info.as_ref().unwrap();
+    assert_eq!(
+        info.channels[FAN_1_CHANNEL_ID].label.as_deref(),
+        Some("CPU Fan")
+    );
+    assert_eq!(
+        info.channels[FAN_2_CHANNEL_ID].label.as_deref(),
+        Some("Fan 2")
+    );
+    assert_eq!(info.temps["temp_gpu"].label, "dGPU");
+    assert!(!info.channels.contains_key("bogus"));
+}
+
+#[tokio::test]
+async fn list_devices_allows_stopping_fans_when_supported() {
+    let backend = MockBackend::default();
+    backend.state().min_speed = 25;
+    backend.state().fans_off_available = true;
+    let service = backend.service();
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    for channel_id in [FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID] {
+        assert_eq!(speed_options(&response.devices[0], channel_id).min_duty, 0);
+    }
+}
+
+#[tokio::test]
+async fn list_devices_reports_supported_tdp_rails() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, Some((25, 60))];
+    let service = backend.service();
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let device = &response.devices[0];
+    assert_eq!(device.info.as_ref().unwrap().channels.len(), 5);
+    assert!(!device.info.as_ref().unwrap().channels.contains_key("tdp1"));
+
+    let options = speed_options(device, "tdp0");
+    assert_eq!((options.min_duty, options.max_duty), (10, 45));
+    let options = speed_options(device, "tdp2");
+    assert_eq!((options.min_duty, options.max_duty), (25, 60));
+}
+
+#[tokio::test]
+async fn list_devices_omits_performance_profile_when_unsupported() {
+    let backend = MockBackend::default();
+    backend.state().no_performance_profiles = true;
+    let service = backend.service();
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let channels = &response.devices[0].info.as_ref().unwrap().channels;
+    assert!(channels.contains_key(FAN_1_CHANNEL_ID));
+    assert!(!channels.contains_key(PERFORMANCE_CHANNEL_ID));
+}
+
+#[tokio::test]
+async fn status_reports_both_fan_speeds() {
+    let backend = MockBackend::default();
+    backend.state().speeds = [40.0, 60.0];
+    let service = backend.service();
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let duties = response
+        .status
+        .iter()
+        .filter_map(|status| match &status.metric {
+            Some(models::v1::status::Metric::Speed(speed)) => {
+                Some((status.id.as_str(), speed.duty))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        duties,
+        [
+            (FAN_1_CHANNEL_ID, Some(40.0)),
+            (FAN_2_CHANNEL_ID, Some(60.0))
+        ]
+    );
+}
+
+#[tokio::test]
+async fn status_reuses_recent_reads() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        status_ttl: Duration::from_millis(50),
+        ..Default::default()
+    });
+    let status = || {
+        service.status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+    };
+
+    let (first, second) = tokio::join!(status(), status());
+    first.unwrap();
+    second.unwrap();
+    assert_eq!(service.io_stats().reads, 1);
+
+    sleep(Duration::from_millis(60)).await;
+    status().await.unwrap();
+    assert_eq!(service.io_stats().reads, 2);
+}
+
+#[tokio::test]
+async fn status_reports_supported_power_limits() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, Some((25, 60))];
+    backend.state().tdp = [35, 40, 55];
+    let service = backend.service();
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let watts = response
+        .status
+        .iter()
+        .filter_map(|status| match &status.metric {
+            Some(models::v1::status::Metric::Watts(watts)) => Some((status.id.as_str(), *watts)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(watts, [("tdp0", 35.0), ("tdp2", 55.0)]);
+}
+
+#[tokio::test]
+async fn status_reports_temperatures() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [65, 48];
+    let service = backend.service();
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let temps = response
+        .status
+        .iter()
+        .filter_map(|status| match &status.metric {
+            Some(models::v1::status::Metric::Temp(temp)) => Some((status.id.as_str(), *temp)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(temps, [("temp_cpu", 65.0), ("temp_gpu", 48.0)]);
+}
+
+#[tokio::test]
+async fn status_holds_values_within_deadband() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        status_ttl: Duration::ZERO,
+        status_filters: FilterSettings {
+            duty_deadband: 2.0,
+            temp_deadband: 2.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let status = || async {
+        let response = service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let temp = response
+            .status
+            .iter()
+            .find_map(|status| match &status.metric {
+                Some(models::v1::status::Metric::Temp(temp)) => Some(*temp),
+                _ => None,
+            })
+            .unwrap();
+
+        (status_speeds(&response)[0].duty.unwrap(), temp)
+    };
+
+    backend.state().speeds = [40.0, 0.0];
+    backend.state().temperatures = [60, 50];
+    assert_eq!(status().await, (40.0, 60.0));
+
+    backend.state().speeds = [41.0, 0.0];
+    backend.state().temperatures = [61, 50];
+    assert_eq!(status().await, (40.0, 60.0));
+
+    backend.state().speeds = [43.0, 0.0];
+    backend.state().temperatures = [62, 50];
+    assert_eq!(status().await, (43.0, 62.0));
+}
+
+#[tokio::test]
+async fn status_averages_readings() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        status_ttl: Duration::ZERO,
+        status_filters: FilterSettings {
+            duty_alpha: 0.5,
+            temp_alpha: 0.25,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let status = || async {
+        let response = service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let temp = response
+            .status
+            .iter()
+            .find_map(|status| match &status.metric {
+                Some(models::v1::status::Metric::Temp(temp)) => Some(*temp),
+                _ => None,
+            })
+            .unwrap();
+
+        (status_speeds(&response)[0].duty.unwrap(), temp)
+    };
+
+    backend.state().speeds = [40.0, 0.0];
+    backend.state().temperatures = [60, 50];
+    assert_eq!(status().await, (40.0, 60.0));
+
+    backend.state().speeds = [60.0, 0.0];
+    backend.state().temperatures = [68, 50];
+    assert_eq!(status().await, (50.0, 62.0));
+
+    assert_eq!(status().await, (55.0, 63.5));
+}
+
+#[tokio::test]
+async fn status_estimates_rpm_without_hwmon() {
+    let backend = MockBackend::default();
+    backend.state().speeds = [40.0, 0.0];
+    let status = |service: TuxedoService| async move {
+        let response = service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        status_speeds(&response)
+            .iter()
+            .map(|speed| speed.rpm)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(status(backend.service()).await, [None, None]);
+
+    let service = backend.service_with(ServiceConfig {
+        max_rpm: Some(5000),
+        ..Default::default()
+    });
+    assert_eq!(status(service).await, [Some(2000), Some(0)]);
+}
+
+fn status_speeds(response: &StatusResponse) -> Vec<FanSpeed> {
+    response
+        .status
+        .iter()
+        .filter_map(|status| match &status.metric {
+            Some(models::v1::status::Metric::Speed(speed)) => Some(*speed),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn status_reports_stopped_fans_as_off() {
+    let backend = MockBackend::default();
+    backend.state().fans_off_available = true;
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 0))
+        .await
+        .unwrap();
+    service
+        .fixed_duty(fixed_duty_request(FAN_2_CHANNEL_ID, 20))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    // The EC reports a small speed even though the first fan has stopped.
+    backend.state().speeds = [4.0, 20.0];
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let speeds = status_speeds(&response);
+    assert_eq!(speeds[0].duty, Some(0.0));
+    assert_eq!(speeds[0].rpm, Some(0));
+    assert_eq!(speeds[1].duty, Some(20.0));
+    assert_eq!(speeds[1].rpm, None);
+}
+
+#[tokio::test]
+async fn status_reports_raw_speed_without_fans_off_support() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 0))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    // Without fans-off support the EC keeps the fan at its minimum speed.
+    backend.state().speeds = [15.0, 0.0];
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(status_speeds(&response)[0].duty, Some(15.0));
+}
+
+#[tokio::test]
+async fn status_reports_rpm_from_hwmon() {
+    let dir = std::env::temp_dir().join(format!("tuxedo-hwmon-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("fan1_input"), "2400\n").unwrap();
+
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        hwmon: Some(Hwmon::new(&dir)),
+        ..Default::default()
+    });
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rpms = response
+        .status
+        .iter()
+        .filter_map(|status| match &status.metric {
+            Some(models::v1::status::Metric::Speed(speed)) => Some(speed.rpm),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rpms, [Some(2400), None]);
+}
+
+#[tokio::test]
+async fn fixed_duty_sets_the_requested_fan() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 30))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [30.0, 0.0]);
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_2_CHANNEL_ID, 70))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [30.0, 70.0]);
+}
+
+#[tokio::test]
+async fn fixed_duty_raises_speed_to_floor() {
+    let backend = MockBackend::default();
+    backend.state().min_speed = 20;
+    let service = backend.service_with(ServiceConfig {
+        fan_floor: Some(30),
+        ..Default::default()
+    });
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 10))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 30.0);
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(
+        speed_options(&response.devices[0], FAN_1_CHANNEL_ID).min_duty,
+        30
+    );
+}
+
+#[tokio::test]
+async fn channel_floor_raises_speed_of_one_fan() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        fan_floor: Some(20),
+        channel_floors: HashMap::from([(FAN_2_CHANNEL_ID.into(), 35)]),
+        ..Default::default()
+    });
+
+    for channel_id in [FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID] {
+        service
+            .fixed_duty(fixed_duty_request(channel_id, 10))
+            .await
+            .unwrap();
+    }
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [20.0, 35.0]);
+
+    let response = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    let device = &response.devices[0];
+    assert_eq!(speed_options(device, FAN_1_CHANNEL_ID).min_duty, 20);
+    assert_eq!(speed_options(device, FAN_2_CHANNEL_ID).min_duty, 35);
+}
+
+#[tokio::test]
+async fn reconfigure_changes_the_floor_of_later_writes() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        fan_floor: Some(30),
+        ..Default::default()
+    });
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 10))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 30.0);
+
+    service.reconfigure(
+        None,
+        &HashMap::new(),
+        Duration::ZERO,
+        0.0,
+        FilterSettings::default(),
+        HashMap::new(),
+    );
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 10))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 10.0);
+}
+
+#[tokio::test]
+async fn fixed_duty_returns_before_the_write_and_coalesces() {
+    let backend = MockBackend::default();
+    backend.state().write_delay = Duration::from_millis(100);
+    let service = backend.service();
+
+    let start = Instant::now();
+    for duty in [30, 40, 50] {
+        service
+            .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, duty))
+            .await
+            .unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    // Speeds requested while an earlier one is waiting to be written replace
+    // it, so the fan does not step through all of them.
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [50.0, 0.0]);
+    assert!(service.io_stats().writes < 3);
+}
+
+#[tokio::test]
+async fn fixed_duty_ramps_towards_requested_speed() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        ramp_rate: 100.0,
+        ..Default::default()
+    });
+
+    // There is nothing to ramp from until a speed has been set.
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 20))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 20.0);
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 60))
+        .await
+        .unwrap();
+    sleep(Duration::from_millis(50)).await;
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 45.0);
+
+    // CoolerControl is told about the speed it asked for.
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(status_speeds(&response)[0].duty, Some(60.0));
+
+    sleep(Duration::from_millis(300)).await;
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 60.0);
+}
+
+#[tokio::test]
+async fn fixed_duty_writes_different_fans_concurrently() {
+    let backend = MockBackend::default();
+    backend.state().write_delay = Duration::from_millis(200);
+    let service = backend.service();
+
+    let start = Instant::now();
+    let (fan1, fan2) = tokio::join!(
+        service.fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 30)),
+        service.fixed_duty(fixed_duty_request(FAN_2_CHANNEL_ID, 70)),
+    );
+    fan1.unwrap();
+    fan2.unwrap();
+    settle(&service).await;
+    let elapsed = start.elapsed();
+
+    assert_eq!(backend.state().speeds, [30.0, 70.0]);
+    assert!(elapsed < Duration::from_millis(350), "took {elapsed:?}");
+}
+
+#[tokio::test]
+async fn fixed_duty_skips_writes_that_change_nothing() {
+    let backend = MockBackend::default();
+    let service = &backend.service();
+    let set_fan1 = move |duty| async move {
+        service
+            .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, duty))
+            .await
+            .unwrap();
+        settle(service).await;
+    };
+
+    set_fan1(50).await;
+    set_fan1(50).await;
+    assert_eq!(service.io_stats().writes, 1);
+
+    set_fan1(60).await;
+    assert_eq!(service.io_stats().writes, 2);
+
+    // After a reset the fan has to be set again.
+    service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap();
+    set_fan1(60).await;
+    assert_eq!(backend.state().speeds[0], 60.0);
+}
+
+#[test]
+fn resolve_fan_maps_every_fan_channel() {
+    assert!(matches!(resolve_fan(FAN_1_CHANNEL_ID), Ok(Fan::Fan1)));
+    assert!(matches!(resolve_fan(FAN_2_CHANNEL_ID), Ok(Fan::Fan2)));
+
+    for channel_id in ["fan3", "tdp0", ""] {
+        let status = resolve_fan(channel_id).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
+
+#[tokio::test]
+async fn fixed_duty_stops_fans_only_when_supported() {
+    let backend = MockBackend::default();
+    backend.state().min_speed = 25;
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 0))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [25.0, 0.0]);
+
+    backend.state().fans_off_available = true;
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 0))
+        .await
+        .unwrap();
+    service
+        .fixed_duty(fixed_duty_request(FAN_2_CHANNEL_ID, 10))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [0.0, 25.0]);
+}
+
+#[tokio::test]
+async fn fixed_duty_validates_duty_range() {
+    let backend = MockBackend::default();
+    backend.state().min_speed = 25;
+    let service = backend.service();
+
+    for duty in [101, -1] {
+        let status = service
+            .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, duty))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 10))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [25.0, 0.0]);
+}
+
+#[tokio::test]
+async fn fixed_duty_rejects_unknown_channel() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    let status = service
+        .fixed_duty(fixed_duty_request("fan3", 50))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(backend.state().speeds, [0.0, 0.0]);
+}
+
+#[tokio::test]
+async fn fixed_duty_rejects_read_only_channel() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    let status = service
+        .fixed_duty(fixed_duty_request("temp_cpu", 20))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert_eq!(backend.state().speeds, [0.0, 0.0]);
+}
+
+fn speed_profile_request(
+    channel_id: &str,
+    temp_source_id: Option<&str>,
+    points: &[(f64, u32)],
+) -> Request<SpeedProfileRequest> {
+    Request::new(SpeedProfileRequest {
+        device_id: DEVICE_ID.into(),
+        channel_id: channel_id.into(),
+        temp_source_id: temp_source_id.map(Into::into),
+        speed_profile: points
+            .iter()
+            .map(|&(temp, duty)| SpeedProfilePoint { temp, duty })
+            .collect(),
+    })
+}
+
+#[tokio::test]
+async fn speed_profile_interpolates_fan_temperature() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [55, 90];
+    let service = backend.service();
+
+    service
+        .speed_profile(speed_profile_request(
+            FAN_1_CHANNEL_ID,
+            None,
+            &[(80.0, 100), (40.0, 20)],
+        ))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [50.0, 0.0]);
+
+    service
+        .speed_profile(speed_profile_request(
+            FAN_2_CHANNEL_ID,
+            Some("temp_cpu"),
+            &[(40.0, 20), (80.0, 100)],
+        ))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [50.0, 50.0]);
+
+    // Temperatures beyond the curve use the nearest end.
+    backend.state().temperatures = [95, 20];
+    service.apply_profiles().await.unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [100.0, 100.0]);
+}
+
+#[tokio::test]
+async fn speed_profile_keeps_fractional_duty() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [45, 0];
+    let service = backend.service();
+
+    service
+        .speed_profile(speed_profile_request(
+            FAN_1_CHANNEL_ID,
+            None,
+            &[(40.0, 30), (50.0, 45)],
+        ))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    assert_eq!(backend.state().speeds, [37.5, 0.0]);
+}
+
+#[tokio::test]
+async fn fixed_duty_replaces_speed_profile() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [60, 60];
+    let service = backend.service();
+
+    service
+        .speed_profile(speed_profile_request(
+            FAN_1_CHANNEL_ID,
+            None,
+            &[(0.0, 30), (100.0, 30)],
+        ))
+        .await
+        .unwrap();
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 70))
+        .await
+        .unwrap();
+    service.apply_profiles().await.unwrap();
+    settle(&service).await;
+
+    assert_eq!(backend.state().speeds, [70.0, 0.0]);
+}
+
+#[tokio::test]
+async fn speed_profile_rejects_invalid_requests() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    for (request, code) in [
+        (
+            speed_profile_request(FAN_1_CHANNEL_ID, None, &[]),
+            Code::InvalidArgument,
+        ),
+        (
+            speed_profile_request(FAN_1_CHANNEL_ID, None, &[(50.0, 101)]),
+            Code::InvalidArgument,
+        ),
+        (
+            speed_profile_request(FAN_1_CHANNEL_ID, Some("temp_nvme"), &[(50.0, 50)]),
+            Code::InvalidArgument,
+        ),
+        (
+            speed_profile_request("temp_cpu", None, &[(50.0, 50)]),
+            Code::FailedPrecondition,
+        ),
+    ] {
+        let status = service.speed_profile(request).await.unwrap_err();
+        assert_eq!(status.code(), code);
+    }
+
+    assert_eq!(backend.state().speeds, [0.0, 0.0]);
+}
+
+#[tokio::test]
+async fn lighting_sets_keyboard_backlight() {
+    let dir = std::env::temp_dir().join(format!("tuxedo-leds-test-{}", std::process::id()));
+    let led = dir.join("rgb:kbd_backlight");
+    std::fs::create_dir_all(&led).unwrap();
+    std::fs::write(led.join("max_brightness"), "50\n").unwrap();
+    std::fs::write(led.join("multi_intensity"), "255 255 255\n").unwrap();
+
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        keyboard: KeyboardBacklight::new([led.clone()]),
+        ..Default::default()
+    });
+    let lighting = |mode: &str, colors: Vec<Rgb>| {
+        service.lighting(Request::new(LightingRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: KEYBOARD_CHANNEL_ID.into(),
+            setting: Some(LightingSetting {
+                mode: mode.into(),
+                colors,
+                ..Default::default()
+            }),
+        }))
+    };
+
+    lighting(
+        LIGHTING_MODE_FIXED,
+        vec![Rgb {
+            r: 255,
+            g: 0,
+            b: 64,
+        }],
+    )
+    .await
+    .unwrap();
+    let intensity = std::fs::read_to_string(led.join("multi_intensity")).unwrap();
+    let brightness = std::fs::read_to_string(led.join("brightness")).unwrap();
+    assert_eq!(
+        (intensity.as_str(), brightness.as_str()),
+        ("255 0 64", "50")
+    );
+
+    lighting(LIGHTING_MODE_OFF, vec![]).await.unwrap();
+    let brightness = std::fs::read_to_string(led.join("brightness")).unwrap();
+    assert_eq!(brightness, "0");
+
+    let status = lighting("rainbow", vec![]).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let status = lighting(LIGHTING_MODE_OFF, vec![]).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn fixed_duty_sets_battery_charge_limit() {
+    let dir = std::env::temp_dir().join(format!("tuxedo-battery-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("charge_control_end_threshold"), "100\n").unwrap();
+
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        charge_limit: Some(ChargeLimit::new(&dir)),
+        ..Default::default()
+    });
+
+    service
+        .fixed_duty(fixed_duty_request(CHARGE_LIMIT_CHANNEL_ID, 80))
+        .await
+        .unwrap();
+    let threshold = std::fs::read_to_string(dir.join("charge_control_end_threshold")).unwrap();
+    assert_eq!(threshold, "80");
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.status.iter().any(|status| {
+        status.id == CHARGE_LIMIT_CHANNEL_ID
+            && status.metric
+                == Some(models::v1::status::Metric::Speed(FanSpeed {
+                    duty: Some(80.0),
+                    rpm: None,
+                }))
+    }));
+
+    let status = service
+        .fixed_duty(fixed_duty_request(CHARGE_LIMIT_CHANNEL_ID, 20))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // A battery that has gone away is reported as missing.
+    let status = service
+        .fixed_duty(fixed_duty_request(CHARGE_LIMIT_CHANNEL_ID, 80))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn lcd_sets_display_brightness() {
+    let dir = std::env::temp_dir().join(format!("tuxedo-backlight-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("max_brightness"), "400\n").unwrap();
+
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        display: DisplayBacklight::new(&dir),
+        ..Default::default()
+    });
+    let lcd = |mode: &str, brightness| {
+        service.lcd(Request::new(LcdRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: DISPLAY_CHANNEL_ID.into(),
+            setting: Some(LcdSetting {
+                mode: mode.into(),
+                brightness,
+                ..Default::default()
+            }),
+        }))
+    };
+
+    lcd(LCD_MODE_BRIGHTNESS, Some(30)).await.unwrap();
+    let brightness = std::fs::read_to_string(dir.join("brightness")).unwrap();
+    assert_eq!(brightness, "120");
+
+    let status = lcd(LCD_MODE_BRIGHTNESS, Some(101)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    let status = lcd("image", None).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let status = lcd(LCD_MODE_BRIGHTNESS, Some(30)).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn fixed_duty_sets_power_limit_within_bounds() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, None];
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request("tdp0", 30))
+        .await
+        .unwrap();
+    assert_eq!(backend.state().tdp, [30, 0, 0]);
+
+    for (channel_id, watts, code) in [
+        ("tdp0", 50, Code::InvalidArgument),
+        ("tdp0", -1, Code::InvalidArgument),
+        ("tdp1", 30, Code::FailedPrecondition),
+    ] {
+        let status = service
+            .fixed_duty(fixed_duty_request(channel_id, watts))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), code);
+    }
+    assert_eq!(backend.state().tdp, [30, 0, 0]);
+    assert_eq!(backend.state().speeds, [0.0, 0.0]);
+}
+
+#[tokio::test]
+async fn lighting_switches_performance_profile() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let lighting = |mode: &str| {
+        service.lighting(Request::new(LightingRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: PERFORMANCE_CHANNEL_ID.into(),
+            setting: Some(LightingSetting {
+                mode: mode.into(),
+                ..Default::default()
+            }),
+        }))
+    };
+
+    lighting("overboost").await.unwrap();
+    assert_eq!(backend.state().mode, PerformanceProfile::Overboost as i32);
+
+    let status = lighting("turbo").await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(backend.state().mode, PerformanceProfile::Overboost as i32);
+}
+
+#[tokio::test]
+async fn hwmon_backend_controls_pwm() {
+    let dir = std::env::temp_dir().join(format!("tuxedo-pwm-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, value) in [
+        ("pwm1", "0"),
+        ("pwm2", "255"),
+        ("pwm1_enable", "2"),
+        ("pwm2_enable", "2"),
+        ("temp1_input", "61000"),
+        ("temp2_input", "47500"),
+    ] {
+        std::fs::write(dir.join(name), value).unwrap();
+    }
+
+    let backend_dir = dir.clone();
+    let service = TuxedoService::with_backend(ServiceConfig::default(), move || {
+        Ok(Box::new(HwmonBackend::new(&backend_dir)))
+    });
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(
+        (read("pwm1_enable"), read("pwm1")),
+        ("1".into(), "128".into())
+    );
+
+    let response = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    // The duty is reported as the PWM value that 50% was rounded to.
+    let speeds = status_speeds(&response);
+    assert_eq!(
+        (speeds[0].duty, speeds[1].duty),
+        (Some(128.0 * 100.0 / 255.0), Some(100.0))
+    );
+    assert!(response.status.iter().any(|status| status.id == "temp_cpu"
+        && status.metric == Some(models::v1::status::Metric::Temp(61.0))));
+
+    service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap();
+    assert_eq!(
+        (read("pwm1_enable"), read("pwm2_enable")),
+        ("2".into(), "2".into())
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn reset_channel_returns_fans_to_auto() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.state().auto_calls, 1);
+}
+
+#[tokio::test]
+async fn reset_channel_keeps_the_other_fan_manual() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 40))
+        .await
+        .unwrap();
+    service
+        .fixed_duty(fixed_duty_request(FAN_2_CHANNEL_ID, 60))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    // Clear the speeds to see which fan is set again after the reset.
+    backend.state().speeds = [0.0, 0.0];
+    service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.state().auto_calls, 1);
+    assert_eq!(backend.state().speeds, [0.0, 60.0]);
+    assert_eq!(*service.commanded.lock().unwrap(), [None, Some(60.0)]);
+}
+
+#[tokio::test]
+async fn reset_channel_rejects_unknown_channel() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    let status = service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: "fan3".into(),
+        }))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(backend.state().auto_calls, 0);
+}
+
+#[tokio::test]
+async fn driver_errors_are_translated_to_status_codes() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    backend.state().errno = Some(nix::libc::ENOENT);
+    let status = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    backend.state().errno = Some(nix::libc::EACCES);
+    let status = service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert!(status.message().contains("needs to run as root"));
+
+    backend.state().errno = Some(nix::libc::EBUSY);
+    let status = service
+        .list_devices(Request::new(ListDevicesRequest {}))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+}
+
+#[tokio::test]
+async fn health_degrades_after_repeated_failures_and_recovers() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let health = || async {
+        service
+            .health(Request::new(HealthRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .status()
+    };
+    let status = || async {
+        service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+    };
+
+    backend.state().errno = Some(nix::libc::EIO);
+    for _ in 0..3 {
+        assert!(status().await.is_err());
+    }
+    assert_eq!(health().await, health_response::Status::Warning);
+
+    backend.state().errno = None;
+    assert!(status().await.is_ok());
+    assert_eq!(health().await, health_response::Status::Ok);
+}
+
+#[tokio::test]
+async fn health_reports_failures_as_metadata() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let metadata = || async {
+        let response = service
+            .health(Request::new(HealthRequest {}))
+            .await
+            .unwrap();
+        let value = |key| {
+            response
+                .metadata()
+                .get(key)
+                .map(|value| value.to_str().unwrap().to_owned())
+        };
+        (
+            value(REACHABLE_METADATA),
+            value(CONSECUTIVE_FAILURES_METADATA),
+            value(TOTAL_FAILURES_METADATA),
+            value(LAST_ERROR_METADATA),
+        )
+    };
+    let status = || async {
+        service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+    };
+
+    assert_eq!(
+        metadata().await,
+        (
+            Some("true".into()),
+            Some("0".into()),
+            Some("0".into()),
+            None
+        )
+    );
+
+    backend.state().errno = Some(nix::libc::EPERM);
+    for _ in 0..2 {
+        assert!(status().await.is_err());
+    }
+    let (reachable, consecutive, total, last_error) = metadata().await;
+    assert_eq!(reachable.as_deref(), Some("true"));
+    assert_eq!(consecutive.as_deref(), Some("2"));
+    assert_eq!(total.as_deref(), Some("2"));
+    assert!(last_error.is_some_and(|error| !error.is_empty()));
+
+    // Only the consecutive failures are forgotten once access works again.
+    backend.state().errno = None;
+    assert!(status().await.is_ok());
+    let (_, consecutive, total, last_error) = metadata().await;
+    assert_eq!(consecutive.as_deref(), Some("0"));
+    assert_eq!(total.as_deref(), Some("2"));
+    assert!(last_error.is_some());
+}
+
+#[tokio::test]
+async fn health_reports_error_while_device_is_unreachable() {
+    let backend = MockBackend::default();
+    let faults = Faults::default();
+    let service = backend.faulty_service(ServiceConfig::default(), &faults);
+    faults.inject("read_all_status=ENODEV*1".parse().unwrap());
+    faults.inject("check=ENODEV".parse().unwrap());
+
+    service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+    let _ = timeout(Duration::from_millis(100), service.run_device_recovery()).await;
+
+    let response = service
+        .health(Request::new(HealthRequest {}))
+        .await
+        .unwrap();
+    assert_eq!(response.get_ref().status(), health_response::Status::Error);
+    assert_eq!(
+        response.metadata().get(REACHABLE_METADATA).unwrap(),
+        "false"
+    );
+}
+
+#[tokio::test]
+async fn lost_device_is_recovered() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    // The device goes away, and comes back with the fans reset.
+    backend.state().errno = Some(nix::libc::ENODEV);
+    service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+    backend.state().errno = None;
+    backend.state().speeds = [0.0, 0.0];
+
+    let _ = timeout(Duration::from_millis(100), service.run_device_recovery()).await;
+
+    assert_eq!(backend.state().speeds, [50.0, 0.0]);
+    assert_eq!(service.io_stats().device_errors, 1);
+    assert_eq!(service.health.status(), health_response::Status::Ok);
+}
+
+#[tokio::test]
+async fn busy_driver_recovers_on_retry() {
+    let backend = MockBackend::default();
+    let faults = Faults::default();
+    let service = backend.faulty_service(ServiceConfig::default(), &faults);
+    faults.inject("set_fans_auto=EBUSY*1".parse().unwrap());
+    let reset = || async {
+        service
+            .reset_channel(Request::new(ResetChannelRequest {
+                device_id: DEVICE_ID.into(),
+                channel_id: FAN_1_CHANNEL_ID.into(),
+            }))
+            .await
+    };
+
+    assert_eq!(reset().await.unwrap_err().code(), Code::Unavailable);
+    assert!(reset().await.is_ok());
+    assert_eq!(backend.state().auto_calls, 1);
+}
+
+#[tokio::test]
+async fn hung_driver_times_out() {
+    let backend = MockBackend::default();
+    let faults = Faults::default();
+    let service = backend.faulty_service(
+        ServiceConfig {
+            io_timeout: Duration::from_millis(50),
+            ..Default::default()
+        },
+        &faults,
+    );
+    faults.inject("read_all_status=hang:200*1".parse().unwrap());
+
+    let status = service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::DeadlineExceeded);
+    assert_eq!(service.io_stats().timeouts, 1);
+}
+
+#[tokio::test]
+async fn lost_device_is_reopened_when_check_fails() {
+    let backend = MockBackend::default();
+    let faults = Faults::default();
+    let service = backend.faulty_service(ServiceConfig::default(), &faults);
+    faults.inject("read_all_status=EIO*1".parse().unwrap());
+    faults.inject("check=EIO*1".parse().unwrap());
+
+    service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+    let _ = timeout(Duration::from_millis(100), service.run_device_recovery()).await;
+
+    assert_eq!(backend.state().opens, 2);
+    assert_eq!(service.health.status(), health_response::Status::Ok);
+    assert!(
+        service
+            .status(Request::new(StatusRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn self_checks_degrade_health_while_idle() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    backend.state().errno = Some(nix::libc::EIO);
+
+    let _ = timeout(
+        Duration::from_millis(100),
+        service.run_self_checks(Duration::from_millis(10)),
+    )
+    .await;
+
+    let health = service
+        .health(Request::new(HealthRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(health.status(), health_response::Status::Warning);
+}
+
+#[tokio::test]
+async fn watchdog_returns_idle_fans_to_auto() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    let _ = timeout(
+        Duration::from_millis(150),
+        service.run_watchdog(Duration::from_millis(50)),
+    )
+    .await;
+
+    // The fans are only handed back once, not on every idle period.
+    assert_eq!(backend.state().auto_calls, 1);
+    assert_eq!(*service.commanded.lock().unwrap(), [None, None]);
+}
+
+#[tokio::test]
+async fn fallback_runs_curve_on_idle_manual_fans() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [60, 70];
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    let curve = Curve::new(vec![(40.0, 20), (80.0, 100)]).unwrap();
+    let _ = timeout(
+        Duration::from_millis(100),
+        service.run_fallback(Duration::from_millis(50), curve),
+    )
+    .await;
+    service.apply_profiles().await.unwrap();
+    settle(&service).await;
+
+    // Only the fan that CoolerControl had taken over follows the curve.
+    assert_eq!(backend.state().speeds, [60.0, 0.0]);
+    assert_eq!(*service.commanded.lock().unwrap(), [Some(60.0), None]);
+}
+
+#[tokio::test]
+async fn thermal_failsafe_overrides_requested_speeds() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [95, 60];
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 40))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    let _ = timeout(Duration::from_millis(100), service.run_thermal_failsafe(90)).await;
+    assert_eq!(backend.state().speeds, [100.0, 100.0]);
+
+    // Requests made in the meantime are remembered, but not applied.
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 30))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [100.0, 100.0]);
+
+    // Cooling to just below the critical temperature is not enough.
+    backend.state().temperatures = [88, 60];
+    let _ = timeout(Duration::from_millis(100), service.run_thermal_failsafe(90)).await;
+    assert_eq!(backend.state().auto_calls, 0);
+
+    backend.state().temperatures = [80, 60];
+    let _ = timeout(Duration::from_millis(100), service.run_thermal_failsafe(90)).await;
+    assert_eq!(backend.state().auto_calls, 1);
+    assert_eq!(backend.state().speeds[0], 30.0);
+}
+
+#[tokio::test]
+async fn metrics_report_firmware_mode() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        status_ttl: Duration::ZERO,
+        ..Default::default()
+    });
+    let metrics = || async {
+        let mut metrics = Exposition::default();
+        service.write_metrics(&mut metrics).await;
+        metrics.to_string()
+    };
+
+    assert!(!metrics().await.contains("tuxedo_firmware_mode_enabled"));
+
+    backend.state().mode_enable = Some(true);
+    assert!(metrics().await.contains("tuxedo_firmware_mode_enabled 1\n"));
+
+    backend.state().mode_enable = Some(false);
+    assert!(metrics().await.contains("tuxedo_firmware_mode_enabled 0\n"));
+}
+
+#[tokio::test]
+async fn metrics_report_fans_and_temperatures() {
+    let backend = MockBackend::default();
+    backend.state().temperatures = [61, 48];
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 40))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    let mut metrics = Exposition::default();
+    service.write_metrics(&mut metrics).await;
+    let text = metrics.to_string();
+
+    assert!(text.contains("tuxedo_up 1\n"));
+    assert!(text.contains("tuxedo_fan_commanded_duty_percent{channel=\"fan1\"} 40\n"));
+    assert!(!text.contains("tuxedo_fan_commanded_duty_percent{channel=\"fan2\"}"));
+    assert!(text.contains("tuxedo_fan_duty_percent{channel=\"fan1\"} 40\n"));
+    assert!(text.contains("tuxedo_temperature_celsius{sensor=\"temp_cpu\"} 61\n"));
+    assert!(text.contains("tuxedo_driver_calls_total{outcome=\"write\"} 1\n"));
+}
+
+#[tokio::test]
+async fn io_stats_count_driver_accesses() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+    backend.state().errno = Some(nix::libc::EACCES);
+    service
+        .status(Request::new(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap_err();
+
+    // Setting a speed reads the hardware minimum first.
+    let stats = service.io_stats();
+    assert_eq!(stats.writes, 1);
+    assert_eq!(stats.reads, 1);
+    assert_eq!(stats.permission_errors, 1);
+    assert_eq!(stats.other_errors, 0);
+}
+
+#[tokio::test]
+async fn initialize_device_reopens_stale_handle() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let initialize = || async {
+        service
+            .initialize_device(Request::new(InitializeDeviceRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .unwrap()
+    };
+
+    initialize().await;
+    initialize().await;
+    assert_eq!(backend.state().opens, 1);
+
+    backend.state().errno = Some(nix::libc::ENODEV);
+    initialize().await;
+    assert_eq!(backend.state().opens, 2);
+}
+
+#[tokio::test]
+async fn open_waits_for_the_driver_to_appear() {
+    let backend = MockBackend::default();
+    let missing = Arc::new(StdMutex::new(2));
+    let service = {
+        let backend = backend.clone();
+        let missing = missing.clone();
+
+        TuxedoService::with_backend(ServiceConfig::default(), move || {
+            let mut missing = missing.lock().unwrap();
+            if *missing > 0 {
+                *missing -= 1;
+                return Err(io::Error::from_raw_os_error(nix::libc::ENOENT));
+            }
+            Ok(Box::new(backend.clone()))
+        })
+    };
+
+    service.open_waiting(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(*missing.lock().unwrap(), 0);
+
+    // Other errors are not worth waiting for.
+    let service = TuxedoService::with_backend(ServiceConfig::default(), || {
+        Err(io::Error::from_raw_os_error(nix::libc::EACCES))
+    });
+    let status = service
+        .open_waiting(Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+}
+
+#[tokio::test]
+async fn open_gives_up_waiting_for_the_driver() {
+    let service = TuxedoService::with_backend(ServiceConfig::default(), || {
+        Err(io::Error::from_raw_os_error(nix::libc::ENOENT))
+    });
+
+    let started = Instant::now();
+    let status = service
+        .open_waiting(Duration::from_millis(500))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn shutdown_can_keep_the_driver_open() {
+    let backend = MockBackend::default();
+    let service = backend.service_with(ServiceConfig {
+        keep_driver_open: true,
+        ..Default::default()
+    });
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 50))
+        .await
+        .unwrap();
+    settle(&service).await;
+    service
+        .shutdown(Request::new(ShutdownRequest {}))
+        .await
+        .unwrap();
+    assert_eq!(backend.state().auto_calls, 1);
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 60))
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds[0], 60.0);
+    assert_eq!(backend.state().opens, 1);
+}
+
+#[tokio::test]
+async fn initialize_device_restores_commanded_speeds() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+    let initialize = || async {
+        service
+            .initialize_device(Request::new(InitializeDeviceRequest {
+                device_id: DEVICE_ID.into(),
+            }))
+            .await
+            .unwrap()
+    };
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 45))
+        .await
+        .unwrap();
+    settle(&service).await;
+
+    // Firmware resets the fans while suspended.
+    backend.state().speeds = [0.0, 0.0];
+    initialize().await;
+    assert_eq!(backend.state().speeds, [45.0, 0.0]);
+
+    service
+        .reset_channel(Request::new(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        }))
+        .await
+        .unwrap();
+    backend.state().speeds = [0.0, 0.0];
+    initialize().await;
+    assert_eq!(backend.state().speeds, [0.0, 0.0]);
+}
+
+#[tokio::test]
+async fn initialize_device_reapplies_power_settings() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, None];
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request("tdp0", 30))
+        .await
+        .unwrap();
+    service
+        .lighting(Request::new(LightingRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: PERFORMANCE_CHANNEL_ID.into(),
+            setting: Some(LightingSetting {
+                mode: "power_save".into(),
+                ..Default::default()
+            }),
+        }))
+        .await
+        .unwrap();
+
+    // Firmware resets the power settings while suspended.
+    backend.state().tdp = [0, 0, 0];
+    backend.state().mode = 0;
+    service
+        .initialize_device(Request::new(InitializeDeviceRequest {
+            device_id: DEVICE_ID.into(),
+        }))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.state().tdp, [30, 0, 0]);
+    assert_eq!(backend.state().mode, PerformanceProfile::PowerSave as i32);
+}
+
+#[tokio::test]
+async fn shutdown_restores_power_settings() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, None];
+    backend.state().tdp = [25, 0, 0];
+    backend.state().mode = PerformanceProfile::Enthusiast as i32;
+    let service = backend.service();
+
+    for watts in [30, 40] {
+        service
+            .fixed_duty(fixed_duty_request("tdp0", watts))
+            .await
+            .unwrap();
+    }
+    service
+        .lighting(Request::new(LightingRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: PERFORMANCE_CHANNEL_ID.into(),
+            setting: Some(LightingSetting {
+                mode: "overboost".into(),
+                ..Default::default()
+            }),
+        }))
+        .await
+        .unwrap();
+
+    service
+        .shutdown(Request::new(ShutdownRequest {}))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.state().tdp, [25, 0, 0]);
+    assert_eq!(backend.state().mode, PerformanceProfile::Enthusiast as i32);
+    assert_eq!(backend.state().auto_calls, 1);
+}
+
+#[tokio::test]
+async fn custom_function_toggles_mode_enable() {
+    let backend = MockBackend::default();
+    backend.state().mode_enable = Some(false);
+    let service = backend.service();
+    let toggle = || async {
+        service
+            .custom_function_one(Request::new(CustomFunctionOneRequest {}))
+            .await
+            .unwrap();
+    };
+
+    toggle().await;
+    assert_eq!(backend.state().mode_enable, Some(true));
+    toggle().await;
+    toggle().await;
+    assert_eq!(backend.state().mode_enable, Some(true));
+
+    service
+        .shutdown(Request::new(ShutdownRequest {}))
+        .await
+        .unwrap();
+    assert_eq!(backend.state().mode_enable, Some(false));
+}
+
+#[tokio::test]
+async fn custom_function_requires_mode_enable_support() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    let status = service
+        .custom_function_one(Request::new(CustomFunctionOneRequest {}))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::Unimplemented);
+}
+
+#[tokio::test]
+async fn shutdown_can_keep_power_settings() {
+    let backend = MockBackend::default();
+    backend.state().tdp_bounds = [Some((10, 45)), None, None];
+    backend.state().tdp = [25, 0, 0];
+    let service = backend.service_with(ServiceConfig {
+        restore_power_settings: false,
+        ..Default::default()
+    });
+
+    service
+        .fixed_duty(fixed_duty_request("tdp0", 30))
+        .await
+        .unwrap();
+    service
+        .shutdown(Request::new(ShutdownRequest {}))
+        .await
+        .unwrap();
+
+    assert_eq!(backend.state().tdp, [30, 0, 0]);
+    assert_eq!(backend.state().auto_calls, 1);
+}
+
+#[tokio::test]
+async fn release_fans_now_returns_fans_to_firmware() {
+    let backend = MockBackend::default();
+    let service = backend.service();
+
+    service
+        .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, 60))
+        .await
+        .unwrap();
+    settle(&service).await;
+    service.release_fans_now();
+
+    assert_eq!(backend.state().auto_calls, 1);
+}
+
+#[tokio::test]
+async fn drain_finishes_the_write_in_progress_and_drops_queued_ones() {
+    let backend = MockBackend::default();
+    backend.state().write_delay = Duration::from_millis(100);
+    let service = backend.service();
+
+    for duty in [30, 40] {
+        service
+            .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, duty))
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    // Shutdown does not wait for ever on a write that is stuck.
+    assert!(
+        !service
+            .drain(Instant::now() + Duration::from_millis(10))
+            .await
+    );
+    assert!(service.drain(Instant::now() + Duration::from_secs(1)).await);
+    assert_eq!(backend.state().speeds, [30.0, 0.0]);
+}
+
+#[tokio::test]
+async fn grpc_lists_devices_and_reports_status() {
+    let backend = MockBackend::default();
+    backend.state().speeds = [40.0, 60.0];
+    let (mut client, socket) = serve("status", Arc::new(backend.service())).await;
+
+    let response = client
+        .list_devices(ListDevicesRequest {})
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(response.devices.len(), 1);
+    assert_eq!(response.devices[0].id, DEVICE_ID);
+
+    let response = client
+        .status(StatusRequest {
+            device_id: DEVICE_ID.into(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let duties = status_speeds(&response)
+        .into_iter()
+        .map(|speed| speed.duty)
+        .collect::<Vec<_>>();
+    assert_eq!(duties, [Some(40.0), Some(60.0)]);
+
+    std::fs::remove_file(socket).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn grpc_serves_concurrent_clients_on_worker_threads() {
+    let backend = MockBackend::default();
+    let service = Arc::new(backend.service());
+    let (client, socket) = serve("multi-thread", service.clone()).await;
+
+    let requests = (0..8).map(|i| {
+        let mut client = client.clone();
+
+        tokio::spawn(async move {
+            client
+                .fixed_duty(FixedDutyRequest {
+                    device_id: DEVICE_ID.into(),
+                    channel_id: [FAN_1_CHANNEL_ID, FAN_2_CHANNEL_ID][i % 2].into(),
+                    duty: 50,
+                })
+                .await
+                .unwrap();
+            client
+                .status(StatusRequest {
+                    device_id: DEVICE_ID.into(),
+                })
+                .await
+                .unwrap();
+        })
+    });
+    for request in requests.collect::<Vec<_>>() {
+        request.await.unwrap();
+    }
+    settle(&service).await;
+
+    assert_eq!(backend.state().speeds, [50.0, 50.0]);
+    std::fs::remove_file(socket).unwrap();
+}
+
+#[tokio::test]
+async fn grpc_sets_and_resets_fans() {
+    let backend = MockBackend::default();
+    let service = Arc::new(backend.service());
+    let (mut client, socket) = serve("fixed-duty", service.clone()).await;
+
+    client
+        .fixed_duty(FixedDutyRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+            duty: 60,
+        })
+        .await
+        .unwrap();
+    settle(&service).await;
+    assert_eq!(backend.state().speeds, [60.0, 0.0]);
+
+    client
+        .reset_channel(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(backend.state().auto_calls, 1);
+
+    std::fs::remove_file(socket).unwrap();
+}
+
+#[tokio::test]
+async fn grpc_returns_errors_with_their_code() {
+    let backend = MockBackend::default();
+    let (mut client, socket) = serve("errors", Arc::new(backend.service())).await;
+
+    let status = client
+        .fixed_duty(FixedDutyRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: "fan3".into(),
+            duty: 50,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    backend.state().errno = Some(libc::ENOENT);
+    let status = client
+        .reset_channel(ResetChannelRequest {
+            device_id: DEVICE_ID.into(),
+            channel_id: FAN_1_CHANNEL_ID.into(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    std::fs::remove_file(socket).unwrap();
+}
+
+#[tokio::test]
+async fn telemetry_shows_readings_and_pending_writes() {
+    let backend = MockBackend::default();
+    backend.state().write_delay = Duration::from_millis(100);
+    backend.state().temperatures = [61, 48];
+    let service = backend.service();
+    let snapshot = service.read_status().await.unwrap();
+
+    // The second duty waits for the writer, which hasn't had a chance to run.
+    for duty in [30, 50] {
+        service
+            .fixed_duty(fixed_duty_request(FAN_1_CHANNEL_ID, duty))
+            .await
+            .unwrap();
+    }
+
+    let line = service.telemetry(&snapshot);
+    assert!(
+        line.starts_with("fan1 0% 61°C (pending 50%), fan2 0% 48°C, "),
+        "{line}"
+    );
+    assert!(line.ends_with(" driver calls in flight"), "{line}");
+
+    settle(&service).await;
+    assert!(!service.telemetry(&snapshot).contains("pending"));
+}
+
+/// Serve the service on a Unix socket in the temporary directory and connect
+/// a client to it, so that requests go through the whole gRPC stack. Returns
+/// the path of the socket, to be removed by the test.
+async fn serve(name: &str, service: Arc<TuxedoService>) -> (DeviceServiceClient<Channel>, PathBuf) {
+    let socket = std::env::temp_dir().join(format!(
+        "tuxedo-grpc-{name}-test-{}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket).unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(DeviceServiceServer::from_arc(service))
+            .serve_with_incoming(UnixListenerStream::new(listener)),
+    );
+
+    let channel = crate::client::connect(socket.to_str().unwrap())
+        .await
+        .unwrap();
+
+    (DeviceServiceClient::new(channel), socket)
+}
+
+/// Wait for the fan speeds queued by the service to be written, which is once
+/// nothing is left in the queues and no call to the backend is in flight for
+/// a while.
+async fn settle(service: &TuxedoService) {
+    let idle = || {
+        service.io.in_flight.load(Ordering::Relaxed) == 0
+            && service
+                .write_queues
+                .iter()
+                .all(|queue| queue.pending().is_none())
+    };
+
+    loop {
+        while !idle() {
+            sleep(Duration::from_millis(1)).await;
+        }
+        sleep(Duration::from_millis(1)).await;
+
+        if idle() {
+            return;
+        }
+    }
+}
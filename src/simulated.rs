@@ -0,0 +1,199 @@
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp},
+};
+use std::{
+    io::{Error, ErrorKind, Result},
+    sync::Mutex,
+    time::Instant,
+};
+
+/// Lowest speed the simulated fans will run at, as a percentage.
+const MIN_SPEED: u8 = 20;
+
+/// Time constant in seconds of how quickly a fan approaches its target speed.
+const SPIN_UP_SECS: f64 = 2.0;
+
+const TDP_BOUNDS: [Option<(u32, u32)>; 3] = [Some((10, 45)), Some((20, 60)), None];
+
+/// A simulated laptop, for developing against the service without TUXEDO
+/// hardware or drivers.
+///
+/// The temperatures follow a slowly varying synthetic load and drop as the
+/// fans speed up. The fans take a moment to reach a new speed, and follow a
+/// simple curve of their own while under firmware control.
+pub struct SimulatedBackend {
+    started: Instant,
+    state: Mutex<State>,
+}
+
+struct State {
+    last_update: Instant,
+
+    /// Current speed of each fan as a percentage.
+    speeds: [f64; 2],
+
+    /// Requested speed of each fan, or `None` under firmware control.
+    targets: [Option<f64>; 2],
+
+    tdp: [u32; 3],
+    mode: i32,
+    mode_enable: bool,
+}
+
+impl SimulatedBackend {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            started: now,
+            state: Mutex::new(State {
+                last_update: now,
+                speeds: [MIN_SPEED.into(); 2],
+                targets: [None; 2],
+                tdp: [25, 40, 0],
+                mode: PerformanceProfile::Enthusiast as i32,
+                mode_enable: false,
+            }),
+        }
+    }
+
+    /// Advance the simulation to now and return the fan speeds and
+    /// temperatures.
+    fn update(&self) -> ([f64; 2], [f64; 2]) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let dt = now.duration_since(state.last_update).as_secs_f64();
+        state.last_update = now;
+
+        let temperatures = self.temperatures(&state.speeds);
+        let approach = 1.0 - (-dt / SPIN_UP_SECS).exp();
+
+        for fan in Fan::ALL {
+            let i = fan as usize;
+            let target = match state.targets[i] {
+                Some(duty) => duty,
+                None => firmware_curve(temperatures[i]),
+            };
+
+            state.speeds[i] += (target - state.speeds[i]) * approach;
+        }
+
+        (state.speeds, temperatures)
+    }
+
+    fn temperatures(&self, speeds: &[f64; 2]) -> [f64; 2] {
+        let t = self.started.elapsed().as_secs_f64();
+        let load = 0.5 + 0.5 * (t / 20.0).sin();
+
+        [
+            40.0 + 50.0 * load - speeds[0] * 0.2,
+            35.0 + 40.0 * load - speeds[1] * 0.15,
+        ]
+    }
+}
+
+impl Default for SimulatedBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Speed the simulated firmware runs a fan at for a temperature.
+fn firmware_curve(temperature: f64) -> f64 {
+    ((temperature - 40.0) * 2.0).clamp(MIN_SPEED.into(), 100.0)
+}
+
+impl FanBackend for SimulatedBackend {
+    fn model(&self) -> String {
+        "Simulated".into()
+    }
+
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        TDP_BOUNDS[tdp as usize]
+    }
+
+    fn get_tdp(&self, tdp: Tdp) -> Result<u32> {
+        match TDP_BOUNDS[tdp as usize] {
+            Some(_) => Ok(self.state.lock().unwrap().tdp[tdp as usize]),
+            None => Err(Error::from(ErrorKind::Unsupported)),
+        }
+    }
+
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()> {
+        self.get_tdp(tdp)?;
+        self.state.lock().unwrap().tdp[tdp as usize] = watts;
+        Ok(())
+    }
+
+    fn get_mode(&self) -> Result<i32> {
+        Ok(self.state.lock().unwrap().mode)
+    }
+
+    fn get_mode_enable(&self) -> Result<bool> {
+        Ok(self.state.lock().unwrap().mode_enable)
+    }
+
+    fn set_mode_enable(&self, enabled: bool) -> Result<()> {
+        self.state.lock().unwrap().mode_enable = enabled;
+        Ok(())
+    }
+
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()> {
+        self.state.lock().unwrap().mode = profile as i32;
+        Ok(())
+    }
+
+    fn fans_off_available(&self) -> bool {
+        false
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        true
+    }
+
+    fn get_fan_min_speed(&self) -> Result<u8> {
+        Ok(MIN_SPEED)
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        Ok(self.update().0[fan as usize])
+    }
+
+    fn read_all_status(&self) -> Result<FanStatusSnapshot> {
+        let (speeds, temperatures) = self.update();
+        let mode = self.get_mode()?;
+        let mode_enable = self.get_mode_enable()?;
+
+        Ok(FanStatusSnapshot {
+            speeds,
+            temperatures: temperatures.map(|temperature| temperature.round() as u8),
+            mode: Some(mode),
+            mode_enable: Some(mode_enable),
+            fans_off_available: false,
+            tdp: Tdp::ALL.map(|tdp| self.get_tdp(tdp).ok()),
+        })
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        let percentage = percentage.clamp(0.0, 100.0);
+
+        self.update();
+        self.state.lock().unwrap().targets[fan as usize] = Some(percentage);
+        Ok(percentage.round() as i32)
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        percentage.clamp(0.0, 100.0).round() as i32
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        self.update();
+        self.state.lock().unwrap().targets = [None; 2];
+        Ok(())
+    }
+}
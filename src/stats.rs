@@ -0,0 +1,243 @@
+use crate::{
+    backend::{FanBackend, FanStatusSnapshot},
+    tuxedo_io::{Fan, PerformanceProfile, Tdp},
+};
+use nix::libc::{EIO, ENODEV};
+use std::{
+    fmt,
+    io::{ErrorKind, Result},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tracing::{field, trace, trace_span};
+
+/// Running totals of driver accesses, for diagnosing long-running deployments.
+#[derive(Default)]
+pub struct IoStats {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    not_found_errors: AtomicU64,
+    permission_errors: AtomicU64,
+    device_errors: AtomicU64,
+    other_errors: AtomicU64,
+
+    /// Driver calls given up on, as they did not return in time.
+    timeouts: AtomicU64,
+
+    /// Total time spent in driver calls.
+    busy_nanos: AtomicU64,
+
+    /// Woken when a driver call fails in a way that suggests the device has
+    /// gone away or stopped responding.
+    device_lost: Notify,
+}
+
+/// A point-in-time copy of [`IoStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IoStatsSnapshot {
+    pub reads: u64,
+    pub writes: u64,
+    pub not_found_errors: u64,
+    pub permission_errors: u64,
+    pub device_errors: u64,
+    pub other_errors: u64,
+    pub timeouts: u64,
+    pub busy: Duration,
+}
+
+impl IoStats {
+    pub fn snapshot(&self) -> IoStatsSnapshot {
+        IoStatsSnapshot {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            not_found_errors: self.not_found_errors.load(Ordering::Relaxed),
+            permission_errors: self.permission_errors.load(Ordering::Relaxed),
+            device_errors: self.device_errors.load(Ordering::Relaxed),
+            other_errors: self.other_errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Make a driver call, counting its outcome and the time spent on it. Each
+    /// call runs in an `ioctl` span with the `tuxedo_io` target, which records
+    /// how long it took, and is logged at trace level, so that slow calls can
+    /// be found with `CC_LOG=info,tuxedo_io=trace`.
+    fn count<T>(
+        &self,
+        successes: &AtomicU64,
+        name: &'static str,
+        call: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let span = trace_span!(
+            target: "tuxedo_io",
+            "ioctl",
+            call = name,
+            elapsed_us = field::Empty,
+            ok = field::Empty,
+        );
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        let result = call();
+        let elapsed = start.elapsed();
+        self.busy_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+
+        let elapsed_us = elapsed.as_micros() as u64;
+        let ok = result.is_ok();
+        span.record("elapsed_us", elapsed_us);
+        span.record("ok", ok);
+        trace!(target: "tuxedo_io", "{name} took {elapsed:?}");
+
+        let counter = match &result {
+            Ok(_) => successes,
+            Err(e) if matches!(e.raw_os_error(), Some(ENODEV | EIO)) => {
+                self.device_lost.notify_one();
+                &self.device_errors
+            }
+            Err(e) => match e.kind() {
+                ErrorKind::NotFound => &self.not_found_errors,
+                ErrorKind::PermissionDenied => &self.permission_errors,
+                _ => &self.other_errors,
+            },
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        result
+    }
+
+    /// Count a driver call that was given up on. The call itself is still
+    /// counted by its outcome, once the driver returns.
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Wait until a driver call fails with `ENODEV` or `EIO`. A failure since
+    /// the last wait returns right away.
+    pub async fn device_lost(&self) {
+        self.device_lost.notified().await;
+    }
+
+    fn read<T>(&self, name: &'static str, call: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.count(&self.reads, name, call)
+    }
+
+    fn write<T>(&self, name: &'static str, call: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.count(&self.writes, name, call)
+    }
+}
+
+impl fmt::Display for IoStatsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads, {} writes, {} not found errors, {} permission errors, {} device errors, \
+            {} other errors, {} timeouts",
+            self.reads,
+            self.writes,
+            self.not_found_errors,
+            self.permission_errors,
+            self.device_errors,
+            self.other_errors,
+            self.timeouts
+        )
+    }
+}
+
+/// Wraps a backend to count every access made through it.
+pub struct CountingBackend {
+    inner: Box<dyn FanBackend>,
+    stats: Arc<IoStats>,
+}
+
+impl CountingBackend {
+    pub fn new(inner: Box<dyn FanBackend>, stats: Arc<IoStats>) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl FanBackend for CountingBackend {
+    fn model(&self) -> String {
+        self.inner.model()
+    }
+
+    fn check(&self) -> Result<()> {
+        self.stats.read("check", || self.inner.check())
+    }
+
+    fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        self.inner.get_tdp_bounds(tdp)
+    }
+
+    fn fans_off_available(&self) -> bool {
+        self.inner.fans_off_available()
+    }
+
+    fn performance_profiles_available(&self) -> bool {
+        self.inner.performance_profiles_available()
+    }
+
+    fn get_mode(&self) -> Result<i32> {
+        self.stats.read("get_mode", || self.inner.get_mode())
+    }
+
+    fn get_mode_enable(&self) -> Result<bool> {
+        self.stats
+            .read("get_mode_enable", || self.inner.get_mode_enable())
+    }
+
+    fn set_mode_enable(&self, enabled: bool) -> Result<()> {
+        self.stats
+            .write("set_mode_enable", || self.inner.set_mode_enable(enabled))
+    }
+
+    fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()> {
+        self.stats.write("set_performance_profile", || {
+            self.inner.set_performance_profile(profile)
+        })
+    }
+
+    fn get_tdp(&self, tdp: Tdp) -> Result<u32> {
+        self.stats.read("get_tdp", || self.inner.get_tdp(tdp))
+    }
+
+    fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()> {
+        self.stats
+            .write("set_tdp", || self.inner.set_tdp(tdp, watts))
+    }
+
+    fn get_fan_min_speed(&self) -> Result<u8> {
+        self.stats
+            .read("get_fan_min_speed", || self.inner.get_fan_min_speed())
+    }
+
+    fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        self.stats
+            .read("get_fan_speed", || self.inner.get_fan_speed(fan))
+    }
+
+    fn read_all_status(&self) -> Result<FanStatusSnapshot> {
+        self.stats
+            .read("read_all_status", || self.inner.read_all_status())
+    }
+
+    fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        self.stats.write("set_fan_speed", || {
+            self.inner.set_fan_speed(fan, percentage)
+        })
+    }
+
+    fn fan_speed_value(&self, percentage: f64) -> i32 {
+        self.inner.fan_speed_value(percentage)
+    }
+
+    fn set_fans_auto(&self) -> Result<()> {
+        self.stats
+            .write("set_fans_auto", || self.inner.set_fans_auto())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{
+    device_service::v1::{StatusRequest, device_service_server::DeviceService},
+    models::v1,
+    service::TuxedoService,
+    tuxedo::v1::{WatchStatusRequest, WatchStatusResponse, status_stream_server::StatusStream},
+};
+use std::{pin::Pin, sync::Arc, time::Duration};
+use tokio::{
+    spawn,
+    sync::mpsc,
+    time::{MissedTickBehavior, interval},
+};
+use tokio_util::sync::CancellationToken;
+use tonic::{
+    Request, Response, Status,
+    codegen::tokio_stream::{Stream, wrappers::ReceiverStream},
+};
+use tracing::debug;
+
+/// Time between samples when the client leaves it up to the plugin.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Shortest time between samples a client may ask for, as every sample is a
+/// round of driver calls.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pushes status updates to subscribed clients, sampling the device on the
+/// plugin's schedule instead of the client polling for it.
+pub struct StatusStreamService {
+    service: Arc<TuxedoService>,
+
+    /// Ends every subscription when the plugin shuts down, as the server
+    /// waits for open streams before stopping.
+    shutdown: CancellationToken,
+}
+
+impl StatusStreamService {
+    pub fn new(service: Arc<TuxedoService>, shutdown: CancellationToken) -> Self {
+        Self { service, shutdown }
+    }
+}
+
+#[tonic::async_trait]
+impl StatusStream for StatusStreamService {
+    type WatchStatusStream =
+        Pin<Box<dyn Stream<Item = Result<WatchStatusResponse, Status>> + Send>>;
+
+    async fn watch_status(
+        &self,
+        request: Request<WatchStatusRequest>,
+    ) -> Result<Response<Self::WatchStatusStream>, Status> {
+        let request = request.into_inner();
+        let period = match request.interval_ms {
+            0 => DEFAULT_INTERVAL,
+            millis => Duration::from_millis(millis.into()).max(MIN_INTERVAL),
+        };
+
+        // Take the first sample right away, so that a subscription that cannot
+        // work fails instead of starting a stream.
+        let status = sample(&self.service, &request.device_id).await?;
+        let (sender, receiver) = mpsc::channel(1);
+        sender
+            .send(Ok(WatchStatusResponse {
+                status: status.clone(),
+            }))
+            .await
+            .ok();
+
+        spawn(watch(
+            self.service.clone(),
+            request.device_id,
+            period,
+            status,
+            sender,
+            self.shutdown.clone(),
+        ));
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+/// Keep sampling the device and sending the channels that changed, until the
+/// client goes away, a sample fails or the plugin shuts down.
+async fn watch(
+    service: Arc<TuxedoService>,
+    device_id: String,
+    period: Duration,
+    mut last: Vec<v1::Status>,
+    sender: mpsc::Sender<Result<WatchStatusResponse, Status>>,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = sender.closed() => return,
+            _ = shutdown.cancelled() => return,
+        }
+
+        let message = match sample(&service, &device_id).await {
+            Ok(status) => {
+                let changed = changes(&last, &status);
+                last = status;
+
+                if changed.is_empty() {
+                    continue;
+                }
+                Ok(WatchStatusResponse { status: changed })
+            }
+            Err(e) => {
+                debug!("Ending status stream: {}", e.message());
+                Err(e)
+            }
+        };
+        let failed = message.is_err();
+
+        if sender.send(message).await.is_err() || failed {
+            return;
+        }
+    }
+}
+
+async fn sample(service: &TuxedoService, device_id: &str) -> Result<Vec<v1::Status>, Status> {
+    Ok(service
+        .status(Request::new(StatusRequest {
+            device_id: device_id.into(),
+        }))
+        .await?
+        .into_inner()
+        .status)
+}
+
+/// The channels in `current` that are new or read differently than in
+/// `last`.
+fn changes(last: &[v1::Status], current: &[v1::Status]) -> Vec<v1::Status> {
+    current
+        .iter()
+        .filter(|status| !last.contains(status))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+use super::*;
+use crate::{
+    service::{DEVICE_ID, ServiceConfig},
+    simulated::SimulatedBackend,
+};
+use tokio::time::timeout;
+use tonic::codegen::tokio_stream::StreamExt;
+
+fn temp(id: &str, temp: f64) -> v1::Status {
+    v1::Status {
+        id: id.into(),
+        metric: Some(v1::status::Metric::Temp(temp)),
+    }
+}
+
+fn subscribe(shutdown: &CancellationToken) -> StatusStreamService {
+    let service = TuxedoService::with_backend(ServiceConfig::default(), || {
+        Ok(Box::new(SimulatedBackend::new()))
+    });
+
+    StatusStreamService::new(Arc::new(service), shutdown.clone())
+}
+
+#[test]
+fn only_changed_and_new_channels_are_sent() {
+    let last = [temp("cpu", 50.0), temp("gpu", 40.0)];
+    let current = [temp("cpu", 50.0), temp("gpu", 41.0), temp("nvme", 30.0)];
+
+    assert_eq!(
+        changes(&last, &current),
+        [temp("gpu", 41.0), temp("nvme", 30.0)]
+    );
+    assert!(changes(&current, &current).is_empty());
+}
+
+#[tokio::test]
+async fn first_message_has_every_channel() {
+    let shutdown = CancellationToken::new();
+    let mut stream = subscribe(&shutdown)
+        .watch_status(Request::new(WatchStatusRequest {
+            device_id: DEVICE_ID.into(),
+            interval_ms: 0,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let first = stream.next().await.unwrap().unwrap();
+    let ids = first
+        .status
+        .iter()
+        .map(|status| status.id.as_str())
+        .collect::<Vec<_>>();
+    assert!(ids.contains(&"fan1"), "{ids:?}");
+    assert!(ids.contains(&"fan2"), "{ids:?}");
+}
+
+#[tokio::test]
+async fn streams_end_on_shutdown() {
+    let shutdown = CancellationToken::new();
+    let mut stream = subscribe(&shutdown)
+        .watch_status(Request::new(WatchStatusRequest {
+            device_id: DEVICE_ID.into(),
+            interval_ms: 100,
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+    stream.next().await.unwrap().unwrap();
+
+    shutdown.cancel();
+
+    let rest = timeout(Duration::from_secs(1), stream.collect::<Vec<_>>())
+        .await
+        .expect("stream did not end");
+    assert!(rest.len() <= 1);
+}
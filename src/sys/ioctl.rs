@@ -1,14 +1,24 @@
+//! Raw ioctl requests of the TUXEDO driver, named after the ones in its
+//! `tuxedo_io_ioctl.h` header. `cl_` requests are for Clevo devices and `uw_`
+//! requests for Uniwill devices.
+
 #![allow(unused)]
 
 use nix::{errno::Errno, ioctl_none, libc::ioctl, request_code_read, request_code_write};
 use std::{mem::size_of, os::fd::RawFd};
 
 const MAGIC: u8 = 0xec;
+const MAGIC_READ_CL: u8 = MAGIC + 1;
+const MAGIC_WRITE_CL: u8 = MAGIC + 2;
 const MAGIC_READ: u8 = MAGIC + 3;
 const MAGIC_WRITE: u8 = MAGIC + 4;
 
 macro_rules! ioctl_read_int {
     ($name:ident, $id:expr, $seq:expr) => {
+        /// # Safety
+        ///
+        /// `fd` must be the driver's ioctl device, and `data_ptr` must point to
+        /// an `i32` that the result can be written to.
         pub unsafe fn $name(fd: RawFd, data_ptr: *mut i32) -> ::nix::Result<i32> {
             let request_code = request_code_read!($id, $seq, size_of::<*mut i32>());
             Errno::result(unsafe { ioctl(fd, request_code, data_ptr) })
@@ -18,6 +28,10 @@ macro_rules! ioctl_read_int {
 
 macro_rules! ioctl_write_int {
     ($name:ident, $id:expr, $seq:expr) => {
+        /// # Safety
+        ///
+        /// `fd` must be the driver's ioctl device, and `data_ptr` must point to
+        /// the `i32` to write.
         pub unsafe fn $name(fd: RawFd, data_ptr: *const i32) -> ::nix::Result<i32> {
             let request_code = request_code_write!($id, $seq, size_of::<*const i32>());
             Errno::result(unsafe { ioctl(fd, request_code, data_ptr) })
@@ -25,8 +39,16 @@ macro_rules! ioctl_write_int {
     };
 }
 
+ioctl_read_int!(cl_hwcheck, MAGIC, 0x05);
 ioctl_read_int!(uw_hwcheck, MAGIC, 0x06);
 
+ioctl_read_int!(r_cl_faninfo1, MAGIC_READ_CL, 0x10);
+ioctl_read_int!(r_cl_faninfo2, MAGIC_READ_CL, 0x11);
+ioctl_read_int!(r_cl_faninfo3, MAGIC_READ_CL, 0x12);
+
+ioctl_write_int!(w_cl_fanspeed, MAGIC_WRITE_CL, 0x10);
+ioctl_write_int!(w_cl_fanauto, MAGIC_WRITE_CL, 0x11);
+
 ioctl_read_int!(r_uw_fanspeed, MAGIC_READ, 0x10);
 ioctl_read_int!(r_uw_fanspeed2, MAGIC_READ, 0x11);
 ioctl_read_int!(r_uw_fan_temp, MAGIC_READ, 0x12);
@@ -49,7 +71,11 @@ ioctl_write_int!(w_uw_fanspeed, MAGIC_WRITE, 0x10);
 ioctl_write_int!(w_uw_fanspeed2, MAGIC_WRITE, 0x11);
 ioctl_write_int!(w_uw_mode, MAGIC_WRITE, 0x12);
 ioctl_write_int!(w_uw_mode_enable, MAGIC_WRITE, 0x13);
-ioctl_none!(w_uw_fanauto, MAGIC_WRITE, 0x14);
+ioctl_none!(
+    /// # Safety
+    ///
+    /// `fd` must be the driver's ioctl device.
+    w_uw_fanauto, MAGIC_WRITE, 0x14);
 ioctl_write_int!(w_uw_tdp0, MAGIC_WRITE, 0x15);
 ioctl_write_int!(w_uw_tdp1, MAGIC_WRITE, 0x16);
 ioctl_write_int!(w_uw_tdp2, MAGIC_WRITE, 0x17);
@@ -1,3 +1,9 @@
+//! Low level definitions of the TUXEDO driver interface.
+
 pub mod ioctl;
 
+/// Raw fan speed the Uniwill EC treats as full speed.
 pub const UW_MAX_FAN_SPEED: u8 = 0xC8;
+
+/// Raw fan speed the Clevo EC treats as full speed.
+pub const CL_MAX_FAN_SPEED: u8 = 0xFF;
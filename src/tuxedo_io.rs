@@ -1,99 +1,453 @@
 use crate::sys::{UW_MAX_FAN_SPEED, ioctl};
 use std::{
     fs::OpenOptions,
-    io::{Error, ErrorKind, Result},
-    os::fd::{AsRawFd, OwnedFd},
+    io::{Error, Result},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
 };
+use tracing::debug;
 
 /// Safe wrapper around the Tuxedo driver IOCTL interface.
 ///
 /// Note that this assumes Gen10 Uniwill hardware, since that's my device. I have
 /// not implemented support for anything else.
-pub struct TuxedoIo(OwnedFd);
+pub struct TuxedoIo {
+    fd: OwnedFd,
 
+    /// Held for every command sent to the driver, so that commands issued from
+    /// different threads are strictly ordered and never interleave at the EC.
+    command_lock: Mutex<()>,
+
+    hwcheck_code: i32,
+    interface: &'static Interface,
+    tdp_bounds: [Option<(u32, u32)>; 3],
+    fans_off_available: bool,
+    max_fan_speed: i32,
+}
+
+/// Where the TUXEDO driver exposes its ioctl device.
+pub const DEFAULT_DEVICE_PATH: &str = "/dev/tuxedo_io";
+
+/// A hardware interface of the driver, as identified by its hardware check,
+/// and the capabilities it has.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Interface {
+    /// The code the hardware check reports for this interface.
+    pub hwcheck_code: i32,
+    /// A description of the interface.
+    pub name: &'static str,
+    /// Whether the interface has configurable TDP rails.
+    pub tdp: bool,
+    /// Whether the interface can report if the fans may be turned off.
+    pub fans_off: bool,
+}
+
+/// Interfaces that identify supported hardware. The driver reports 1 when the
+/// Uniwill interface is available; no other codes are known to identify
+/// compatible hardware.
+const SUPPORTED_INTERFACES: &[Interface] = &[Interface {
+    hwcheck_code: 1,
+    name: "Uniwill",
+    tdp: true,
+    fans_off: true,
+}];
+
+impl Interface {
+    /// Find the supported interface a hardware check code identifies.
+    pub fn from_hwcheck_code(code: i32) -> Option<&'static Interface> {
+        SUPPORTED_INTERFACES
+            .iter()
+            .find(|interface| interface.hwcheck_code == code)
+    }
+}
+
+/// One of the two fans of the laptop.
 #[derive(Debug, Clone, Copy)]
 pub enum Fan {
+    /// The CPU fan.
     Fan1,
+    /// The GPU fan, on models that have one.
     Fan2,
 }
 
+impl Fan {
+    /// Every fan, in order.
+    pub const ALL: [Fan; 2] = [Fan::Fan1, Fan::Fan2];
+}
+
+/// A configurable power limit (TDP) rail.
+#[derive(Debug, Clone, Copy)]
+pub enum Tdp {
+    /// Sustained power limit (PL1).
+    Tdp0,
+    /// Boost power limit (PL2).
+    Tdp1,
+    /// Peak power limit (PL4).
+    Tdp2,
+}
+
+impl Tdp {
+    /// Every rail, in order.
+    pub const ALL: [Tdp; 3] = [Tdp::Tdp0, Tdp::Tdp1, Tdp::Tdp2];
+}
+
+/// A firmware performance profile, using the values TCC uses for Uniwill
+/// devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerformanceProfile {
+    /// Quiet, with lower power limits.
+    PowerSave = 1,
+    /// Balanced power limits.
+    Enthusiast = 2,
+    /// The highest power limits the firmware allows.
+    Overboost = 3,
+}
+
 impl TuxedoIo {
-    pub fn open() -> Result<Self> {
-        let fd: OwnedFd = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open("/dev/tuxedo_io")?
-            .into();
+    /// Open the driver's ioctl device, usually [`DEFAULT_DEVICE_PATH`].
+    ///
+    /// This fails if the hardware check reports anything but a supported
+    /// [`Interface`]. The TDP ranges and whether the fans may be turned off are
+    /// read once here, if the interface has them.
+    pub fn open(path: &Path) -> Result<Self> {
+        let fd = open_device(path)?;
+        let code = hwcheck(&fd)?;
+        debug!("Hardware check returned code {code}");
+
+        let interface = Interface::from_hwcheck_code(code).ok_or_else(|| unsupported_code(code))?;
+        let tdp_bounds = if interface.tdp {
+            Tdp::ALL.map(|tdp| read_tdp_bounds(&fd, tdp))
+        } else {
+            [None; 3]
+        };
+        let fans_off_available =
+            interface.fans_off && read_fans_off_available(&fd).unwrap_or(false);
+
+        Ok(TuxedoIo {
+            fd,
+            command_lock: Mutex::new(()),
+            hwcheck_code: code,
+            interface,
+            tdp_bounds,
+            fans_off_available,
+            max_fan_speed: UW_MAX_FAN_SPEED.into(),
+        })
+    }
+
+    /// Take the command lock to send a command to the driver.
+    fn command(&self) -> Command<'_> {
+        Command {
+            fd: &self.fd,
+            _lock: self
+                .command_lock
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    /// Get the code reported by the driver's hardware check when the device
+    /// was opened.
+    pub fn hwcheck_code(&self) -> i32 {
+        self.hwcheck_code
+    }
+
+    /// Override the raw value the EC uses for full fan speed, for firmware that
+    /// does not use the usual maximum.
+    pub fn set_max_fan_speed(&mut self, raw: i32) {
+        assert!(raw > 0, "maximum fan speed must be positive");
+        self.max_fan_speed = raw;
+    }
 
-        let mut code = 0;
+    /// Get the raw value the EC uses for full fan speed.
+    pub fn max_fan_speed(&self) -> i32 {
+        self.max_fan_speed
+    }
+
+    /// Get the hardware interface in use, as identified by the hardware check.
+    pub fn interface(&self) -> &'static Interface {
+        self.interface
+    }
+
+    /// Get the temperature of the sensor associated with a fan, in degrees
+    /// Celsius.
+    pub fn get_fan_temperature(&self, fan: Fan) -> Result<u8> {
+        let fd = self.command();
+        let mut value = 0;
 
         unsafe {
-            ioctl::uw_hwcheck(fd.as_raw_fd(), &mut code)?;
+            match fan {
+                Fan::Fan1 => ioctl::r_uw_fan_temp(fd.as_raw_fd(), &mut value)?,
+                Fan::Fan2 => ioctl::r_uw_fan_temp2(fd.as_raw_fd(), &mut value)?,
+            };
         }
 
-        if code == 1 {
-            Ok(TuxedoIo(fd))
+        Ok(value as u8)
+    }
+
+    /// Check whether the firmware allows the fans to be turned off completely.
+    pub fn get_fans_off_available(&self) -> Result<bool> {
+        let fd = self.command();
+
+        read_fans_off_available(&fd)
+    }
+
+    /// Whether the firmware allows the fans to be turned off completely, as
+    /// read when the device was opened.
+    pub fn fans_off_available(&self) -> bool {
+        self.fans_off_available
+    }
+
+    /// Check that the driver still reports the same hardware, such as after
+    /// the system resumes from sleep.
+    pub fn check(&self) -> Result<()> {
+        let fd = self.command();
+        let code = hwcheck(&fd)?;
+
+        if code == self.hwcheck_code {
+            Ok(())
         } else {
-            Err(Error::new(ErrorKind::Other, "hardware check failed"))
+            Err(unsupported_code(code))
+        }
+    }
+
+    /// Get the allowed range of a TDP rail in watts, or `None` if the rail is
+    /// not supported. This is read when the device is opened.
+    pub fn get_tdp_bounds(&self, tdp: Tdp) -> Option<(u32, u32)> {
+        self.tdp_bounds[tdp as usize]
+    }
+
+    /// Get the raw performance mode currently set in the firmware.
+    pub fn get_mode(&self) -> Result<i32> {
+        let fd = self.command();
+        let mut value = 0;
+
+        unsafe {
+            ioctl::r_uw_mode(fd.as_raw_fd(), &mut value)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Get whether the firmware leaves the fan and power policy to the OS
+    /// rather than applying its own.
+    pub fn get_mode_enable(&self) -> Result<bool> {
+        let fd = self.command();
+        let mut value = 0;
+
+        unsafe {
+            ioctl::r_uw_mode_enable(fd.as_raw_fd(), &mut value)?;
+        }
+
+        Ok(value != 0)
+    }
+
+    /// Hand the fan and power policy to the OS, or back to the firmware.
+    pub fn set_mode_enable(&self, enabled: bool) -> Result<()> {
+        let fd = self.command();
+
+        unsafe {
+            ioctl::w_uw_mode_enable(fd.as_raw_fd(), &i32::from(enabled))?;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the firmware to a performance profile.
+    pub fn set_performance_profile(&self, profile: PerformanceProfile) -> Result<()> {
+        let fd = self.command();
+
+        unsafe {
+            ioctl::w_uw_perf_prof(fd.as_raw_fd(), &(profile as i32))?;
         }
+
+        Ok(())
+    }
+
+    /// Get the power limit currently set for a TDP rail, in watts.
+    pub fn get_tdp(&self, tdp: Tdp) -> Result<u32> {
+        let fd = self.command();
+        let mut value = 0;
+
+        unsafe {
+            match tdp {
+                Tdp::Tdp0 => ioctl::r_uw_tdp0(fd.as_raw_fd(), &mut value)?,
+                Tdp::Tdp1 => ioctl::r_uw_tdp1(fd.as_raw_fd(), &mut value)?,
+                Tdp::Tdp2 => ioctl::r_uw_tdp2(fd.as_raw_fd(), &mut value)?,
+            };
+        }
+
+        Ok(value.max(0) as u32)
+    }
+
+    /// Set the power limit of a TDP rail, in watts. The firmware may reject
+    /// values outside of [`TuxedoIo::get_tdp_bounds`].
+    pub fn set_tdp(&self, tdp: Tdp, watts: u32) -> Result<()> {
+        let fd = self.command();
+        let value = watts as i32;
+
+        unsafe {
+            match tdp {
+                Tdp::Tdp0 => ioctl::w_uw_tdp0(fd.as_raw_fd(), &value)?,
+                Tdp::Tdp1 => ioctl::w_uw_tdp1(fd.as_raw_fd(), &value)?,
+                Tdp::Tdp2 => ioctl::w_uw_tdp2(fd.as_raw_fd(), &value)?,
+            };
+        }
+
+        Ok(())
     }
 
     /// Get the minimum recommended fan speed for all fans, as a percentage.
     pub fn get_fan_min_speed(&self) -> Result<u8> {
+        let fd = self.command();
         let mut value = 0;
 
         unsafe {
-            ioctl::r_uw_fans_min_speed(self.0.as_raw_fd(), &mut value)?;
+            ioctl::r_uw_fans_min_speed(fd.as_raw_fd(), &mut value)?;
         }
 
-        Ok(speed_to_percentage(value))
+        Ok(min_speed_to_percentage(value, self.max_fan_speed))
     }
 
     /// Get the current speed of a fan as a percentage.
-    pub fn get_fan_speed(&self, fan: Fan) -> Result<u8> {
+    pub fn get_fan_speed(&self, fan: Fan) -> Result<f64> {
+        let fd = self.command();
         let mut value = 0;
 
         unsafe {
             match fan {
-                Fan::Fan1 => ioctl::r_uw_fanspeed(self.0.as_raw_fd(), &mut value)?,
-                Fan::Fan2 => ioctl::r_uw_fanspeed2(self.0.as_raw_fd(), &mut value)?,
+                Fan::Fan1 => ioctl::r_uw_fanspeed(fd.as_raw_fd(), &mut value)?,
+                Fan::Fan2 => ioctl::r_uw_fanspeed2(fd.as_raw_fd(), &mut value)?,
             };
         }
 
-        Ok(speed_to_percentage(value))
+        Ok(speed_to_percentage(value, self.max_fan_speed))
     }
 
-    /// Set the desired speed of a fan as a percentage.
+    /// Set the desired speed of a fan as a percentage, returning the raw value
+    /// that was sent to the EC.
     ///
     /// This function is blocking. The driver will not return until the desired
-    /// speed is reached.
-    pub fn set_fan_speed(&self, fan: Fan, percentage: u8) -> Result<()> {
-        let value = percentage_to_speed(percentage).into();
+    /// speed is reached, and no other commands are sent in the meantime.
+    pub fn set_fan_speed(&self, fan: Fan, percentage: f64) -> Result<i32> {
+        let fd = self.command();
+        let value = self.fan_speed_value(percentage);
 
         unsafe {
             match fan {
-                Fan::Fan1 => ioctl::w_uw_fanspeed(self.0.as_raw_fd(), &value)?,
-                Fan::Fan2 => ioctl::w_uw_fanspeed2(self.0.as_raw_fd(), &value)?,
+                Fan::Fan1 => ioctl::w_uw_fanspeed(fd.as_raw_fd(), &value)?,
+                Fan::Fan2 => ioctl::w_uw_fanspeed2(fd.as_raw_fd(), &value)?,
             };
         }
 
-        Ok(())
+        Ok(value)
+    }
+
+    /// Get the raw value that [`TuxedoIo::set_fan_speed`] would send to the EC
+    /// for a percentage.
+    pub fn fan_speed_value(&self, percentage: f64) -> i32 {
+        percentage_to_speed(percentage, self.max_fan_speed)
     }
 
     /// Set all fans to default mode (controlled by firmware).
     pub fn set_fans_auto(&self) -> Result<()> {
+        let fd = self.command();
+
         unsafe {
-            ioctl::w_uw_fanauto(self.0.as_raw_fd())?;
+            ioctl::w_uw_fanauto(fd.as_raw_fd())?;
         }
 
         Ok(())
     }
 }
 
-fn speed_to_percentage(speed: i32) -> u8 {
-    (speed as f32 / UW_MAX_FAN_SPEED as f32 * 100f32) as u8
+/// The driver handle, borrowed while holding the command lock.
+struct Command<'a> {
+    fd: &'a OwnedFd,
+    _lock: MutexGuard<'a, ()>,
+}
+
+impl AsRawFd for Command<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Convert a raw fan speed to a percentage of the given full speed value.
+fn speed_to_percentage(speed: i32, max_speed: i32) -> f64 {
+    speed as f64 * 100.0 / max_speed as f64
 }
 
-fn percentage_to_speed(percentage: u8) -> u8 {
-    (UW_MAX_FAN_SPEED as f32 * percentage as f32 / 100f32) as u8
+/// Convert the raw minimum fan speed to a percentage of the given full speed
+/// value. This rounds up, so that the minimum is never undercut, and is capped
+/// at 100% for a full speed value lower than what the firmware reports.
+fn min_speed_to_percentage(speed: i32, max_speed: i32) -> u8 {
+    (speed.max(0) as u32 * 100)
+        .div_ceil(max_speed as u32)
+        .min(100) as u8
 }
+
+/// Convert a percentage to the nearest raw fan speed, given the raw value for
+/// full speed.
+fn percentage_to_speed(percentage: f64, max_speed: i32) -> i32 {
+    (max_speed as f64 * percentage.clamp(0.0, 100.0) / 100.0).round() as i32
+}
+
+/// Open the TUXEDO driver's ioctl device at the given path.
+pub fn open_device(path: &Path) -> Result<OwnedFd> {
+    Ok(OpenOptions::new().read(true).write(true).open(path)?.into())
+}
+
+fn hwcheck(fd: &impl AsRawFd) -> Result<i32> {
+    let mut code = 0;
+
+    unsafe {
+        ioctl::uw_hwcheck(fd.as_raw_fd(), &mut code)?;
+    }
+
+    Ok(code)
+}
+
+/// The error returned when the driver's hardware check reports an interface
+/// that is not supported.
+pub fn unsupported_code(code: i32) -> Error {
+    Error::other(format!(
+        "hardware check failed with unsupported code {code}"
+    ))
+}
+
+fn read_fans_off_available(fd: &impl AsRawFd) -> Result<bool> {
+    let mut value = 0;
+
+    unsafe {
+        ioctl::r_uw_fans_off_available(fd.as_raw_fd(), &mut value)?;
+    }
+
+    Ok(value != 0)
+}
+
+/// Read the allowed range of a TDP rail in watts. Rails that the firmware does
+/// not report a usable range for are considered unsupported.
+fn read_tdp_bounds(fd: &OwnedFd, tdp: Tdp) -> Option<(u32, u32)> {
+    let mut min = 0;
+    let mut max = 0;
+
+    let result = unsafe {
+        match tdp {
+            Tdp::Tdp0 => ioctl::r_uw_tdp0_min(fd.as_raw_fd(), &mut min)
+                .and_then(|_| ioctl::r_uw_tdp0_max(fd.as_raw_fd(), &mut max)),
+            Tdp::Tdp1 => ioctl::r_uw_tdp1_min(fd.as_raw_fd(), &mut min)
+                .and_then(|_| ioctl::r_uw_tdp1_max(fd.as_raw_fd(), &mut max)),
+            Tdp::Tdp2 => ioctl::r_uw_tdp2_min(fd.as_raw_fd(), &mut min)
+                .and_then(|_| ioctl::r_uw_tdp2_max(fd.as_raw_fd(), &mut max)),
+        }
+    };
+
+    match result {
+        Ok(_) if min >= 0 && max > min => Some((min as u32, max as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,139 @@
+use super::*;
+
+#[test]
+fn percentages_round_trip_through_raw_speeds() {
+    let max = UW_MAX_FAN_SPEED.into();
+
+    // Each raw step is half a percent, so every such percentage survives.
+    for step in 0..=200 {
+        let percentage = step as f64 / 2.0;
+        let raw = percentage_to_speed(percentage, max);
+
+        assert_eq!(raw, step);
+        assert_eq!(speed_to_percentage(raw, max), percentage);
+    }
+}
+
+#[test]
+fn percentages_round_to_the_nearest_raw_speed() {
+    let max = UW_MAX_FAN_SPEED.into();
+
+    assert_eq!(percentage_to_speed(37.0, max), 74);
+    assert_eq!(percentage_to_speed(37.5, max), 75);
+    assert_eq!(percentage_to_speed(37.3, max), 75);
+    assert_eq!(percentage_to_speed(37.2, max), 74);
+    assert_eq!(percentage_to_speed(150.0, max), 200);
+    assert_eq!(percentage_to_speed(-5.0, max), 0);
+}
+
+#[test]
+fn raw_speeds_round_trip_with_custom_maximum() {
+    for max in [0xff, 0x64, 0xc8] {
+        for raw in 0..=max {
+            assert_eq!(percentage_to_speed(speed_to_percentage(raw, max), max), raw);
+        }
+    }
+}
+
+/// Maximum raw speeds to check the conversions with: the Uniwill and Clevo
+/// defaults, and ones that don't divide 100 evenly.
+const MAX_SPEEDS: [i32; 5] = [0xc8, 0xff, 0x64, 0x01, 0x7f];
+
+/// Percentages from below 0% to above 100% in steps of a thousandth, which is
+/// finer than any raw step.
+fn percentages() -> impl Iterator<Item = f64> {
+    (-1000..=101_000).map(|step| step as f64 / 1000.0)
+}
+
+#[test]
+fn raw_speeds_stay_within_range() {
+    for max in MAX_SPEEDS {
+        for percentage in percentages().chain([f64::NAN, f64::INFINITY, f64::NEG_INFINITY]) {
+            let raw = percentage_to_speed(percentage, max);
+
+            assert!(
+                (0..=max).contains(&raw),
+                "{percentage}% gave {raw} of {max}"
+            );
+        }
+    }
+}
+
+#[test]
+fn raw_speeds_increase_with_percentage() {
+    for max in MAX_SPEEDS {
+        let raws = percentages()
+            .map(|percentage| percentage_to_speed(percentage, max))
+            .collect::<Vec<_>>();
+
+        assert!(raws.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(raws.first(), Some(&0));
+        assert_eq!(raws.last(), Some(&max));
+    }
+}
+
+#[test]
+fn percentages_round_trip_within_half_a_raw_step() {
+    for max in MAX_SPEEDS {
+        let tolerance = 50.0 / max as f64;
+
+        for percentage in percentages().filter(|p| (0.0..=100.0).contains(p)) {
+            let round_trip = speed_to_percentage(percentage_to_speed(percentage, max), max);
+
+            assert!(
+                (round_trip - percentage).abs() <= tolerance + 1e-9,
+                "{percentage}% came back as {round_trip}% with maximum {max}"
+            );
+        }
+    }
+}
+
+#[test]
+fn edge_percentages_map_to_expected_raw_speeds() {
+    let max = UW_MAX_FAN_SPEED.into();
+
+    assert_eq!(percentage_to_speed(0.0, max), 0);
+    assert_eq!(percentage_to_speed(0.1, max), 0);
+    assert_eq!(percentage_to_speed(99.0, max), 198);
+    assert_eq!(percentage_to_speed(99.9, max), 200);
+    assert_eq!(percentage_to_speed(100.0, max), 200);
+    assert_eq!(speed_to_percentage(max, max), 100.0);
+}
+
+#[test]
+fn min_speeds_round_up_to_a_percentage() {
+    let max = UW_MAX_FAN_SPEED.into();
+
+    assert_eq!(min_speed_to_percentage(0, max), 0);
+    assert_eq!(min_speed_to_percentage(50, max), 25);
+    assert_eq!(min_speed_to_percentage(51, max), 26);
+    assert_eq!(min_speed_to_percentage(-1, max), 0);
+}
+
+#[test]
+fn min_speeds_are_capped_with_small_maximum() {
+    // A --max-fan-raw below the firmware's minimum must not wrap around.
+    assert_eq!(min_speed_to_percentage(50, 0x01), 100);
+    assert_eq!(min_speed_to_percentage(50, 0x20), 100);
+    assert_eq!(min_speed_to_percentage(0xc8, 0x7f), 100);
+    assert_eq!(min_speed_to_percentage(1, 0x7f), 1);
+
+    for max in MAX_SPEEDS {
+        for speed in 0..=0xff {
+            assert!(min_speed_to_percentage(speed, max) <= 100);
+        }
+    }
+}
+
+#[test]
+fn hwcheck_code_selects_interface() {
+    let uniwill = Interface::from_hwcheck_code(1).unwrap();
+
+    assert_eq!(uniwill.name, "Uniwill");
+    assert!(uniwill.tdp);
+    assert!(uniwill.fans_off);
+
+    for code in [0, -1, 2] {
+        assert_eq!(Interface::from_hwcheck_code(code), None);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            fs::{FileTypeExt, PermissionsExt, chown},
+            net::{self, SocketAddr},
+        },
+    },
+    path::Path,
+};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Name of a socket in the abstract namespace, if the path is one.
+///
+/// Abstract sockets have no file, so there is nothing to clean up and no
+/// directory permissions to get right. They are written with a leading `@`,
+/// as `ss` and systemd show them, or with the leading NUL byte itself.
+pub fn abstract_name(path: &str) -> Option<&str> {
+    path.strip_prefix('@').or_else(|| path.strip_prefix('\0'))
+}
+
+/// The file of a socket, or `None` if it is in the abstract namespace.
+pub fn file(path: &str) -> Option<&Path> {
+    match abstract_name(path) {
+        Some(_) => None,
+        None => Some(Path::new(path)),
+    }
+}
+
+fn address(path: &str) -> io::Result<SocketAddr> {
+    match abstract_name(path) {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(path),
+    }
+}
+
+/// Describe the address of a socket in the same form as it is configured.
+pub fn describe(addr: &SocketAddr) -> String {
+    if let Some(path) = addr.as_pathname() {
+        path.display().to_string()
+    } else if let Some(name) = addr.as_abstract_name() {
+        format!("@{}", String::from_utf8_lossy(name))
+    } else {
+        format!("{addr:?}")
+    }
+}
+
+/// Listen on a socket file, or on a name in the abstract namespace.
+pub fn bind(path: &str) -> io::Result<UnixListener> {
+    let listener = net::UnixListener::bind_addr(&address(path)?)?;
+    listener.set_nonblocking(true)?;
+
+    UnixListener::from_std(listener)
+}
+
+/// Give a socket file the given permissions and group, so that processes
+/// other than the plugin's own user can connect to it.
+pub fn set_permissions(path: &Path, mode: Option<u32>, group: Option<u32>) -> io::Result<()> {
+    if let Some(group) = group {
+        chown(path, None, Some(group))?;
+    }
+    if let Some(mode) = mode {
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+/// Remove a socket file left behind by an instance that is no longer running,
+/// so that it can be bound again.
+///
+/// Fails with [`io::ErrorKind::AddrInUse`] if another instance still accepts
+/// connections on it, and with [`io::ErrorKind::AlreadyExists`] if there is
+/// something other than a socket at the path. Any other failure to connect is
+/// passed on too, as the socket may belong to an instance that merely won't
+/// let us in.
+pub async fn remove_stale(path: &str) -> io::Result<()> {
+    match connect(path).await {
+        Ok(_) => return Err(io::ErrorKind::AddrInUse.into()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+        Err(e) => return Err(e),
+    }
+
+    // Nothing to remove for a name in the abstract namespace, which goes away
+    // with its last listener.
+    let Some(file) = file(path) else {
+        return Ok(());
+    };
+    if !fs::symlink_metadata(file)?.file_type().is_socket() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    fs::remove_file(file)
+}
+
+/// Connect to a socket file, or to a name in the abstract namespace.
+pub async fn connect(path: &str) -> io::Result<UnixStream> {
+    if abstract_name(path).is_none() {
+        return UnixStream::connect(path).await;
+    }
+
+    // Connecting to a local socket doesn't block for long, if at all.
+    let stream = net::UnixStream::connect_addr(&address(path)?)?;
+    stream.set_nonblocking(true)?;
+
+    UnixStream::from_std(stream)
+}
+
+#[cfg(test)]
+mod tests;
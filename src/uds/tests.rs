@@ -0,0 +1,98 @@
+use super::*;
+use std::{env, process};
+
+#[test]
+fn abstract_names_are_recognized() {
+    assert_eq!(abstract_name("@tuxedo"), Some("tuxedo"));
+    assert_eq!(abstract_name("\0tuxedo"), Some("tuxedo"));
+    assert_eq!(abstract_name("/tmp/tuxedo.sock"), None);
+
+    assert_eq!(file("@tuxedo"), None);
+    assert_eq!(
+        file("/tmp/tuxedo.sock"),
+        Some(Path::new("/tmp/tuxedo.sock"))
+    );
+}
+
+#[tokio::test]
+async fn abstract_sockets_leave_no_file() {
+    let path = format!("@tuxedo-uds-test-{}", process::id());
+    let listener = bind(&path).unwrap();
+
+    assert_eq!(describe(&listener.local_addr().unwrap().into()), path);
+    let (accepted, connected) = tokio::join!(listener.accept(), connect(&path));
+    accepted.unwrap();
+    connected.unwrap();
+    assert!(!Path::new(&path).exists());
+}
+
+#[tokio::test]
+async fn socket_files_are_bound_and_described() {
+    let path = env::temp_dir().join(format!("tuxedo-uds-test-{}.sock", process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let listener = bind(path).unwrap();
+
+    assert_eq!(describe(&listener.local_addr().unwrap().into()), path);
+    let (accepted, connected) = tokio::join!(listener.accept(), connect(path));
+    accepted.unwrap();
+    connected.unwrap();
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn socket_files_are_given_permissions() {
+    let path = env::temp_dir().join(format!("tuxedo-uds-mode-test-{}.sock", process::id()));
+    let _ = std::fs::remove_file(&path);
+    let _listener = bind(path.to_str().unwrap()).unwrap();
+    let gid = nix::unistd::getegid().as_raw();
+
+    set_permissions(&path, Some(0o660), Some(gid)).unwrap();
+
+    let metadata = std::fs::metadata(&path).unwrap();
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o660);
+    assert_eq!(std::os::unix::fs::MetadataExt::gid(&metadata), gid);
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn temp_path(name: &str) -> String {
+    let path = env::temp_dir().join(format!("tuxedo-uds-{name}-test-{}.sock", process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    path.to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn stale_sockets_are_removed() {
+    let path = temp_path("stale");
+    drop(bind(&path).unwrap());
+    assert!(Path::new(&path).exists());
+
+    remove_stale(&path).await.unwrap();
+    assert!(!Path::new(&path).exists());
+
+    // Having nothing to remove is fine too.
+    remove_stale(&path).await.unwrap();
+}
+
+#[tokio::test]
+async fn live_sockets_are_kept() {
+    let path = temp_path("live");
+    let _listener = bind(&path).unwrap();
+
+    let error = remove_stale(&path).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+    assert!(Path::new(&path).exists());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn other_files_are_kept() {
+    let path = temp_path("regular");
+    std::fs::write(&path, "not a socket").unwrap();
+
+    let error = remove_stale(&path).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    assert!(Path::new(&path).exists());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,127 @@
+use nix::libc;
+use socket2::{Domain, SockAddr, Socket, Type};
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf, unix::AsyncFd};
+use tonic::{codegen::tokio_stream::Stream, transport::server::Connected};
+use tracing::debug;
+
+/// Connections waiting to be accepted before more are refused.
+const BACKLOG: i32 = 128;
+
+/// Listens for connections from virtual machines on an `AF_VSOCK` port, so
+/// that a CoolerControl daemon running in a guest can reach the plugin on the
+/// host. Tokio has no support for vsock, so the socket is driven through its
+/// file descriptor directly.
+pub struct VsockListener {
+    socket: AsyncFd<Socket>,
+}
+
+impl VsockListener {
+    /// Listen on the given port, for connections from any guest.
+    pub fn bind(port: u32) -> io::Result<Self> {
+        let socket = Socket::new(Domain::VSOCK, Type::STREAM.nonblocking().cloexec(), None)?;
+        socket.bind(&SockAddr::vsock(libc::VMADDR_CID_ANY, port))?;
+        socket.listen(BACKLOG)?;
+
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+        })
+    }
+}
+
+impl Stream for VsockListener {
+    type Item = io::Result<VsockStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut guard = match ready!(self.socket.poll_read_ready(cx)) {
+                Ok(guard) => guard,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+
+            if let Ok(result) = guard.try_io(|socket| socket.get_ref().accept()) {
+                return Poll::Ready(Some(result.and_then(|(socket, addr)| {
+                    if let Some((cid, port)) = addr.as_vsock_address() {
+                        debug!("Accepted vsock connection from CID {cid} port {port}");
+                    }
+                    VsockStream::new(socket)
+                })));
+            }
+        }
+    }
+}
+
+/// A connection accepted by a [`VsockListener`].
+pub struct VsockStream {
+    socket: AsyncFd<Socket>,
+}
+
+impl VsockStream {
+    fn new(socket: Socket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+        })
+    }
+}
+
+impl Connected for VsockStream {
+    type ConnectInfo = ();
+
+    fn connect_info(&self) -> Self::ConnectInfo {}
+}
+
+impl AsyncRead for VsockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.socket.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+
+            match guard.try_io(|socket| socket.get_ref().read(unfilled)) {
+                Ok(Ok(read)) => {
+                    buf.advance(read);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for VsockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.socket.poll_write_ready(cx))?;
+
+            if let Ok(result) = guard.try_io(|socket| socket.get_ref().write(buf)) {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.socket.get_ref().shutdown(Shutdown::Write))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,42 @@
+use super::*;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A connected stream, and a plain socket at the other end of it. Vsock is
+/// usually not available where tests run, so a Unix socket pair stands in, as
+/// the stream does not care about the address family.
+fn pair() -> (VsockStream, Socket) {
+    let (ours, theirs) = Socket::pair(Domain::UNIX, Type::STREAM, None).unwrap();
+
+    (VsockStream::new(ours).unwrap(), theirs)
+}
+
+#[tokio::test]
+async fn streams_read_and_write() {
+    let (mut stream, mut peer) = pair();
+
+    peer.write_all(b"ping").unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+
+    stream.write_all(b"pong").await.unwrap();
+    peer.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+}
+
+#[tokio::test]
+async fn reads_wait_for_data() {
+    let (mut stream, mut peer) = pair();
+
+    let read = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        buf
+    });
+    tokio::task::yield_now().await;
+
+    peer.write_all(b"late").unwrap();
+    drop(peer);
+
+    assert_eq!(read.await.unwrap(), b"late");
+}